@@ -1,5 +1,8 @@
+use crate::conversation::EventHandler;
+use crate::usage::UsageTracker;
 use anyhow::Result;
 use serde_json::Value;
+use std::sync::Arc;
 
 /// Core trait that all agents must implement
 /// Agents can delegate to sub-agents or execute tools
@@ -14,6 +17,14 @@ pub trait Agent: Send + Sync {
     /// Get the input schema for this agent (like tools have parameters)
     fn input_schema(&self) -> Value;
 
+    /// Report stream events of this agent, and of any agents it delegates to, to `handler`.
+    /// Agents that don't talk to a model ignore it.
+    fn set_event_handler(&mut self, _handler: EventHandler) {}
+
+    /// Record token usage of this agent, and of any agents it delegates to, in `tracker`, and
+    /// stop once its budget is used up. Agents that don't talk to a model ignore it.
+    fn set_usage_tracker(&mut self, _tracker: Arc<UsageTracker>) {}
+
     /// Entry point for agent execution with logging
     async fn call(&self, task: &str) -> Result<String> {
         log::info!("Agent call start: {} - task: {}", self.name(), task);
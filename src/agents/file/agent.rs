@@ -1,49 +1,147 @@
 use crate::agent::Agent;
+use crate::config::{AgentSettings, LlmSettings, Settings, ToolSettings};
+use crate::conversation::EventHandler;
+use crate::llm::{self, LlmProvider};
+use crate::policy::PermissionPolicy;
 use crate::tool::Tool;
+use crate::usage::UsageTracker;
+use crate::ClaudeConfig;
 use super::claude::FileAgentClaude;
-use super::tools::{LsTool, GlobTool, FindTool, GrepTool, TodoWriteTool, ReadTool, WriteTool, EditTool, MultiEditTool, BashTool};
+use super::sandbox::Sandbox;
+use super::workspace::Workspace;
+use super::tools::{LsTool, GlobTool, FindTool, GrepTool, TodoWriteTool, ReadTool, WriteTool, EditTool, MultiEditTool, BashTool, BashOutputTool, BashListTool, BashKillTool, ReadResultTool, ProcessRegistry, ShellSession};
 use anyhow::Result;
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Names of the tools the agent can offer, for `[agents.file_agent.tools]`
+pub const TOOL_NAMES: &[&str] = &[
+    "ls", "glob", "find", "grep", "todo_write", "read", "write", "edit", "multi_edit", "bash",
+    "bash_output", "bash_list", "bash_kill", "read_result",
+];
 
 /// Single unified file operations agent
 pub struct FileAgent {
     claude: FileAgentClaude,
-    tools: HashMap<String, Box<dyn Tool>>,
+    workspace: Workspace,
+    sandbox: Sandbox,
+    tool_settings: ToolSettings,
+    /// Shell state shared by the agent's tasks, so `cd` and `export` carry over between
+    /// delegations
+    shell: Arc<Mutex<ShellSession>>,
 }
 
 
 impl FileAgent {
     pub fn new() -> Result<Self> {
-        let claude = FileAgentClaude::new()?;
+        Self::from_settings(&Settings::load(&Value::Null)?)
+    }
+
+    pub fn from_settings(settings: &Settings) -> Result<Self> {
+        let workspace = Workspace::from_settings(&settings.workspace)?;
+        log::info!("FileAgent workspace root: {}", workspace.root().display());
+        let sandbox = Sandbox::new(&settings.sandbox, &workspace)?;
+        let policy = PermissionPolicy::load(settings.workspace.policy.as_deref(), workspace.root())?;
+        let budget = settings.budget()?;
+        let agent_settings = settings.agent("file_agent");
+        let provider_for = |llm: LlmSettings| -> Result<Arc<dyn LlmProvider>> {
+            llm::create_provider(&ClaudeConfig::from_settings(&llm, budget.clone())?)
+        };
+
+        let provider = provider_for(settings.llm_for("file_agent"))?;
+        let mut agent = Self::with_provider(provider, workspace, policy, sandbox);
+        agent.apply_settings(&agent_settings);
+        for phase in &agent_settings.phases {
+            let provider = provider_for(settings.llm_for_phase("file_agent", phase))?;
+            agent.add_phase(phase.tools.clone(), provider);
+        }
+        if let Some(escalation) = &agent_settings.escalation {
+            agent.set_escalation(provider_for(settings.llm_for_escalation("file_agent", escalation))?);
+        }
+        agent.set_usage_tracker(Arc::new(UsageTracker::new(budget)));
+        Ok(agent)
+    }
+
+    /// Build the agent from explicit parts instead of the environment
+    pub fn with_provider(
+        provider: Arc<dyn LlmProvider>,
+        workspace: Workspace,
+        policy: PermissionPolicy,
+        sandbox: Sandbox,
+    ) -> Self {
+        let shell = Arc::new(Mutex::new(ShellSession::new(workspace.root())));
+        Self {
+            claude: FileAgentClaude::new(provider, policy),
+            workspace,
+            sandbox,
+            tool_settings: ToolSettings::default(),
+            shell,
+        }
+    }
+
+    /// Apply the round limit, context thresholds, compaction and tool selection. The model
+    /// settings are part of the provider's config.
+    pub fn apply_settings(&mut self, settings: &AgentSettings) {
+        self.claude.set_limits(settings.limits());
+        self.claude.set_context_limits(settings.context.clone());
+        self.claude.set_result_store(settings.tools.is_enabled("read_result"));
+        self.claude.set_compaction(settings.compaction.clone());
+        self.tool_settings = settings.tools.clone();
+    }
+
+    /// Use `provider` for the rounds that follow a call to any of `tools`
+    pub fn add_phase(&mut self, tools: Vec<String>, provider: Arc<dyn LlmProvider>) {
+        self.claude.add_phase(tools, provider);
+    }
+
+    /// Run a task again with `provider` when the first attempt runs out of rounds or is refused
+    pub fn set_escalation(&mut self, provider: Arc<dyn LlmProvider>) {
+        self.claude.set_escalation(provider);
+    }
+
+    /// Token usage of the tasks run so far
+    pub fn usage(&self) -> &UsageTracker {
+        self.claude.usage()
+    }
+
+    /// Create the tool set for one task. Tasks share the agent's shell session but get their
+    /// own background processes, so concurrent tasks don't kill each other's processes.
+    fn create_tools(&self, processes: &ProcessRegistry) -> BTreeMap<String, Box<dyn Tool>> {
+        let workspace = &self.workspace;
         
-        // Initialize all file tools (all tools in one agent)
-        let mut tools: HashMap<String, Box<dyn Tool>> = HashMap::new();
+        // Initialize all file tools (all tools in one agent). Kept sorted by name so every
+        // request lists the tools in the same order.
+        let mut tools: BTreeMap<String, Box<dyn Tool>> = BTreeMap::new();
         
         // Discovery tools
-        tools.insert("ls".to_string(), Box::new(LsTool::new()));
-        tools.insert("glob".to_string(), Box::new(GlobTool::new()));
-        tools.insert("find".to_string(), Box::new(FindTool::new()));
+        tools.insert("ls".to_string(), Box::new(LsTool::new(workspace.clone())));
+        tools.insert("glob".to_string(), Box::new(GlobTool::new(workspace.clone())));
+        tools.insert("find".to_string(), Box::new(FindTool::new(workspace.clone())));
         
         // Search tools
-        tools.insert("grep".to_string(), Box::new(GrepTool::new()));
+        tools.insert("grep".to_string(), Box::new(GrepTool::new(workspace.clone())));
         tools.insert("todo_write".to_string(), Box::new(TodoWriteTool::new()));
         
         // Modification tools
-        tools.insert("read".to_string(), Box::new(ReadTool::new()));
-        tools.insert("write".to_string(), Box::new(WriteTool::new()));
-        tools.insert("edit".to_string(), Box::new(EditTool::new()));
-        tools.insert("multi_edit".to_string(), Box::new(MultiEditTool::new()));
+        tools.insert("read".to_string(), Box::new(ReadTool::new(workspace.clone())));
+        tools.insert("write".to_string(), Box::new(WriteTool::new(workspace.clone())));
+        tools.insert("edit".to_string(), Box::new(EditTool::new(workspace.clone())));
+        tools.insert("multi_edit".to_string(), Box::new(MultiEditTool::new(workspace.clone())));
         
-        // Operations tools
-        tools.insert("bash".to_string(), Box::new(BashTool::new()));
-        
-        Ok(Self {
-            claude,
-            tools,
-        })
-    }
+        // Operations tools (background processes are shared between the bash tools)
+        tools.insert("bash".to_string(), Box::new(BashTool::new(workspace.clone(), processes.clone(), self.sandbox.clone()).with_session(self.shell.clone())));
+        tools.insert("bash_output".to_string(), Box::new(BashOutputTool::new(processes.clone())));
+        tools.insert("bash_list".to_string(), Box::new(BashListTool::new(processes.clone())));
+        tools.insert("bash_kill".to_string(), Box::new(BashKillTool::new(processes.clone())));
+
+        // Full outputs of truncated results
+        tools.insert("read_result".to_string(), Box::new(ReadResultTool::new()));
 
+        tools.retain(|name, _| self.tool_settings.is_enabled(name));
+        tools
+    }
 }
 
 #[async_trait::async_trait]
@@ -69,10 +167,26 @@ impl Agent for FileAgent {
         })
     }
 
+    fn set_event_handler(&mut self, handler: EventHandler) {
+        self.claude.set_event_handler(handler);
+    }
+
+    fn set_usage_tracker(&mut self, tracker: Arc<UsageTracker>) {
+        self.claude.set_usage_tracker(tracker);
+    }
+
     async fn execute(&self, task: &str) -> Result<String> {
         log::info!("FileAgent executing task: {}", task);
         
+        let processes = ProcessRegistry::new();
+        let tools = self.create_tools(&processes);
+
         // Delegate to Claude handler following orchestrator pattern
-        self.claude.execute_task(task, &self.tools).await
+        let result = self.claude.execute_task(task, &tools).await;
+
+        // Don't leave dev servers or test runs behind once the task is over
+        processes.kill_all();
+
+        result
     }
 }
\ No newline at end of file
@@ -1,111 +1,147 @@
+use crate::checkpoint::Checkpoint;
+use crate::compaction::CompactionSettings;
+use crate::conversation::{self, Callable, Conversation, ConversationError, ConversationLimits, EventHandler};
+use crate::policy::PermissionPolicy;
+use crate::session::Session;
 use crate::tool::Tool;
-use crate::ClaudeConfig;
-use crate::utils;
-use super::context_manager::{ContextManager, ProcessedResults};
+use crate::llm::{ContentBlock, LlmProvider, Message, Role};
+use crate::usage::UsageTracker;
+use super::context_manager::{ContextLimits, ContextManager};
 use anyhow::Result;
-use serde_json::{json, Value};
-use std::collections::HashMap;
-use futures::future::join_all;
-
-#[derive(Debug, Clone)]
-pub struct ToolCall {
-    pub name: String,
-    pub arguments: String,
-    pub tool_use_id: String,
-}
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
 pub struct FileAgentClaude {
-    config: ClaudeConfig,
+    provider: Arc<dyn LlmProvider>,
     context_manager: ContextManager,
+    policy: PermissionPolicy,
+    events: Option<EventHandler>,
+    usage: Arc<UsageTracker>,
+    limits: ConversationLimits,
+    compaction: CompactionSettings,
+    /// Providers for the rounds after calls to certain tools
+    phases: Vec<(Vec<String>, Arc<dyn LlmProvider>)>,
+    /// Stronger model that runs the task again when an attempt runs out of rounds or is refused
+    escalation: Option<Arc<dyn LlmProvider>>,
 }
 
 impl FileAgentClaude {
-    pub fn new() -> Result<Self> {
-        let config = ClaudeConfig::new()?;
+    pub fn new(provider: Arc<dyn LlmProvider>, policy: PermissionPolicy) -> Self {
         let context_manager = ContextManager::new();
         
-        Ok(Self {
-            config,
+        Self {
+            provider,
             context_manager,
-        })
+            policy,
+            events: None,
+            usage: Arc::new(UsageTracker::default()),
+            limits: ConversationLimits::default(),
+            compaction: CompactionSettings::default(),
+            phases: Vec::new(),
+            escalation: None,
+        }
     }
 
-    /// Execute tools in parallel with error handling
-    pub async fn execute_tools_parallel(&self, tool_calls: Vec<ToolCall>, tools: &HashMap<String, Box<dyn Tool>>) -> Result<Vec<(String, String, String)>> {
-        log::info!("Executing {} tools in parallel", tool_calls.len());
-        
-        // Execute multiple tools simultaneously for efficiency
-        let futures: Vec<_> = tool_calls.into_iter()
-            .map(|call| self.execute_single_tool_with_id(call, tools))
-            .collect();
+    pub fn add_phase(&mut self, tools: Vec<String>, provider: Arc<dyn LlmProvider>) {
+        self.phases.push((tools, provider));
+    }
 
-        let results = join_all(futures).await;
-
-        // Collect results and handle errors, preserving order
-        let mut tool_results = Vec::new();
-        for result in results.into_iter() {
-            match result {
-                Ok((tool_use_id, tool_name, output)) => {
-                    if output.starts_with("Tool execution failed:") || output.starts_with("Tool not found:") {
-                        log::error!("Tool {} had error: {}", tool_name, output);
-                    } else {
-                        log::info!("Tool {} completed successfully", tool_name);
-                    }
-                    tool_results.push((tool_use_id, tool_name, output));
-                }
-                Err(e) => {
-                    // This should rarely happen now since execute_single_tool handles errors
-                    log::error!("Unexpected tool error: {}", e);
-                }
-            }
-        }
+    pub fn set_escalation(&mut self, provider: Arc<dyn LlmProvider>) {
+        self.escalation = Some(provider);
+    }
 
-        Ok(tool_results)
+    pub fn set_limits(&mut self, limits: ConversationLimits) {
+        self.limits = limits;
     }
 
-    /// Execute a single tool call with tool_use_id preservation
-    async fn execute_single_tool_with_id(&self, call: ToolCall, tools: &HashMap<String, Box<dyn Tool>>) -> Result<(String, String, String)> {
-        if let Some(tool) = tools.get(&call.name) {
-            match tool.execute(&call.arguments).await {
-                Ok(result) => {
-                    // Store tool-specific message using tool name
-                    let tool_message = json!({
-                        "tool": call.name,
-                        "arguments": call.arguments,
-                        "result": result.clone(),
-                        "timestamp": chrono::Utc::now().to_rfc3339(),
-                    });
-                    utils::store_claude_message(&call.name, &tool_message)?;
-                    
-                    Ok((call.tool_use_id.clone(), call.name.clone(), result))
-                },
-                Err(e) => Ok((call.tool_use_id.clone(), call.name.clone(), format!("Tool execution failed: {}", e))),
-            }
-        } else {
-            Ok((call.tool_use_id.clone(), call.name.clone(), format!("Tool not found: {}", call.name)))
-        }
+    pub fn set_compaction(&mut self, settings: CompactionSettings) {
+        self.compaction = settings;
     }
 
+    pub fn set_context_limits(&mut self, limits: ContextLimits) {
+        self.context_manager = ContextManager::with_limits(limits);
+    }
 
-    /// Execute tools with context management and result processing
-    async fn execute_tools_with_context_management(&self, tools: Vec<ToolCall>, tool_map: &HashMap<String, Box<dyn Tool>>) -> Result<ProcessedResults> {
-        // Execute tools in parallel for efficiency
-        let raw_results = self.execute_tools_parallel(tools, tool_map).await?;
-        
-        // Process results locally to manage context window
-        let processed_results = self.context_manager.process_results_locally(raw_results)?;
-        
-        Ok(processed_results)
+    /// Store full outputs of truncated results for the read_result tool
+    pub fn set_result_store(&mut self, enabled: bool) {
+        self.context_manager.set_store_results(enabled);
     }
 
-    /// Execute task with file management capabilities
-    pub async fn execute_task(&self, task: &str, tools: &HashMap<String, Box<dyn Tool>>) -> Result<String> {
+    pub fn set_event_handler(&mut self, handler: EventHandler) {
+        self.events = Some(handler);
+    }
+
+    pub fn set_usage_tracker(&mut self, tracker: Arc<UsageTracker>) {
+        self.usage = tracker;
+    }
+
+    pub fn usage(&self) -> &UsageTracker {
+        &self.usage
+    }
+
+    /// Execute task with file management capabilities. A task delegated by the orchestrator in
+    /// a session is checkpointed under the delegating call, and picks up where it stopped when
+    /// that call is run again.
+    pub async fn execute_task(&self, task: &str, tools: &BTreeMap<String, Box<dyn Tool>>) -> Result<String> {
+        let delegation = Session::current().zip(conversation::current_call_id());
+        let escalated = delegation
+            .as_ref()
+            .map(|(session, id)| (session, format!("{}.escalated", id)));
+
+        // An escalated attempt that was cut off goes on without running the first one again
+        if let (Some(stronger), Some((session, id))) = (&self.escalation, &escalated) {
+            if session.has_delegation(id) {
+                return self.attempt(stronger.as_ref(), task, tools, Some((session, id))).await;
+            }
+        }
+
         // Send task to Claude API with file tools available
-        self.call_claude_api(task, tools).await
+        let first = delegation.as_ref().map(|(session, id)| (session, id.as_str()));
+        let result = self.attempt(self.provider.as_ref(), task, tools, first).await;
+
+        let (Err(error), Some(stronger)) = (&result, &self.escalation) else {
+            return result;
+        };
+        if !should_escalate(error) {
+            return result;
+        }
+        log::warn!(
+            "file_agent attempt with model {} failed, retrying with {}: {}",
+            self.provider.model(),
+            stronger.model(),
+            error
+        );
+        let task = format!("{}\n\n{}", task, escalation_note(error));
+        let escalated = escalated.as_ref().map(|(session, id)| (*session, id.as_str()));
+        self.attempt(stronger.as_ref(), &task, tools, escalated).await
+    }
+
+    /// Run one attempt at a task, checkpointed under `delegation` when there is one. A
+    /// checkpointed attempt that already answered returns its answer without running again.
+    async fn attempt(&self, provider: &dyn LlmProvider, task: &str, tools: &BTreeMap<String, Box<dyn Tool>>, delegation: Option<(&Session, &str)>) -> Result<String> {
+        let Some((session, id)) = delegation else {
+            return self.call_claude_api(provider, task, tools, &mut Vec::new(), None).await;
+        };
+
+        let mut history = session.load_delegation(id)?;
+        if let Some(answer) = final_answer(&history) {
+            log::info!("file_agent already answered delegation {}", id);
+            return Ok(answer);
+        }
+        let checkpoint = session.delegation_checkpoint(id)?;
+        self.call_claude_api(provider, task, tools, &mut history, Some(&checkpoint)).await
     }
 
-    /// Call Claude API with file management capabilities
-    pub async fn call_claude_api(&self, task: &str, tools: &HashMap<String, Box<dyn Tool>>) -> Result<String> {
+    /// Call Claude API with file management capabilities. `history` is empty for a new task, or
+    /// holds an unfinished conversation to continue instead, e.g. one rebuilt from `checkpoint`.
+    pub async fn call_claude_api(
+        &self,
+        provider: &dyn LlmProvider,
+        task: &str,
+        tools: &BTreeMap<String, Box<dyn Tool>>,
+        history: &mut Vec<Message>,
+        checkpoint: Option<&dyn Checkpoint>,
+    ) -> Result<String> {
         let system_prompt = r#"
 You are a sophisticated file operations agent with comprehensive file management capabilities.
 
@@ -116,121 +152,70 @@ When working with multiple related operations, use the todo_write tool to break
 Choose the most appropriate tools for each operation and execute them in parallel when operations are independent of each other.
 "#;
 
-        let tools_json: Vec<Value> = tools.values()
-            .map(|tool| {
-                json!({
-                    "name": tool.name(),
-                    "description": tool.description(),
-                    "input_schema": tool.parameters()
-                })
-            })
+        let callables: Vec<Box<dyn Callable + '_>> = tools
+            .values()
+            .map(|tool| Box::new(tool.as_ref()) as Box<dyn Callable + '_>)
             .collect();
 
-        let mut messages = vec![json!({
-            "role": "user",
-            "content": task
-        })];
-
-        let mut round = 0;
-        let max_rounds = 100;
-
-        loop {
-            round += 1;
-            if round > max_rounds {
-                log::warn!("FileAgent reached maximum rounds: {}", max_rounds);
-                break;
-            }
+        let results = self.context_manager.for_tools(tools);
+        let mut conversation =
+            Conversation::new(provider, "file_agent", system_prompt, callables)
+                .with_policy(&self.policy)
+                .with_result_processor(&results)
+                .with_limits(self.limits.clone())
+                .with_compaction(self.compaction.clone())
+                .with_usage(&self.usage);
+        for (phase_tools, phase_provider) in &self.phases {
+            conversation = conversation.with_phase(phase_tools, phase_provider.as_ref());
+        }
+        if let Some(handler) = &self.events {
+            conversation = conversation.with_events(handler.as_ref());
+        }
+        if let Some(checkpoint) = checkpoint {
+            conversation = conversation.with_checkpoint(checkpoint);
+        }
+        match history.last() {
+            Some(message) if message.role == Role::User => conversation.resume(history).await,
+            _ => conversation.run_continued(history, task).await,
+        }
+    }
+}
 
-            let request_payload = json!({
-                "model": self.config.model,
-                "max_tokens": self.config.max_tokens,
-                "temperature": self.config.temperature,
-                "system": system_prompt,
-                "messages": messages,
-                "tools": tools_json
-            });
-
-            log::debug!("FileAgent calling Claude API - Round {}", round);
-
-            let response = self.config.client
-                .post(&self.config.api_url)
-                .header("x-api-key", &self.config.api_key)
-                .header("anthropic-version", "2023-06-01")
-                .header("content-type", "application/json")
-                .json(&request_payload)
-                .send()
-                .await?;
-
-            if !response.status().is_success() {
-                let error_text = response.text().await?;
-                return Err(anyhow::anyhow!("Claude API error: {}", error_text));
-            }
+/// Answer of a conversation that ended with the model's reply, if it did
+fn final_answer(history: &[Message]) -> Option<String> {
+    let last = history.last().filter(|message| message.role == Role::Assistant)?;
+    let text: Vec<&str> = last
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+    Some(text.concat())
+}
 
-            let claude_response: Value = response.json().await?;
-
-            // Store the Claude response for debugging/analysis
-            utils::store_claude_message("file_agent", &claude_response)?;
-
-            // Process tool calls if any
-            if let Some(content) = claude_response.get("content") {
-                if let Some(array) = content.as_array() {
-                    let mut tool_calls = Vec::new();
-                    let mut text_response = String::new();
-
-                    for content_element in array {
-                        if let Some(content_type) = content_element.get("type") {
-                            if content_type.as_str() == Some("tool_use") {
-                                if let Some(tool_name) = content_element.get("name").and_then(|n| n.as_str()) {
-                                    if let Some(input) = content_element.get("input") {
-                                        if let Some(tool_use_id) = content_element.get("id").and_then(|id| id.as_str()) {
-                                            tool_calls.push(ToolCall {
-                                                name: tool_name.to_string(),
-                                                arguments: input.to_string(),
-                                                tool_use_id: tool_use_id.to_string(),
-                                            });
-                                        }
-                                    }
-                                }
-                            } else if content_type.as_str() == Some("text") {
-                                if let Some(text) = content_element.get("text").and_then(|t| t.as_str()) {
-                                    text_response = text.to_string();
-                                }
-                            }
-                        }
-                    }
-
-                    if !tool_calls.is_empty() {
-                        // Execute tools with context management
-                        let processed_results = self.execute_tools_with_context_management(tool_calls.clone(), tools).await?;
-                        
-                        // Add assistant message
-                        messages.push(json!({
-                            "role": "assistant", 
-                            "content": content
-                        }));
-
-                        // Add tool results as user messages
-                        for (tool_use_id, _tool_name, result) in processed_results.results.into_iter() {
-                            messages.push(json!({
-                                "role": "user",
-                                "content": [{
-                                    "type": "tool_result",
-                                    "tool_use_id": tool_use_id,
-                                    "content": self.context_manager.truncate_content(&result)
-                                }]
-                            }));
-                        }
-
-                        continue;
-                    } else if !text_response.is_empty() {
-                        return Ok(text_response);
-                    }
-                }
-            }
+/// An attempt that ran out of rounds or was refused is retried, since a stronger model may
+/// finish or accept the task. Provider, auth and config errors would fail the same way with
+/// another model, and an exhausted budget ends the task with a partial result instead of an
+/// error.
+fn should_escalate(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref(),
+        Some(ConversationError::MaxRoundsReached { .. } | ConversationError::Refused { .. })
+    )
+}
 
-            break;
+/// Appended to the task when it is run again by the escalation model
+fn escalation_note(error: &anyhow::Error) -> String {
+    let mut note = format!(
+        "A previous attempt at this task did not finish: {}. It may already have changed some files, so check their current state before making changes.",
+        error
+    );
+    if let Some(ConversationError::MaxRoundsReached { last_text, .. }) = error.downcast_ref() {
+        if !last_text.trim().is_empty() {
+            note.push_str(&format!("\n\nIts last progress note:\n{}", last_text.trim()));
         }
-
-        Ok("Task completed successfully".to_string())
     }
-}
\ No newline at end of file
+    note
+}
@@ -1,181 +1,257 @@
+use super::result_store;
+use crate::conversation::{CallResult, ResultProcessor};
+use crate::output::OutputProcessor;
+use crate::tokens;
+use crate::tool::Tool;
+use crate::utils;
 use anyhow::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
 
-/// Context thresholds for efficient result processing
-pub const GREP_TRUNCATE_THRESHOLD: usize = 30;
-pub const READ_TRUNCATE_THRESHOLD: usize = 2000;
+/// Context thresholds for efficient result processing. Longer lines are cut by the tools that
+/// number lines.
 pub const LINE_CHAR_LIMIT: usize = 2000;
 pub const TOOL_OUTPUT_LIMIT: usize = 30000;
 
-/// Manages context window optimization for file operations
-pub struct ContextManager;
+/// Token budget thresholds
+pub const CONTEXT_WINDOW_TOKENS: usize = 200_000;
+pub const TURN_RESULT_TOKENS: usize = 25_000;
+pub const MIN_TURN_RESULT_TOKENS: usize = 2_000;
+
+/// Tokens reserved for the notes added to a cut output: what was cut and where the full
+/// output is stored
+const TRUNCATION_NOTE_TOKENS: usize = 128;
 
-#[derive(Debug)]
-pub struct ProcessedResults {
-    pub results: Vec<(String, String, String)>, // (tool_use_id, tool_name, result)
+/// Context thresholds of one agent, from its `[agents.<name>.context]` settings
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContextLimits {
+    /// Output lines kept per tool before its output processor shortens the output, from
+    /// `[agents.<name>.context.lines]`. Tools not listed use their processor's default.
+    pub lines: BTreeMap<String, usize>,
+    /// Size of any one tool result in bytes
+    pub output_bytes: usize,
+    /// Context window of the agent's model in tokens
+    pub context_tokens: usize,
+    /// Tokens the results of one round may take together while the conversation is empty.
+    /// The budget shrinks as the conversation fills the context window.
+    pub turn_tokens: usize,
 }
 
-impl ProcessedResults {
-    pub fn new() -> Self {
+impl Default for ContextLimits {
+    fn default() -> Self {
         Self {
-            results: Vec::new(),
+            lines: BTreeMap::new(),
+            output_bytes: TOOL_OUTPUT_LIMIT,
+            context_tokens: CONTEXT_WINDOW_TOKENS,
+            turn_tokens: TURN_RESULT_TOKENS,
         }
     }
+}
 
-    pub fn push(&mut self, tool_use_id: String, tool_name: String, result: String) {
-        self.results.push((tool_use_id, tool_name, result));
-    }
+/// Tool that pages through stored results. Its own output is never stored again.
+const READ_RESULT_TOOL: &str = "read_result";
 
-    pub fn get_by_name(&self, tool_name: &str) -> Option<&String> {
-        self.results.iter()
-            .find(|(_, name, _)| name == tool_name)
-            .map(|(_, _, result)| result)
+/// Manages context window optimization for file operations
+pub struct ContextManager {
+    limits: ContextLimits,
+    /// Store full outputs of truncated results, so read_result can retrieve them
+    store_results: bool,
+}
+
+impl Default for ContextManager {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl ContextManager {
     pub fn new() -> Self {
-        Self
-    }
-
-    /// Process tool results locally to manage context window
-    pub fn process_results_locally(&self, results: Vec<(String, String, String)>) -> Result<ProcessedResults> {
-        let mut processed = ProcessedResults::new();
-
-        for (tool_use_id, tool_name, result) in results {
-            let processed_result = match tool_name.as_str() {
-                "grep" => self.process_grep_result(result)?,
-                "read" => self.process_read_result(result)?,
-                "ls" => self.process_ls_result(result)?,
-                "glob" => self.process_glob_result(result)?,
-                _ => result, // Pass through for smaller results
-            };
-            processed.push(tool_use_id, tool_name, processed_result);
+        Self::with_limits(ContextLimits::default())
+    }
+
+    pub fn with_limits(limits: ContextLimits) -> Self {
+        Self {
+            limits,
+            store_results: true,
         }
+    }
 
-        Ok(processed)
+    pub fn set_store_results(&mut self, enabled: bool) {
+        self.store_results = enabled;
     }
 
-    /// Smart truncation for grep results with context preservation
-    fn process_grep_result(&self, result: String) -> Result<String> {
-        let lines: Vec<&str> = result.lines().collect();
+    /// Store the full output of a truncated result and point to it from the truncated one
+    fn with_handle(&self, tool_name: &str, full: &str, truncated: String) -> String {
+        if !self.store_results || tool_name == READ_RESULT_TOOL {
+            return truncated;
+        }
+        match result_store::save(tool_name, full) {
+            Ok(handle) => format!(
+                "{}\n\n{}",
+                truncated,
+                result_store::note(&handle, full.lines().count())
+            ),
+            Err(e) => {
+                log::warn!("Failed to store full {} output: {}", tool_name, e);
+                truncated
+            }
+        }
+    }
 
-        if lines.len() <= GREP_TRUNCATE_THRESHOLD {
-            return Ok(result); // Small enough, return as-is
+    /// Shorten an output with its tool's processor, at the threshold configured for the tool
+    /// or else the processor's own
+    fn shorten(&self, tool_name: &str, output: &str, processor: Option<&dyn OutputProcessor>) -> String {
+        match processor {
+            Some(processor) => {
+                let max_lines = self
+                    .limits
+                    .lines
+                    .get(tool_name)
+                    .copied()
+                    .unwrap_or_else(|| processor.max_lines());
+                processor.shorten(output, max_lines)
+            }
+            None => output.to_string(),
         }
+    }
 
-        // Smart truncation with context preservation
-        let file_count = self.count_unique_files(&lines);
-        let first_matches = lines.iter().take(50).cloned().collect::<Vec<_>>().join("\n");
-        let last_matches = if lines.len() > 10 {
-            lines.iter().skip(lines.len() - 10).cloned().collect::<Vec<_>>().join("\n")
-        } else {
-            lines.join("\n")
-        };
-
-        let summary = format!(
-            "Found {} matches across {} files.\n\nFirst 50 matches:\n{}\n\n... [TRUNCATED] ...\n\nLast 10 matches:\n{}",
-            lines.len(),
-            file_count,
-            first_matches,
-            last_matches
-        );
+    /// Shorten each output once with its tool's processor, then cap it in bytes and fit the
+    /// round into its token budget. Cut outputs are stored in full for read_result.
+    pub fn process_with<'p>(
+        &self,
+        results: Vec<CallResult>,
+        conversation_tokens: usize,
+        processor_for: impl Fn(&str) -> Option<&'p dyn OutputProcessor>,
+    ) -> Result<Vec<CallResult>> {
+        let outputs = results
+            .iter()
+            .map(|r| self.truncate_content(&self.shorten(&r.name, &r.output, processor_for(&r.name))))
+            .collect();
+        let outputs = self.fit_to_budget(outputs, conversation_tokens);
 
-        Ok(summary)
+        Ok(results
+            .into_iter()
+            .zip(outputs)
+            .map(|(result, output)| {
+                let output = if output == result.output {
+                    output
+                } else {
+                    self.with_handle(&result.name, &result.output, output)
+                };
+                CallResult { output, ..result }
+            })
+            .collect())
     }
 
-    /// Auto-sampling for large files with intelligent sectioning
-    fn process_read_result(&self, result: String) -> Result<String> {
-        let lines: Vec<&str> = result.lines().collect();
+    /// Result processor that shortens outputs with the processors of `tools`
+    pub fn for_tools<'a>(&'a self, tools: &'a BTreeMap<String, Box<dyn Tool>>) -> ToolResults<'a> {
+        ToolResults { manager: self, tools }
+    }
 
-        if lines.len() <= READ_TRUNCATE_THRESHOLD {
-            return Ok(result); // Threshold: 2000 lines
+    /// Truncate content to stay within limits
+    pub fn truncate_content(&self, content: &str) -> String {
+        if content.len() <= self.limits.output_bytes {
+            content.to_string()
+        } else {
+            format!("{}... [TRUNCATED - {} total bytes]", 
+                   utils::truncate_bytes(content, self.limits.output_bytes), 
+                   content.len())
         }
+    }
 
-        // Auto-sampling for large files with beginning, middle, and end sections
-        let beginning = lines.iter().take(50).cloned().collect::<Vec<_>>().join("\n");
-        let middle_start = lines.len() / 2;
-        let middle = lines.iter()
-            .skip(middle_start.saturating_sub(25))
-            .take(50)
-            .cloned()
-            .collect::<Vec<_>>()
-            .join("\n");
-        let ending = lines.iter()
-            .skip(lines.len().saturating_sub(50))
-            .cloned()
-            .collect::<Vec<_>>()
-            .join("\n");
-
-        let summary = format!(
-            "=== FILE PREVIEW (Large file: {} lines) ===\n\nBEGINNING (50 lines):\n{}\n\nMIDDLE (around line {}):\n{}\n\nEND (last 50 lines):\n{}",
-            lines.len(),
-            beginning,
-            middle_start,
-            middle,
-            ending
-        );
-
-        Ok(summary)
+    /// Tokens a round's results may take together when the conversation already takes
+    /// `conversation_tokens`. Scales with the free part of the context window, so outputs
+    /// shrink as the conversation grows.
+    pub fn turn_budget(&self, conversation_tokens: usize) -> usize {
+        let window = self.limits.context_tokens.max(1);
+        let free = window.saturating_sub(conversation_tokens);
+        let budget = (self.limits.turn_tokens as u128 * free as u128 / window as u128) as usize;
+        budget.max(MIN_TURN_RESULT_TOKENS.min(self.limits.turn_tokens))
     }
 
-    /// Process ls results for large directories
-    fn process_ls_result(&self, result: String) -> Result<String> {
-        let lines: Vec<&str> = result.lines().collect();
-        
-        if lines.len() <= 100 {
-            return Ok(result);
+    /// Fit a round's outputs into its token budget. Outputs smaller than an even share keep
+    /// everything, and what they leave over goes to the larger ones.
+    pub fn fit_to_budget(&self, outputs: Vec<String>, conversation_tokens: usize) -> Vec<String> {
+        let budget = self.turn_budget(conversation_tokens);
+        let sizes: Vec<usize> = outputs.iter().map(|output| tokens::estimate(output)).collect();
+        if sizes.iter().sum::<usize>() <= budget {
+            return outputs;
         }
 
-        // Summarize large directory listings
-        let summary = format!(
-            "Large directory listing ({} items):\n\nFirst 50 items:\n{}\n\n... [TRUNCATED] ...\n\nLast 10 items:\n{}",
-            lines.len(),
-            lines.iter().take(50).cloned().collect::<Vec<_>>().join("\n"),
-            lines.iter().skip(lines.len() - 10).cloned().collect::<Vec<_>>().join("\n")
+        log::info!(
+            "Fitting {} results of about {} tokens into a budget of {} tokens ({} tokens in the conversation)",
+            outputs.len(),
+            sizes.iter().sum::<usize>(),
+            budget,
+            conversation_tokens
         );
-
-        Ok(summary)
+        let shares = shares(&sizes, budget);
+        outputs
+            .into_iter()
+            .zip(shares)
+            .map(|(output, share)| fit(&output, share))
+            .collect()
     }
+}
 
-    /// Process glob results for large file lists
-    fn process_glob_result(&self, result: String) -> Result<String> {
-        let lines: Vec<&str> = result.lines().collect();
-        
-        if lines.len() <= 100 {
-            return Ok(result);
-        }
+/// Split `budget` between outputs of the given sizes, smallest first, so no output gets more
+/// than it needs
+fn shares(sizes: &[usize], budget: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| sizes[i]);
 
-        // Summarize large glob results
-        let summary = format!(
-            "Found {} matching files:\n\nFirst 50 files:\n{}\n\n... [TRUNCATED] ...\n\nLast 10 files:\n{}",
-            lines.len(),
-            lines.iter().take(50).cloned().collect::<Vec<_>>().join("\n"),
-            lines.iter().skip(lines.len() - 10).cloned().collect::<Vec<_>>().join("\n")
-        );
+    let mut shares = vec![0; sizes.len()];
+    let mut left = budget;
+    for (n, &i) in order.iter().enumerate() {
+        shares[i] = sizes[i].min(left / (sizes.len() - n));
+        left -= shares[i];
+    }
+    shares
+}
 
-        Ok(summary)
+/// Cut an output down to about `max_tokens`, keeping its beginning and its end, where errors
+/// and summaries usually are
+fn fit(output: &str, max_tokens: usize) -> String {
+    let total = tokens::estimate(output);
+    if total <= max_tokens {
+        return output.to_string();
     }
 
-    /// Count unique files in grep output (helper function)
-    fn count_unique_files(&self, lines: &[&str]) -> usize {
-        let mut files = std::collections::HashSet::new();
-        for line in lines {
-            if let Some(colon_pos) = line.find(':') {
-                let file_path = &line[..colon_pos];
-                files.insert(file_path);
-            }
-        }
-        files.len()
+    let keep = max_tokens.saturating_sub(TRUNCATION_NOTE_TOKENS);
+    let head = tokens::prefix(output, keep * 3 / 4);
+    let tail = tokens::suffix(&output[head.len()..], keep - keep * 3 / 4);
+    format!(
+        "{}\n... [TRUNCATED - showing about {} of {} tokens to stay within the context budget. Narrow the request, e.g. with a line range or a more specific pattern, to see the rest] ...\n{}",
+        head, keep, total, tail
+    )
+}
+
+/// Processes results with the output processors of the tools that produced them
+pub struct ToolResults<'a> {
+    manager: &'a ContextManager,
+    tools: &'a BTreeMap<String, Box<dyn Tool>>,
+}
+
+impl ResultProcessor for ToolResults<'_> {
+    fn process(
+        &self,
+        results: Vec<CallResult>,
+        conversation_tokens: usize,
+    ) -> Result<Vec<CallResult>> {
+        self.manager.process_with(results, conversation_tokens, |name| {
+            self.tools.get(name).and_then(|tool| tool.output_processor())
+        })
     }
+}
 
-    /// Truncate content to stay within limits
-    pub fn truncate_content(&self, content: &str) -> String {
-        if content.len() <= TOOL_OUTPUT_LIMIT {
-            content.to_string()
-        } else {
-            format!("{}... [TRUNCATED - {} total chars]", 
-                   &content[..TOOL_OUTPUT_LIMIT], 
-                   content.len())
-        }
+/// Without tools, outputs are only capped in bytes and fitted into the token budget
+impl ResultProcessor for ContextManager {
+    fn process(
+        &self,
+        results: Vec<CallResult>,
+        conversation_tokens: usize,
+    ) -> Result<Vec<CallResult>> {
+        self.process_with(results, conversation_tokens, |_| None)
     }
-}
\ No newline at end of file
+}
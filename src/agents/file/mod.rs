@@ -1,6 +1,11 @@
 pub mod agent;
 pub mod claude;
 pub mod context_manager;
+pub mod result_store;
+pub mod sandbox;
 pub mod tools;
+pub mod workspace;
 
-pub use agent::FileAgent;
\ No newline at end of file
+pub use agent::FileAgent;
+pub use sandbox::{Sandbox, SandboxConfig, SandboxLevel};
+pub use workspace::Workspace;
\ No newline at end of file
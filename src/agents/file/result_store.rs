@@ -0,0 +1,60 @@
+use crate::utils;
+use anyhow::Result;
+use std::fs;
+use std::path::PathBuf;
+
+/// Subdirectory of the message directory holding full tool outputs
+pub const RESULTS_DIR: &str = "results";
+
+/// Precedes the handle in the note appended to a truncated output
+const HANDLE_PREFIX: &str = "Use read_result with handle \"";
+
+/// Where full outputs of the current session are stored, next to its message logs
+pub fn results_dir() -> PathBuf {
+    utils::message_dir().join(RESULTS_DIR)
+}
+
+/// Store the full output of a call and return its handle, e.g. "004_grep"
+pub fn save(tool_name: &str, output: &str) -> Result<String> {
+    let dir = results_dir();
+    fs::create_dir_all(&dir)?;
+
+    let handle = format!("{:03}_{}", utils::next_sequence_number(&dir), tool_name);
+    fs::write(dir.join(format!("{}.txt", handle)), output)?;
+    log::debug!("Stored full {} output as {}", tool_name, handle);
+    Ok(handle)
+}
+
+/// Note appended to a truncated output, pointing to the full output stored under `handle`
+pub fn note(handle: &str, lines: usize) -> String {
+    format!(
+        "[Full output ({} lines) stored as {}. {}{}\" to page through it with offset/limit or search it with a pattern.]",
+        lines, handle, HANDLE_PREFIX, handle
+    )
+}
+
+/// Handle named by the note of a truncated output, if it has one
+pub fn handle_in(output: &str) -> Option<&str> {
+    let start = output.rfind(HANDLE_PREFIX)? + HANDLE_PREFIX.len();
+    let rest = &output[start..];
+    rest.find('"').map(|end| &rest[..end])
+}
+
+/// Full output stored under `handle`
+pub fn load(handle: &str) -> Result<String> {
+    let valid = !handle.is_empty()
+        && handle
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(anyhow::anyhow!("Invalid result handle: {}", handle));
+    }
+
+    let path = results_dir().join(format!("{}.txt", handle));
+    fs::read_to_string(&path).map_err(|_| {
+        anyhow::anyhow!(
+            "No stored result with handle {}. Handles are only valid in the session that created them.",
+            handle
+        )
+    })
+}
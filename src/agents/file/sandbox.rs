@@ -0,0 +1,527 @@
+use crate::agents::file::workspace::Workspace;
+use anyhow::Result;
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use tokio::process::Command;
+
+/// How strictly bash commands are confined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub enum SandboxLevel {
+    /// No confinement
+    Off,
+    /// Resource limits only (CPU time, memory, file size, process count)
+    Limits,
+    /// Resource limits, plus Landlock rules that only allow writes inside the workspace root
+    /// and the temp directory
+    Workspace,
+    /// Everything in `Workspace`, plus no network access
+    Strict,
+}
+
+impl FromStr for SandboxLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "off" | "none" => Ok(SandboxLevel::Off),
+            "limits" => Ok(SandboxLevel::Limits),
+            "workspace" => Ok(SandboxLevel::Workspace),
+            "strict" => Ok(SandboxLevel::Strict),
+            other => Err(anyhow::anyhow!(
+                "Invalid sandbox level '{}'. Expected one of: off, limits, workspace, strict",
+                other
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for SandboxLevel {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for SandboxLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxLevel::Off => write!(f, "off"),
+            SandboxLevel::Limits => write!(f, "limits"),
+            SandboxLevel::Workspace => write!(f, "workspace"),
+            SandboxLevel::Strict => write!(f, "strict"),
+        }
+    }
+}
+
+/// Resource limits applied to sandboxed commands. A value of 0 leaves that limit unset.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxLimits {
+    pub cpu_seconds: u64,
+    pub memory_mb: u64,
+    pub file_size_mb: u64,
+    pub max_processes: u64,
+}
+
+impl Default for SandboxLimits {
+    fn default() -> Self {
+        Self {
+            cpu_seconds: 600,
+            memory_mb: 8192,
+            file_size_mb: 1024,
+            max_processes: 1024,
+        }
+    }
+}
+
+/// `[sandbox]` settings for the bash tool
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxConfig {
+    pub level: SandboxLevel,
+    pub limits: SandboxLimits,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            level: SandboxLevel::Off,
+            limits: SandboxLimits::default(),
+        }
+    }
+}
+
+/// OS-level confinement applied to every command the bash tool spawns.
+///
+/// Support is probed once when the sandbox is created. Features the kernel lacks are logged
+/// and skipped, so the sandbox degrades to the strongest level the host supports.
+#[derive(Clone)]
+pub struct Sandbox {
+    level: SandboxLevel,
+    limits: SandboxLimits,
+    #[cfg(target_os = "linux")]
+    landlock: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    #[cfg(target_os = "linux")]
+    network_filter: Option<std::sync::Arc<Vec<libc::sock_filter>>>,
+    /// Commands also run in an empty network namespace
+    #[cfg(target_os = "linux")]
+    network_namespace: bool,
+}
+
+impl fmt::Debug for Sandbox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sandbox")
+            .field("level", &self.level)
+            .field("limits", &self.limits)
+            .field("filesystem", &self.filesystem_enforced())
+            .field("network", &self.network_enforced())
+            .finish()
+    }
+}
+
+impl Sandbox {
+    /// Sandbox that runs commands unconfined
+    pub fn disabled() -> Self {
+        Self {
+            level: SandboxLevel::Off,
+            limits: SandboxLimits::default(),
+            #[cfg(target_os = "linux")]
+            landlock: None,
+            #[cfg(target_os = "linux")]
+            network_filter: None,
+            #[cfg(target_os = "linux")]
+            network_namespace: false,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn new(config: &SandboxConfig, workspace: &Workspace) -> Result<Self> {
+        let mut sandbox = Self::disabled();
+        sandbox.level = config.level;
+        sandbox.limits = config.limits;
+
+        if config.level >= SandboxLevel::Workspace {
+            match linux::landlock_abi() {
+                Some(abi) => {
+                    let writable = [workspace.root().to_path_buf(), env::temp_dir()];
+                    sandbox.landlock = Some(std::sync::Arc::new(linux::landlock_ruleset(
+                        abi, &writable,
+                    )?));
+                }
+                None => log::warn!(
+                    "Landlock is not supported by this kernel; bash commands will not be confined to the workspace"
+                ),
+            }
+        }
+
+        if config.level >= SandboxLevel::Strict {
+            match linux::network_filter() {
+                Some(filter) => sandbox.network_filter = Some(std::sync::Arc::new(filter)),
+                None => log::warn!(
+                    "seccomp is not supported on this system; bash commands will keep network access"
+                ),
+            }
+            sandbox.network_namespace = linux::network_namespace_supported();
+            if !sandbox.network_namespace {
+                log::warn!(
+                    "Network namespaces need privileges this process lacks; only the seccomp filter keeps bash commands off the network"
+                );
+            }
+        }
+
+        log::info!("Bash sandbox: {:?}", sandbox);
+        Ok(sandbox)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(config: &SandboxConfig, _workspace: &Workspace) -> Result<Self> {
+        if config.level != SandboxLevel::Off {
+            log::warn!(
+                "Sandbox level '{}' is only supported on Linux; bash commands will run unconfined",
+                config.level
+            );
+        }
+        Ok(Self::disabled())
+    }
+
+    /// Configured sandbox level
+    pub fn level(&self) -> SandboxLevel {
+        self.level
+    }
+
+    /// True if Landlock filesystem rules are applied
+    pub fn filesystem_enforced(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        return self.landlock.is_some();
+        #[cfg(not(target_os = "linux"))]
+        return false;
+    }
+
+    /// True if network access is blocked
+    pub fn network_enforced(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        return self.network_filter.is_some();
+        #[cfg(not(target_os = "linux"))]
+        return false;
+    }
+
+    /// Confine the process that `cmd` will spawn
+    pub fn apply(&self, cmd: &mut Command) {
+        #[cfg(target_os = "linux")]
+        {
+            if self.level == SandboxLevel::Off {
+                return;
+            }
+
+            let limits = self.limits;
+            let landlock = self.landlock.clone();
+            let network_filter = self.network_filter.clone();
+            let network_namespace = self.network_namespace;
+
+            // Runs in the forked child before exec, so it only makes raw syscalls
+            let confine = move || {
+                linux::set_limits(&limits)?;
+                if let Some(filter) = &network_filter {
+                    linux::isolate_network(filter, network_namespace)?;
+                }
+                if let Some(ruleset) = &landlock {
+                    linux::landlock_restrict(ruleset)?;
+                }
+                Ok(())
+            };
+
+            // SAFETY: the closure does not allocate or take locks, it only calls setrlimit,
+            // unshare, prctl and the Landlock syscalls on data prepared before the fork
+            unsafe {
+                cmd.pre_exec(confine);
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        let _ = cmd;
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::SandboxLimits;
+    use anyhow::Result;
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+    const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+    const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+    /// Every filesystem right defined by Landlock ABI v1 (remove, make-* and the ones above)
+    const ACCESS_FS_V1: u64 = (1 << 13) - 1;
+    /// Renaming and linking across directories, ABI v2
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    /// Truncating files, ABI v3
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+    /// Device files commands may read and write even though `/dev` is read-only
+    const WRITABLE_DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/full", "/dev/tty"];
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH: Option<u32> = None;
+
+    /// Syscall numbers at or above this are the x32 ABI on x86_64
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    /// Landlock ABI version supported by the running kernel, if any
+    pub fn landlock_abi() -> Option<i64> {
+        // SAFETY: querying the version takes no pointers
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        (abi > 0).then_some(abi)
+    }
+
+    /// Ruleset that allows reading and executing everything, and writing only below `writable`
+    pub fn landlock_ruleset(abi: i64, writable: &[PathBuf]) -> Result<OwnedFd> {
+        let mut handled = ACCESS_FS_V1;
+        if abi >= 2 {
+            handled |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            handled |= ACCESS_FS_TRUNCATE;
+        }
+
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        // SAFETY: attr is a valid ruleset_attr and its size is passed alongside it
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0u32,
+            )
+        };
+        if fd < 0 {
+            return Err(anyhow::anyhow!(
+                "Failed to create Landlock ruleset: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        // SAFETY: the syscall returned a new file descriptor that nothing else owns
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        let read_only = ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+        add_path_rule(&ruleset, Path::new("/"), read_only)?;
+
+        for path in writable {
+            add_path_rule(&ruleset, path, handled)?;
+        }
+
+        let device_access = ACCESS_FS_READ_FILE | ACCESS_FS_WRITE_FILE | (handled & ACCESS_FS_TRUNCATE);
+        for device in WRITABLE_DEVICES {
+            let device = Path::new(device);
+            if device.exists() {
+                add_path_rule(&ruleset, device, device_access)?;
+            }
+        }
+
+        Ok(ruleset)
+    }
+
+    fn add_path_rule(ruleset: &OwnedFd, path: &Path, access: u64) -> Result<()> {
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        // SAFETY: c_path is a valid NUL-terminated string
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(anyhow::anyhow!(
+                "Failed to open {} for the Landlock ruleset: {}",
+                path.display(),
+                io::Error::last_os_error()
+            ));
+        }
+        // SAFETY: fd was just opened and is owned here
+        let parent = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let attr = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd: parent.as_raw_fd(),
+        };
+        // SAFETY: attr is a valid path_beneath_attr and both fds are open
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                LANDLOCK_RULE_PATH_BENEATH,
+                &attr as *const PathBeneathAttr,
+                0u32,
+            )
+        };
+        if result < 0 {
+            return Err(anyhow::anyhow!(
+                "Failed to add Landlock rule for {}: {}",
+                path.display(),
+                io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Seccomp filter that makes creating IPv4/IPv6 sockets (and io_uring, which could create
+    /// them without a syscall) fail with EACCES. Syscalls made through another architecture's
+    /// ABI, whose numbers the filter can't check, kill the process.
+    pub fn network_filter() -> Option<Vec<libc::sock_filter>> {
+        let arch = AUDIT_ARCH?;
+        // SAFETY: PR_GET_SECCOMP takes no pointers
+        if unsafe { libc::prctl(libc::PR_GET_SECCOMP) } < 0 {
+            return None;
+        }
+
+        let load = |offset: u32| bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset);
+        let deny = libc::SECCOMP_RET_ERRNO | (libc::EACCES as u32 & libc::SECCOMP_RET_DATA);
+
+        // Offsets into struct seccomp_data: nr, arch, then args from 16 (little-endian)
+        Some(vec![
+            load(4),
+            bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, arch, 1, 0),
+            bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            load(0),
+            bpf_jump(libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K, X32_SYSCALL_BIT, 5, 0),
+            bpf_jump(
+                libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                libc::SYS_io_uring_setup as u32,
+                4,
+                0,
+            ),
+            bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::SYS_socket as u32, 0, 4),
+            load(16),
+            bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::AF_INET as u32, 1, 0),
+            bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::AF_INET6 as u32, 0, 1),
+            bpf_stmt(libc::BPF_RET | libc::BPF_K, deny),
+            bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+        ])
+    }
+
+    fn bpf_stmt(code: u32, k: u32) -> libc::sock_filter {
+        bpf_jump(code, k, 0, 0)
+    }
+
+    fn bpf_jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    // The functions below run in the forked child and must stay async-signal-safe
+
+    pub fn set_limits(limits: &SandboxLimits) -> io::Result<()> {
+        const MB: u64 = 1024 * 1024;
+        let resources = [
+            (libc::RLIMIT_CPU, limits.cpu_seconds),
+            (libc::RLIMIT_AS, limits.memory_mb.saturating_mul(MB)),
+            (libc::RLIMIT_FSIZE, limits.file_size_mb.saturating_mul(MB)),
+            (libc::RLIMIT_NPROC, limits.max_processes),
+        ];
+
+        for (resource, value) in resources {
+            if value == 0 {
+                continue;
+            }
+            let limit = libc::rlimit {
+                rlim_cur: value as libc::rlim_t,
+                rlim_max: value as libc::rlim_t,
+            };
+            // SAFETY: limit is a valid rlimit
+            if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether this process may create network namespaces. Probed on a short-lived thread,
+    /// since unshare only moves the calling thread.
+    pub fn network_namespace_supported() -> bool {
+        // SAFETY: unshare takes no pointers
+        std::thread::spawn(|| unsafe { libc::unshare(libc::CLONE_NEWNET) } == 0)
+            .join()
+            .unwrap_or(false)
+    }
+
+    /// Block network sockets with `filter`. With `namespace`, also move into an empty network
+    /// namespace, failing if that doesn't work.
+    pub fn isolate_network(filter: &[libc::sock_filter], namespace: bool) -> io::Result<()> {
+        // SAFETY: unshare takes no pointers
+        if namespace && unsafe { libc::unshare(libc::CLONE_NEWNET) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        set_no_new_privs()?;
+        let program = libc::sock_fprog {
+            len: filter.len() as u16,
+            filter: filter.as_ptr() as *mut libc::sock_filter,
+        };
+        // SAFETY: program points at a filter that outlives this call
+        if unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &program as *const libc::sock_fprog,
+            )
+        } != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn landlock_restrict(ruleset: &OwnedFd) -> io::Result<()> {
+        set_no_new_privs()?;
+        // SAFETY: ruleset is an open Landlock ruleset fd
+        if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0u32) }
+            != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn set_no_new_privs() -> io::Result<()> {
+        // SAFETY: PR_SET_NO_NEW_PRIVS takes integer arguments only
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
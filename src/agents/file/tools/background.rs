@@ -0,0 +1,327 @@
+use super::bash::{signal_process_group, spawn_in_process_group};
+use crate::agents::file::sandbox::Sandbox;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+
+/// Unread output kept per stream of a background process. Older output is dropped first.
+pub const OUTPUT_BUFFER_BYTES: usize = 1024 * 1024;
+
+/// Unread output of one stream, holding at most `limit` bytes of the latest text
+#[derive(Debug)]
+pub struct OutputBuffer {
+    text: String,
+    /// Bytes of a character that was split across reads
+    pending: Vec<u8>,
+    /// Bytes dropped from the front since the last read
+    dropped: usize,
+    limit: usize,
+    /// The pipe reached its end, so no more output will arrive
+    closed: bool,
+}
+
+impl OutputBuffer {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            text: String::new(),
+            pending: Vec::new(),
+            dropped: 0,
+            limit,
+            closed: false,
+        }
+    }
+
+    /// Append raw bytes. Only complete characters are decoded; a split one waits for the
+    /// next chunk.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+        let mut rest = std::mem::take(&mut self.pending);
+        let mut start = 0;
+        loop {
+            match std::str::from_utf8(&rest[start..]) {
+                Ok(text) => {
+                    self.text.push_str(text);
+                    break;
+                }
+                Err(e) => {
+                    let valid = start + e.valid_up_to();
+                    self.text.push_str(&String::from_utf8_lossy(&rest[start..valid]));
+                    match e.error_len() {
+                        Some(invalid) => {
+                            self.text.push(char::REPLACEMENT_CHARACTER);
+                            start = valid + invalid;
+                        }
+                        None => {
+                            rest.drain(..valid);
+                            self.pending = rest;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.drop_overflow();
+    }
+
+    /// Mark the end of the stream, decoding what is left of a split character
+    pub fn close(&mut self) {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.text.push_str(&String::from_utf8_lossy(&pending));
+            self.drop_overflow();
+        }
+        self.closed = true;
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Take the unread output, noting how much was dropped to stay within the limit
+    pub fn take(&mut self) -> String {
+        let text = std::mem::take(&mut self.text);
+        match std::mem::take(&mut self.dropped) {
+            0 => text,
+            dropped => format!("[... {} bytes of earlier output dropped ...]\n{}", dropped, text),
+        }
+    }
+
+    /// Keep only the last `limit` bytes, cut at a character boundary
+    fn drop_overflow(&mut self) {
+        if self.text.len() <= self.limit {
+            return;
+        }
+        let mut cut = self.text.len() - self.limit;
+        while !self.text.is_char_boundary(cut) {
+            cut += 1;
+        }
+        self.text.drain(..cut);
+        self.dropped += cut;
+    }
+}
+
+/// Read a pipe into `buffer` until it closes
+async fn drain_into<R: AsyncRead + Unpin>(mut pipe: R, buffer: Arc<Mutex<OutputBuffer>>) {
+    let mut chunk = [0u8; 8192];
+    loop {
+        match pipe.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => buffer.lock().unwrap().push(&chunk[..n]),
+        }
+    }
+    buffer.lock().unwrap().close();
+}
+
+/// Background process started by the bash tool
+struct BackgroundProcess {
+    command: String,
+    started_at: Instant,
+    child: Child,
+    status: Option<ExitStatus>,
+    // Output not yet returned by `read_new_output`
+    stdout: Arc<Mutex<OutputBuffer>>,
+    stderr: Arc<Mutex<OutputBuffer>>,
+}
+
+impl BackgroundProcess {
+    /// Refresh the exit status without blocking
+    fn poll_status(&mut self) -> Option<ExitStatus> {
+        if self.status.is_none() {
+            if let Ok(Some(status)) = self.child.try_wait() {
+                self.status = Some(status);
+            }
+        }
+        self.status
+    }
+
+    /// Exited, and all of its output has arrived
+    fn is_finished(&mut self) -> bool {
+        self.poll_status().is_some()
+            && self.stdout.lock().unwrap().is_closed()
+            && self.stderr.lock().unwrap().is_closed()
+    }
+
+    fn describe_status(&mut self) -> String {
+        match self.poll_status() {
+            None => "running".to_string(),
+            Some(status) => match status.code() {
+                Some(code) => format!("exited with code {}", code),
+                None => "terminated by signal".to_string(),
+            },
+        }
+    }
+}
+
+/// Summary of a background process for listings
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    pub id: String,
+    pub command: String,
+    pub status: String,
+    pub running: bool,
+    pub elapsed_secs: u64,
+}
+
+/// Output produced by a background process since the previous read
+#[derive(Debug, Clone)]
+pub struct ProcessOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub status: String,
+    pub running: bool,
+    /// The process finished and this was its last output, so its handle was removed
+    pub removed: bool,
+}
+
+/// Registry of background processes shared between the bash tool and its companion tools
+#[derive(Clone, Default)]
+pub struct ProcessRegistry {
+    processes: Arc<Mutex<BTreeMap<String, BackgroundProcess>>>,
+    next_id: Arc<AtomicUsize>,
+}
+
+impl ProcessRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawn `command` through `sh -c` in the background and return its handle ID
+    pub fn spawn(
+        &self,
+        command: &str,
+        working_dir: &Path,
+        env: &BTreeMap<String, String>,
+        sandbox: &Sandbox,
+    ) -> Result<String> {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(command)
+            .current_dir(working_dir)
+            .env_clear()
+            .envs(env);
+        sandbox.apply(&mut cmd);
+
+        let mut child = spawn_in_process_group(&mut cmd)?;
+
+        let stdout = Arc::new(Mutex::new(OutputBuffer::new(OUTPUT_BUFFER_BYTES)));
+        let stderr = Arc::new(Mutex::new(OutputBuffer::new(OUTPUT_BUFFER_BYTES)));
+        // A missing pipe is closed right away, so the process can still finish
+        if let Some(pipe) = child.stdout.take() {
+            tokio::spawn(drain_into(pipe, stdout.clone()));
+        } else {
+            stdout.lock().unwrap().close();
+        }
+        if let Some(pipe) = child.stderr.take() {
+            tokio::spawn(drain_into(pipe, stderr.clone()));
+        } else {
+            stderr.lock().unwrap().close();
+        }
+
+        let id = format!("bash_{}", self.next_id.fetch_add(1, Ordering::SeqCst) + 1);
+        self.processes.lock().unwrap().insert(
+            id.clone(),
+            BackgroundProcess {
+                command: command.to_string(),
+                started_at: Instant::now(),
+                child,
+                status: None,
+                stdout,
+                stderr,
+            },
+        );
+
+        log::info!("Started background process {}: {}", id, command);
+        Ok(id)
+    }
+
+    /// Take the output produced since the last read. A finished process is removed once its
+    /// last output has been read.
+    pub fn read_new_output(&self, id: &str) -> Result<ProcessOutput> {
+        let mut processes = self.processes.lock().unwrap();
+        let process = processes
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Background process not found: {}", id))?;
+
+        // Check for the end first so that output written right before exit is included
+        let finished = process.is_finished();
+        let status = process.describe_status();
+        let stdout = process.stdout.lock().unwrap().take();
+        let stderr = process.stderr.lock().unwrap().take();
+        let running = process.status.is_none();
+
+        if finished {
+            processes.remove(id);
+            log::info!("Removed finished background process {}", id);
+        }
+
+        Ok(ProcessOutput {
+            stdout,
+            stderr,
+            running,
+            status,
+            removed: finished,
+        })
+    }
+
+    /// List all known background processes, including ones that already exited
+    pub fn list(&self) -> Vec<ProcessInfo> {
+        let mut processes = self.processes.lock().unwrap();
+        processes
+            .iter_mut()
+            .map(|(id, process)| ProcessInfo {
+                id: id.clone(),
+                command: process.command.clone(),
+                status: process.describe_status(),
+                running: process.status.is_none(),
+                elapsed_secs: process.started_at.elapsed().as_secs(),
+            })
+            .collect()
+    }
+
+    /// Kill a background process and its children, and forget its handle
+    pub fn kill(&self, id: &str) -> Result<ProcessInfo> {
+        let mut process = self
+            .processes
+            .lock()
+            .unwrap()
+            .remove(id)
+            .ok_or_else(|| anyhow::anyhow!("Background process not found: {}", id))?;
+
+        let info = ProcessInfo {
+            id: id.to_string(),
+            command: process.command.clone(),
+            status: process.describe_status(),
+            running: process.status.is_none(),
+            elapsed_secs: process.started_at.elapsed().as_secs(),
+        };
+
+        if info.running {
+            signal_process_group(&process.child);
+            if let Err(e) = process.child.start_kill() {
+                log::debug!("Failed to kill background process {}: {}", id, e);
+            }
+            log::info!("Killed background process {}: {}", id, process.command);
+        }
+
+        Ok(info)
+    }
+
+    /// Kill every background process. Called when the owning agent finishes a task.
+    pub fn kill_all(&self) {
+        let ids: Vec<String> = self.processes.lock().unwrap().keys().cloned().collect();
+        if ids.is_empty() {
+            return;
+        }
+
+        log::info!("Cleaning up {} background processes", ids.len());
+        for id in ids {
+            let _ = self.kill(&id);
+        }
+    }
+}
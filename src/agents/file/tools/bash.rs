@@ -1,11 +1,97 @@
+use super::background::ProcessRegistry;
+use crate::agents::file::sandbox::Sandbox;
+use crate::agents::file::workspace::Workspace;
+use crate::output::{Excerpt, OutputProcessor};
+use crate::shell_parser::{
+    self, is_shell, nested_source, resolve_invocation, Command as ShellCommand, Invocation,
+    Pipeline, Script, SimpleCommand, Word, MAX_SHELL_NESTING,
+};
 use crate::tool::Tool;
 use anyhow::Result;
 use serde_json::{json, Value};
-use std::process::Command;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+
+/// Grace period for draining output pipes after the process group is killed
+const PIPE_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Variables maintained by the shell itself that should not be carried between calls
+const SHELL_MANAGED_VARS: &[&str] = &["PWD", "OLDPWD", "SHLVL", "_"];
+
+/// Device files that are safe redirection targets
+const SAFE_DEVICES: &[&str] = &["/dev/null", "/dev/stdout", "/dev/stderr", "/dev/stdin", "/dev/tty"];
 
 /// Safe command execution with validation
-pub struct BashTool;
+pub struct BashTool {
+    workspace: Workspace,
+    session: Arc<tokio::sync::Mutex<ShellSession>>,
+    processes: ProcessRegistry,
+    sandbox: Sandbox,
+}
+
+/// Shell state persisted between bash calls: working directory and exported variables
+#[derive(Debug, Clone)]
+pub struct ShellSession {
+    pub cwd: PathBuf,
+    pub env: BTreeMap<String, String>,
+}
+
+impl ShellSession {
+    /// Fresh session rooted at `cwd` with the safe environment
+    pub fn new(cwd: &Path) -> Self {
+        let env = BashTool::get_safe_environment().into_iter().collect();
+        Self {
+            cwd: cwd.to_path_buf(),
+            env,
+        }
+    }
+
+    /// Wrap a command so the final working directory and environment are written to
+    /// `state_file` when the shell exits, including on an explicit `exit`
+    fn wrap_command(&self, command: &str, state_file: &Path) -> String {
+        let save_state = format!(
+            "__fa_status=$?; {{ pwd; env -0; }} > {} 2>/dev/null; exit $__fa_status",
+            shell_quote(&state_file.to_string_lossy())
+        );
+        format!("trap {} EXIT\n{}", shell_quote(&save_state), command)
+    }
+
+    /// Load the state written by a wrapped command. Missing or unreadable state leaves the
+    /// session untouched (e.g. the command was killed on timeout).
+    fn update_from_state_file(&mut self, state_file: &Path) {
+        let Ok(raw) = std::fs::read(state_file) else {
+            return;
+        };
+        let raw = String::from_utf8_lossy(&raw);
+        let Some((cwd, env)) = raw.split_once('\n') else {
+            return;
+        };
+
+        let cwd = PathBuf::from(cwd);
+        if cwd.is_dir() {
+            self.cwd = cwd;
+        }
+
+        self.env = env
+            .split('\0')
+            .filter_map(|entry| entry.split_once('='))
+            .filter(|(key, _)| !key.is_empty() && !SHELL_MANAGED_VARS.contains(key))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+    }
+}
+
+/// Captured result of a shell command. `status` is `None` when the command timed out.
+struct CommandOutput {
+    status: Option<ExitStatus>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
 
 #[derive(serde::Deserialize)]
 struct BashParams {
@@ -14,116 +100,177 @@ struct BashParams {
     description: Option<String>,
     #[serde(default)]
     timeout: Option<u64>, // in milliseconds
+    #[serde(default)]
+    reset_session: bool,
+    #[serde(default)]
+    run_in_background: bool,
 }
 
 impl BashTool {
-    pub fn new() -> Self {
-        Self
+    pub fn new(workspace: Workspace, processes: ProcessRegistry, sandbox: Sandbox) -> Self {
+        let session = ShellSession::new(workspace.root());
+
+        Self {
+            workspace,
+            session: Arc::new(tokio::sync::Mutex::new(session)),
+            processes,
+            sandbox,
+        }
+    }
+
+    /// Share `session` with other bash tools, so its working directory and exports carry over
+    /// between tasks
+    pub fn with_session(mut self, session: Arc<tokio::sync::Mutex<ShellSession>>) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Reset the persistent shell session to its initial working directory and environment
+    pub async fn reset_session(&self) {
+        *self.session.lock().await = ShellSession::new(self.workspace.root());
+        log::info!("Bash shell session reset");
     }
 
-    fn validate_command(&self, command: &str) -> Result<()> {
-        let command_lower = command.to_lowercase();
-
-        // Extremely dangerous commands - absolutely forbidden
-        let forbidden_commands = [
-            "rm -rf /",
-            "rm -rf /*",
-            ":(){ :|:& };:",
-            "mv / /dev/null",
-            "dd if=/dev/zero",
-            "mkfs",
-            "fdisk",
-            "format c:",
-            "del /f /s /q c:\\",
-            "shutdown",
-            "reboot",
-            "halt",
-            "poweroff",
-            "init 0",
-            "init 6",
-            "chmod -R 777 /",
-            "chown -R root /",
-            "killall -9",
-        ];
-
-        for forbidden in &forbidden_commands {
-            if command_lower.contains(forbidden) {
-                return Err(anyhow::anyhow!(
-                    "Forbidden command detected: '{}'. This command could cause system damage.",
-                    forbidden
-                ));
+    /// Snapshot of the current shell session state
+    pub async fn session(&self) -> ShellSession {
+        self.session.lock().await.clone()
+    }
+
+    /// Check a command against the forbidden and risky rules without running it
+    pub fn validate_command(&self, command: &str) -> Result<()> {
+        let script = shell_parser::parse(command).map_err(|e| {
+            anyhow::anyhow!(
+                "Unable to parse command for safety validation: {}. Check the quoting or split the command into simpler steps.",
+                e
+            )
+        })?;
+        self.validate_script(&script, 0)
+    }
+
+    /// Check every pipeline in a parsed script, including nested and substituted commands
+    fn validate_script(&self, script: &Script, depth: usize) -> Result<()> {
+        if depth > MAX_SHELL_NESTING {
+            return Err(anyhow::anyhow!(
+                "Command nests shells too deeply to validate (more than {} levels)",
+                MAX_SHELL_NESTING
+            ));
+        }
+
+        let mut result = Ok(());
+        script.visit_pipelines(&mut |pipeline| {
+            if result.is_ok() {
+                result = self.validate_pipeline(pipeline, depth);
+            }
+        });
+        result
+    }
+
+    fn validate_pipeline(&self, pipeline: &Pipeline, depth: usize) -> Result<()> {
+        let mut programs = Vec::new();
+
+        for command in &pipeline.commands {
+            match command {
+                ShellCommand::Simple(simple) => {
+                    self.validate_simple_command(simple, depth)?;
+                    programs.push(resolve_invocation(&simple.words).map(|inv| inv.program));
+                }
+                ShellCommand::FunctionDef { name, body } => {
+                    // A function that calls itself, e.g. :(){ :|:& };:
+                    let mut recursive = false;
+                    body.visit_nested(&mut |nested| {
+                        recursive |= nested.commands.iter().any(|c| match c {
+                            ShellCommand::Simple(simple) => simple.words.first().is_some_and(|w| &w.value == name),
+                            _ => false,
+                        });
+                    });
+                    if recursive {
+                        return Err(anyhow::anyhow!(
+                            "Forbidden command detected: recursive function '{}' (fork bomb). This command could cause system damage.",
+                            name
+                        ));
+                    }
+                    programs.push(None);
+                }
+                ShellCommand::Compound { .. } => programs.push(None),
             }
         }
 
-        // Risky patterns - warn but allow
-        let risky_patterns = [
-            "rm -rf",
-            "rm -r",
-            "sudo rm",
-            "sudo dd",
-            "sudo chmod",
-            "sudo chown",
-            "sudo mv",
-            "> /dev/",
-            "curl | sh",
-            "wget | sh",
-            "eval ",
-            "exec ",
-            "source /",
-            ". /",
-            "sudo -i",
-            "su -",
-        ];
-
-        for risky in &risky_patterns {
-            if command_lower.contains(risky) {
+        // Downloading a script straight into a shell
+        if let Some(fetch) = programs
+            .iter()
+            .position(|p| matches!(p.as_deref(), Some("curl") | Some("wget")))
+        {
+            if programs[fetch + 1..]
+                .iter()
+                .any(|p| p.as_deref().is_some_and(is_shell))
+            {
                 log::warn!(
-                    "Risky command pattern detected: '{}' in command: {}",
-                    risky,
-                    command
+                    "Risky command detected: downloaded content is piped into a shell ({})",
+                    pipeline_text(pipeline)
                 );
             }
         }
 
-        // Check for command injection attempts
-        let injection_chars = [";", "&&", "||", "|", "`", "$(", "${"];
-        let mut has_injection = false;
-        for inject in &injection_chars {
-            if command.contains(inject) {
-                has_injection = true;
+        Ok(())
+    }
+
+    fn validate_simple_command(&self, simple: &SimpleCommand, depth: usize) -> Result<()> {
+        for redirect in &simple.redirects {
+            let target = &redirect.target.value;
+            if target.starts_with("/dev/") && !SAFE_DEVICES.contains(&target.as_str()) && !target.starts_with("/dev/fd/") {
                 log::warn!(
-                    "Command contains potential injection character: '{}'",
-                    inject
+                    "Risky command detected in `{}`: redirects output to device {}",
+                    simple.text,
+                    target
                 );
             }
         }
 
-        if has_injection {
-            log::warn!(
-                "Command contains shell metacharacters. Ensure this is intentional: {}",
-                command
-            );
+        let Some(invocation) = resolve_invocation(&simple.words) else {
+            if simple.words.first().is_some_and(|w| w.value == "sudo" || w.value == "doas") {
+                log::warn!("Risky command detected in `{}`: opens a privileged shell", simple.text);
+            }
+            return Ok(());
+        };
+
+        if let Some(reason) = forbidden_reason(&invocation) {
+            return Err(anyhow::anyhow!(
+                "Forbidden command detected in `{}`: {}. This command could cause system damage.",
+                simple.text,
+                reason
+            ));
+        }
+
+        for reason in risky_reasons(&invocation) {
+            log::warn!("Risky command detected in `{}`: {}", simple.text, reason);
+        }
+
+        // Commands passed as strings to another shell are validated the same way
+        if let Some(source) = nested_source(&invocation) {
+            let script = shell_parser::parse(&source).map_err(|e| {
+                anyhow::anyhow!(
+                    "Unable to parse nested command in `{}` for safety validation: {}",
+                    simple.text,
+                    e
+                )
+            })?;
+            self.validate_script(&script, depth + 1)?;
         }
 
         Ok(())
     }
 
-    fn sanitize_output(&self, output: &str, max_length: usize) -> String {
+    fn sanitize_output(&self, output: &str) -> String {
         let mut sanitized = output.to_string();
 
         // Remove potential ANSI escape sequences
         let ansi_regex = regex::Regex::new(r"\x1b\[[0-9;]*m").unwrap_or_else(|_| {
             // Fallback if regex fails
-            return regex::Regex::new(r"").unwrap();
+            regex::Regex::new(r"").unwrap()
         });
         sanitized = ansi_regex.replace_all(&sanitized, "").to_string();
 
-        // Truncate if too long
-        if sanitized.len() > max_length {
-            sanitized.truncate(max_length);
-            sanitized.push_str("\n... [OUTPUT TRUNCATED] ...");
-        }
-
+        // Long output is shortened by the tool's output processor
         sanitized
     }
 
@@ -149,10 +296,17 @@ impl BashTool {
         env
     }
 
-    fn get_working_directory(&self) -> Result<std::path::PathBuf> {
-        // Always use current directory for safety
-        std::env::current_dir()
-            .map_err(|e| anyhow::anyhow!("Failed to get current directory: {}", e))
+    fn get_working_directory(&self, session: &ShellSession) -> PathBuf {
+        // Fall back to the workspace root if the session's directory disappeared
+        if session.cwd.is_dir() {
+            session.cwd.clone()
+        } else {
+            log::warn!(
+                "Session working directory no longer exists: {}",
+                session.cwd.display()
+            );
+            self.workspace.root().to_path_buf()
+        }
     }
 }
 
@@ -163,7 +317,7 @@ impl Tool for BashTool {
     }
 
     fn description(&self) -> &str {
-        "Safe command execution with validation. Prefer specialized file tools over bash commands for file operations. Use for system commands, builds, tests, and utilities."
+        "Safe command execution with validation. Runs in a persistent shell session: the working directory and exported variables carry over between calls. Prefer specialized file tools over bash commands for file operations. Use for system commands, builds, tests, and utilities."
     }
 
     fn parameters(&self) -> Value {
@@ -181,6 +335,16 @@ impl Tool for BashTool {
                 "timeout": {
                     "type": "number",
                     "description": "Optional timeout in milliseconds (max 600000ms / 10 minutes). Default: 120000ms (2 minutes)"
+                },
+                "reset_session": {
+                    "type": "boolean",
+                    "description": "Reset the persistent shell session (working directory and exported variables) before running the command (default: false)",
+                    "default": false
+                },
+                "run_in_background": {
+                    "type": "boolean",
+                    "description": "Start the command in the background and return a bash_id immediately. Use bash_output to read its output and bash_kill to stop it (default: false)",
+                    "default": false
                 }
             },
             "required": ["command"]
@@ -200,53 +364,79 @@ impl Tool for BashTool {
 
         // Set timeout (default 2 minutes, max 10 minutes)
         let timeout_ms = params.timeout.unwrap_or(120_000).min(600_000);
-        let _timeout_duration = Duration::from_millis(timeout_ms);
-
-        // Get safe working directory
-        let working_dir = self.get_working_directory()?;
-
-        // Prepare environment
-        let env_vars = Self::get_safe_environment();
+        let timeout_duration = Duration::from_millis(timeout_ms);
+
+        // Working directory and environment come from the persistent session. It stays locked
+        // until the command's state is saved, so commands from concurrent tasks sharing the
+        // session run one at a time instead of overwriting each other's state.
+        let mut session = self.session.lock().await;
+        if params.reset_session {
+            *session = ShellSession::new(self.workspace.root());
+            log::info!("Bash shell session reset");
+        }
+        let working_dir = self.get_working_directory(&session);
+
+        if params.run_in_background {
+            let bash_id = self
+                .processes
+                .spawn(&params.command, &working_dir, &session.env, &self.sandbox)?;
+            return Ok(format!(
+                "Started background process: {}\n\
+                 Command: {}\n\
+                 Working Directory: {}\n\n\
+                 Use bash_output with bash_id '{}' to read new output, and bash_kill to stop it.",
+                bash_id,
+                params.command,
+                working_dir.display(),
+                bash_id
+            ));
+        }
+        // The state file lives in a private directory, so other users can't pre-create it
+        let state_dir = tempfile::Builder::new()
+            .prefix("file-agent-shell-")
+            .tempdir()?;
+        let state_file = state_dir.path().join("session.state");
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&state_file)?;
 
         // Execute command with timeout using shell
         let start_time = std::time::Instant::now();
 
         let mut cmd = Command::new("sh");
         cmd.arg("-c")
-            .arg(&params.command)
+            .arg(session.wrap_command(&params.command, &state_file))
             .current_dir(&working_dir)
-            .env_clear(); // Clear all environment variables first
+            .env_clear() // Clear all environment variables first
+            .envs(&session.env);
+        self.sandbox.apply(&mut cmd);
 
-        // Add safe environment variables
-        for (key, value) in env_vars {
-            cmd.env(key, value);
-        }
+        let output = run_with_timeout(cmd, timeout_duration).await;
 
-        let output = match cmd.output() {
-            Ok(output) => output,
-            Err(e) => {
-                return Err(anyhow::anyhow!(
-                    "Failed to execute command '{}': {}",
-                    params.command,
-                    e
-                ));
-            }
-        };
+        // Carry the resulting directory and exports over to the next call
+        session.update_from_state_file(&state_file);
+        drop(state_dir);
+        let new_working_dir = session.cwd.clone();
+        drop(session);
+
+        let output = output
+            .map_err(|e| anyhow::anyhow!("Failed to execute command '{}': {}", params.command, e))?;
 
         let execution_time = start_time.elapsed();
 
         // Check if command was successful
-        let success = output.status.success();
-        let exit_code = output.status.code().unwrap_or(-1);
+        let timed_out = output.status.is_none();
+        let success = output.status.is_some_and(|status| status.success());
+        let exit_code = output.status.and_then(|status| status.code()).unwrap_or(-1);
 
         // Process output
         let stdout = String::from_utf8_lossy(&output.stdout);
         let stderr = String::from_utf8_lossy(&output.stderr);
 
         // Sanitize and truncate output
-        const MAX_OUTPUT_LENGTH: usize = 30_000; // Output length limit
-        let clean_stdout = self.sanitize_output(&stdout, MAX_OUTPUT_LENGTH);
-        let clean_stderr = self.sanitize_output(&stderr, MAX_OUTPUT_LENGTH / 2);
+        let clean_stdout = self.sanitize_output(&stdout);
+        let clean_stderr = self.sanitize_output(&stderr);
 
         // Format result
         let mut result = String::new();
@@ -257,30 +447,40 @@ impl Tool for BashTool {
             result.push_str(&format!("Description: {}\n", description));
         }
         result.push_str(&format!("Working Directory: {}\n", working_dir.display()));
+        if new_working_dir != working_dir {
+            result.push_str(&format!(
+                "New Working Directory: {}\n",
+                new_working_dir.display()
+            ));
+        }
         result.push_str(&format!("Execution Time: {:?}\n", execution_time));
-        result.push_str(&format!(
-            "Exit Code: {} ({})\n\n",
-            exit_code,
-            if success { "Success" } else { "Failed" }
-        ));
+        if timed_out {
+            result.push_str(&format!("Exit Code: none (timed out after {} ms)\n\n", timeout_ms));
+        } else {
+            result.push_str(&format!(
+                "Exit Code: {} ({})\n\n",
+                exit_code,
+                if success { "Success" } else { "Failed" }
+            ));
+        }
 
         // Output
         if !clean_stdout.is_empty() {
             result.push_str("Standard Output:\n");
             result.push_str(&clean_stdout);
             if !clean_stdout.ends_with('\n') {
-                result.push_str("\n");
+                result.push('\n');
             }
-            result.push_str("\n");
+            result.push('\n');
         }
 
         if !clean_stderr.is_empty() {
             result.push_str("Standard Error:\n");
             result.push_str(&clean_stderr);
             if !clean_stderr.ends_with('\n') {
-                result.push_str("\n");
+                result.push('\n');
             }
-            result.push_str("\n");
+            result.push('\n');
         }
 
         if clean_stdout.is_empty() && clean_stderr.is_empty() {
@@ -288,13 +488,26 @@ impl Tool for BashTool {
         }
 
         // Summary
-        result.push_str(&format!(
-            "Summary: Command {} in {:?}",
-            if success { "succeeded" } else { "failed" },
-            execution_time
-        ));
+        if timed_out {
+            result.push_str(&format!(
+                "Summary: Command timed out after {} ms and was killed (partial output shown above)",
+                timeout_ms
+            ));
+        } else {
+            result.push_str(&format!(
+                "Summary: Command {} in {:?}",
+                if success { "succeeded" } else { "failed" },
+                execution_time
+            ));
+        }
 
-        if !success {
+        if timed_out {
+            log::error!(
+                "Command timed out after {} ms: {}",
+                timeout_ms,
+                params.command
+            );
+        } else if !success {
             log::error!(
                 "Command failed: {} (exit code: {})",
                 params.command,
@@ -310,4 +523,237 @@ impl Tool for BashTool {
 
         Ok(result)
     }
+
+    fn output_processor(&self) -> Option<&dyn OutputProcessor> {
+        Some(&Excerpt::COMMAND)
+    }
+}
+
+/// Run a command in its own process group, killing the whole group if it exceeds `timeout`.
+/// Output produced before the timeout is kept so it can be reported to the caller.
+async fn run_with_timeout(mut cmd: Command, timeout: Duration) -> Result<CommandOutput> {
+    let mut child = spawn_in_process_group(&mut cmd)?;
+
+    let stdout_buf = Arc::new(Mutex::new(Vec::new()));
+    let stderr_buf = Arc::new(Mutex::new(Vec::new()));
+    let stdout_task = child
+        .stdout
+        .take()
+        .map(|pipe| tokio::spawn(drain_pipe(pipe, stdout_buf.clone())));
+    let stderr_task = child
+        .stderr
+        .take()
+        .map(|pipe| tokio::spawn(drain_pipe(pipe, stderr_buf.clone())));
+
+    let status = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => Some(status?),
+        Err(_) => {
+            kill_process_group(&mut child).await;
+            None
+        }
+    };
+
+    // Background grandchildren may keep the pipes open, so don't wait on them forever
+    for task in [stdout_task, stderr_task].into_iter().flatten() {
+        let _ = tokio::time::timeout(PIPE_DRAIN_TIMEOUT, task).await;
+    }
+
+    let stdout = std::mem::take(&mut *stdout_buf.lock().unwrap());
+    let stderr = std::mem::take(&mut *stderr_buf.lock().unwrap());
+
+    Ok(CommandOutput {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Spawn a command with piped output in a new process group so that children spawned by
+/// the shell can be killed together
+pub(crate) fn spawn_in_process_group(cmd: &mut Command) -> Result<tokio::process::Child> {
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    Ok(cmd.spawn()?)
 }
+
+/// Copy everything from a pipe into a shared buffer until EOF
+async fn drain_pipe<R: AsyncRead + Unpin>(mut pipe: R, buffer: Arc<Mutex<Vec<u8>>>) {
+    let mut chunk = [0u8; 8192];
+    loop {
+        match pipe.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => buffer.lock().unwrap().extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+/// Kill the child's entire process group, then reap the child
+async fn kill_process_group(child: &mut tokio::process::Child) {
+    signal_process_group(child);
+
+    if let Err(e) = child.kill().await {
+        log::debug!("Failed to kill timed out command: {}", e);
+    }
+}
+
+/// Send SIGKILL to the child's process group without waiting for it to exit
+pub(crate) fn signal_process_group(child: &tokio::process::Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: killpg has no memory-safety preconditions; the group id was created by process_group(0)
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = child;
+}
+
+/// Quote a string for safe interpolation into a POSIX shell script
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// True for `/`, `//`, `/*`, `/.` and the home directory, also when spelled with `..`,
+/// e.g. `/usr/..` or `~/.cache/..`
+fn is_root_target(word: &Word) -> bool {
+    let value = word.value.as_str();
+    let rest = if value.starts_with('/') {
+        value
+    } else {
+        let Some(rest) = ["~", "$HOME", "${HOME}"]
+            .iter()
+            .find_map(|home| value.strip_prefix(home))
+        else {
+            return false;
+        };
+        if !rest.is_empty() && !rest.starts_with('/') {
+            return false;
+        }
+        rest
+    };
+
+    // Resolve `.` and `..` lexically; what is left must be a glob of everything
+    let mut components: Vec<&str> = Vec::new();
+    for component in rest.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+    components
+        .iter()
+        .all(|component| component.chars().all(|c| c == '*' || c == '.'))
+}
+
+/// True if the arguments contain -r/-R (possibly combined, e.g. -rf) or --recursive
+fn has_recursive_flag(args: &[Word]) -> bool {
+    args.iter().any(|arg| {
+        let value = arg.value.as_str();
+        value == "--recursive"
+            || (value.starts_with('-') && !value.starts_with("--") && value.contains(['r', 'R']))
+    })
+}
+
+fn forbidden_reason(invocation: &Invocation) -> Option<String> {
+    let args = invocation.args;
+    let has_arg = |expected: &str| args.iter().any(|arg| arg.value == expected);
+
+    match invocation.program.as_str() {
+        "shutdown" | "reboot" | "halt" | "poweroff" => {
+            Some(format!("'{}' stops the machine", invocation.program))
+        }
+        "init" | "telinit" if has_arg("0") || has_arg("6") => {
+            Some(format!("'{}' changes the system runlevel", invocation.program))
+        }
+        program if program.starts_with("mkfs") || program == "fdisk" || program == "sfdisk" => {
+            Some(format!("'{}' modifies disks or partitions", program))
+        }
+        "rm" if has_arg("--no-preserve-root") => Some("'rm --no-preserve-root'".to_string()),
+        "rm" if has_recursive_flag(args) && args.iter().any(is_root_target) => {
+            Some("recursive 'rm' of the root or home directory".to_string())
+        }
+        "chmod" | "chown" | "chgrp" if has_recursive_flag(args) && args.iter().any(is_root_target) => {
+            Some(format!(
+                "recursive '{}' of the root or home directory",
+                invocation.program
+            ))
+        }
+        "mv" if args.iter().any(|arg| arg.value == "/") => {
+            Some("'mv' of the root directory".to_string())
+        }
+        "dd" if args.iter().any(|arg| {
+            arg.value.starts_with("of=/dev/") && !SAFE_DEVICES.contains(&&arg.value[3..])
+        }) =>
+        {
+            Some("'dd' writing to a device".to_string())
+        }
+        "killall" if args.iter().any(|arg| matches!(arg.value.as_str(), "-9" | "-KILL" | "-SIGKILL")) => {
+            Some("'killall -9' kills processes indiscriminately".to_string())
+        }
+        "format" if args.iter().any(|arg| arg.value.eq_ignore_ascii_case("c:")) => {
+            Some("'format c:'".to_string())
+        }
+        "del" if args.iter().any(|arg| arg.value.to_lowercase().starts_with("c:\\")) => {
+            Some("'del' of the system drive".to_string())
+        }
+        _ => None,
+    }
+}
+
+fn risky_reasons(invocation: &Invocation) -> Vec<String> {
+    let mut reasons = Vec::new();
+    let args = invocation.args;
+
+    if invocation.privileged {
+        reasons.push(format!("runs '{}' with elevated privileges", invocation.program));
+    }
+    if invocation.dynamic_program {
+        reasons.push("program name is computed at runtime".to_string());
+    }
+
+    match invocation.program.as_str() {
+        "rm" if has_recursive_flag(args) => {
+            reasons.push("recursive delete".to_string());
+            if args.iter().any(|arg| arg.dynamic) {
+                reasons.push("delete target is computed at runtime".to_string());
+            }
+        }
+        "chmod" | "chown" | "chgrp" if has_recursive_flag(args) => {
+            reasons.push(format!("recursive '{}'", invocation.program));
+        }
+        "dd" if args.iter().any(|arg| arg.value.starts_with("if=/dev/")) => {
+            reasons.push("'dd' reading from a device".to_string());
+        }
+        "su" => reasons.push("switches user".to_string()),
+        "eval" | "exec" => reasons.push(format!("uses '{}'", invocation.program)),
+        "source" | "." if args.first().is_some_and(|arg| arg.value.starts_with('/')) => {
+            reasons.push("sources a script by absolute path".to_string());
+        }
+        _ => {}
+    }
+
+    reasons
+}
+
+fn pipeline_text(pipeline: &Pipeline) -> String {
+    pipeline
+        .commands
+        .iter()
+        .map(|command| match command {
+            ShellCommand::Simple(simple) => simple.text.as_str(),
+            _ => "...",
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
@@ -0,0 +1,62 @@
+use super::background::ProcessRegistry;
+use crate::tool::Tool;
+use anyhow::Result;
+use serde_json::{json, Value};
+
+/// Termination of background bash processes
+pub struct BashKillTool {
+    processes: ProcessRegistry,
+}
+
+#[derive(serde::Deserialize)]
+struct BashKillParams {
+    bash_id: String,
+}
+
+impl BashKillTool {
+    pub fn new(processes: ProcessRegistry) -> Self {
+        Self { processes }
+    }
+}
+
+#[async_trait::async_trait]
+impl Tool for BashKillTool {
+    fn name(&self) -> &str {
+        "bash_kill"
+    }
+
+    fn description(&self) -> &str {
+        "Kill a background bash process and all of its child processes."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "bash_id": {
+                    "type": "string",
+                    "description": "ID of the background process to kill"
+                }
+            },
+            "required": ["bash_id"]
+        })
+    }
+
+    async fn execute(&self, arguments: &str) -> Result<String> {
+        let params: BashKillParams = serde_json::from_str(arguments)?;
+
+        let info = self.processes.kill(&params.bash_id)?;
+
+        if info.running {
+            Ok(format!(
+                "Killed background process {} after {}s: {}",
+                info.id, info.elapsed_secs, info.command
+            ))
+        } else {
+            Ok(format!(
+                "Background process {} had already {}; handle removed: {}",
+                info.id, info.status, info.command
+            ))
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use super::background::ProcessRegistry;
+use crate::tool::Tool;
+use anyhow::Result;
+use serde_json::{json, Value};
+
+/// Listing of background bash processes
+pub struct BashListTool {
+    processes: ProcessRegistry,
+}
+
+impl BashListTool {
+    pub fn new(processes: ProcessRegistry) -> Self {
+        Self { processes }
+    }
+}
+
+#[async_trait::async_trait]
+impl Tool for BashListTool {
+    fn name(&self) -> &str {
+        "bash_list"
+    }
+
+    fn description(&self) -> &str {
+        "List background bash processes with their IDs, commands, status and running time."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    async fn execute(&self, _arguments: &str) -> Result<String> {
+        let processes = self.processes.list();
+
+        if processes.is_empty() {
+            return Ok("No background processes".to_string());
+        }
+
+        let running = processes.iter().filter(|p| p.running).count();
+        let mut result = format!(
+            "Background processes ({} total, {} running):\n\n",
+            processes.len(),
+            running
+        );
+
+        for process in processes {
+            result.push_str(&format!(
+                "  {} [{}] ({}s) {}\n",
+                process.id, process.status, process.elapsed_secs, process.command
+            ));
+        }
+
+        Ok(result)
+    }
+}
@@ -0,0 +1,112 @@
+use super::background::ProcessRegistry;
+use crate::output::{Excerpt, OutputProcessor};
+use crate::tool::Tool;
+use anyhow::Result;
+use regex::Regex;
+use serde_json::{json, Value};
+
+/// Incremental output reader for background bash processes
+pub struct BashOutputTool {
+    processes: ProcessRegistry,
+}
+
+#[derive(serde::Deserialize)]
+struct BashOutputParams {
+    bash_id: String,
+    #[serde(default)]
+    filter: Option<String>,
+}
+
+impl BashOutputTool {
+    pub fn new(processes: ProcessRegistry) -> Self {
+        Self { processes }
+    }
+
+    fn filter_lines(&self, output: &str, filter: Option<&Regex>) -> String {
+        match filter {
+            Some(regex) => output
+                .lines()
+                .filter(|line| regex.is_match(line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => output.to_string(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Tool for BashOutputTool {
+    fn name(&self) -> &str {
+        "bash_output"
+    }
+
+    fn description(&self) -> &str {
+        "Read new output from a background bash process. Returns only stdout/stderr produced since the last read, along with the process status."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "bash_id": {
+                    "type": "string",
+                    "description": "ID of the background process returned by bash with run_in_background=true"
+                },
+                "filter": {
+                    "type": "string",
+                    "description": "Optional regex; only output lines matching it are returned. Non-matching lines are discarded."
+                }
+            },
+            "required": ["bash_id"]
+        })
+    }
+
+    async fn execute(&self, arguments: &str) -> Result<String> {
+        let params: BashOutputParams = serde_json::from_str(arguments)?;
+
+        let filter = params
+            .filter
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("Invalid filter regex: {}", e))?;
+
+        let output = self.processes.read_new_output(&params.bash_id)?;
+        let stdout = self.filter_lines(&output.stdout, filter.as_ref());
+        let stderr = self.filter_lines(&output.stderr, filter.as_ref());
+
+        let mut result = format!("Process: {}\nStatus: {}\n\n", params.bash_id, output.status);
+
+        if !stdout.is_empty() {
+            result.push_str("Standard Output:\n");
+            result.push_str(&stdout);
+            if !stdout.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push('\n');
+        }
+
+        if !stderr.is_empty() {
+            result.push_str("Standard Error:\n");
+            result.push_str(&stderr);
+            if !stderr.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push('\n');
+        }
+
+        if stdout.is_empty() && stderr.is_empty() {
+            result.push_str("No new output\n");
+        }
+
+        if output.removed {
+            result.push_str("\nThe process has finished and all of its output was read, so its bash_id was released.\n");
+        }
+
+        Ok(result)
+    }
+
+    fn output_processor(&self) -> Option<&dyn OutputProcessor> {
+        Some(&Excerpt::COMMAND)
+    }
+}
@@ -1,11 +1,13 @@
+use crate::agents::file::workspace::Workspace;
 use crate::tool::Tool;
 use anyhow::Result;
 use serde_json::{json, Value};
 use std::fs;
-use std::path::Path;
 
 /// Precise string replacement with context verification
-pub struct EditTool;
+pub struct EditTool {
+    workspace: Workspace,
+}
 
 #[derive(serde::Deserialize)]
 struct EditParams {
@@ -21,8 +23,8 @@ fn default_false() -> bool {
 }
 
 impl EditTool {
-    pub fn new() -> Self {
-        Self
+    pub fn new(workspace: Workspace) -> Self {
+        Self { workspace }
     }
 
     fn validate_edit_params(&self, params: &EditParams) -> Result<()> {
@@ -202,12 +204,8 @@ impl Tool for EditTool {
         // Validate parameters
         self.validate_edit_params(&params)?;
 
-        // Handle both absolute and relative paths
-        let file_path = if Path::new(&params.file_path).is_absolute() {
-            Path::new(&params.file_path).to_path_buf()
-        } else {
-            std::env::current_dir()?.join(&params.file_path)
-        };
+        // Resolve relative to the workspace root and enforce the workspace boundary
+        let file_path = self.workspace.resolve_write(&params.file_path)?;
 
         if !file_path.exists() {
             return Err(anyhow::anyhow!(
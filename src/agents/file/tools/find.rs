@@ -1,3 +1,5 @@
+use crate::agents::file::workspace::Workspace;
+use crate::output::{Excerpt, OutputProcessor};
 use crate::tool::Tool;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -5,7 +7,6 @@ use chrono::{DateTime, Duration, Utc};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::path::Path;
 use walkdir::{DirEntry, WalkDir};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,20 +34,22 @@ fn default_limit() -> usize {
     1000
 }
 
-pub struct FindTool;
+pub struct FindTool {
+    workspace: Workspace,
+}
 
 impl FindTool {
-    pub fn new() -> Self {
-        Self
+    pub fn new(workspace: Workspace) -> Self {
+        Self { workspace }
     }
 
     /// Parse size filter (e.g., "+1M", "-100K", "50K")
     fn parse_size_filter(&self, size_str: &str) -> Result<(char, u64)> {
         let size_str = size_str.trim();
-        let (op, size_part) = if size_str.starts_with('+') {
-            ('+', &size_str[1..])
-        } else if size_str.starts_with('-') {
-            ('-', &size_str[1..])
+        let (op, size_part) = if let Some(rest) = size_str.strip_prefix('+') {
+            ('+', rest)
+        } else if let Some(rest) = size_str.strip_prefix('-') {
+            ('-', rest)
         } else {
             ('=', size_str)
         };
@@ -75,10 +78,10 @@ impl FindTool {
     /// Parse time filter (e.g., "+7d", "-24h", "-30m")
     fn parse_time_filter(&self, time_str: &str) -> Result<(char, Duration)> {
         let time_str = time_str.trim();
-        let (op, time_part) = if time_str.starts_with('+') {
-            ('+', &time_str[1..])
-        } else if time_str.starts_with('-') {
-            ('-', &time_str[1..])
+        let (op, time_part) = if let Some(rest) = time_str.strip_prefix('+') {
+            ('+', rest)
+        } else if let Some(rest) = time_str.strip_prefix('-') {
+            ('-', rest)
         } else {
             return Err(anyhow::anyhow!("Time filter must start with + or -"));
         };
@@ -182,11 +185,7 @@ impl FindTool {
 
             let modified_datetime: DateTime<Utc> = modified_time.into();
             let now = Utc::now();
-            let threshold = if op == '-' {
-                now - duration
-            } else {
-                now - duration
-            };
+            let threshold = now - duration;
 
             let matches = if op == '-' {
                 modified_datetime > threshold // Modified within the last duration
@@ -322,13 +321,13 @@ impl Tool for FindTool {
             .map_err(|e| anyhow::anyhow!("Failed to parse find arguments: {}", e))?;
 
         // Validate path
-        let search_path = Path::new(&parsed_args.path);
+        let search_path = self.workspace.resolve_read(&parsed_args.path)?;
         if !search_path.exists() {
             return Err(anyhow::anyhow!("Path does not exist: {}", parsed_args.path));
         }
 
         // Build walker with max depth
-        let mut walker = WalkDir::new(search_path);
+        let mut walker = WalkDir::new(&search_path);
         if let Some(max_depth) = parsed_args.max_depth {
             walker = walker.max_depth(max_depth);
         }
@@ -388,4 +387,8 @@ impl Tool for FindTool {
         log::info!("Find completed: {} matches found", results.len());
         Ok(summary)
     }
+
+    fn output_processor(&self) -> Option<&dyn OutputProcessor> {
+        Some(&Excerpt::LISTING)
+    }
 }
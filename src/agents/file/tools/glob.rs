@@ -1,3 +1,5 @@
+use crate::agents::file::workspace::Workspace;
+use crate::output::{Excerpt, OutputProcessor};
 use crate::tool::Tool;
 use anyhow::Result;
 use serde_json::{json, Value};
@@ -5,7 +7,9 @@ use std::path::Path;
 use std::fs;
 
 /// Pattern-based file finding with result optimization
-pub struct GlobTool;
+pub struct GlobTool {
+    workspace: Workspace,
+}
 
 #[derive(serde::Deserialize)]
 struct GlobParams {
@@ -15,8 +19,8 @@ struct GlobParams {
 }
 
 impl GlobTool {
-    pub fn new() -> Self {
-        Self
+    pub fn new(workspace: Workspace) -> Self {
+        Self { workspace }
     }
 
     /// Simple glob pattern matching implementation
@@ -29,9 +33,8 @@ impl GlobTool {
             }
         }
         
-        if pattern.starts_with("*.") {
+        if let Some(ext) = pattern.strip_prefix("*.") {
             // Simple extension pattern like "*.rs"
-            let ext = &pattern[2..];
             return file_path.ends_with(&format!(".{}", ext));
         }
 
@@ -65,7 +68,9 @@ impl GlobTool {
                 continue;
             }
 
-            if path.is_file() {
+            // Don't follow symlinks so the search can't escape the workspace
+            let file_type = entry.file_type()?;
+            if file_type.is_file() {
                 if self.matches_pattern(&path_str, pattern) {
                     let metadata = entry.metadata()?;
                     let modified = metadata.modified()?
@@ -73,7 +78,7 @@ impl GlobTool {
                         .as_secs();
                     results.push((path_str, modified));
                 }
-            } else if path.is_dir() {
+            } else if file_type.is_dir() {
                 // Recurse into subdirectories
                 self.collect_files(&path, pattern, results)?;
             }
@@ -114,17 +119,10 @@ impl Tool for GlobTool {
         let params: GlobParams = serde_json::from_str(arguments)?;
         
         let search_path = match &params.path {
-            Some(path) => {
-                // Handle both absolute and relative paths (like ls tool)
-                if Path::new(path).is_absolute() {
-                    std::env::current_dir()?.join(path).canonicalize()?
-                } else {
-                    std::env::current_dir()?.join(path).canonicalize()?
-                }
-            }
+            Some(path) => self.workspace.resolve_read(path)?,
             None => {
-                // Use current working directory
-                std::env::current_dir()?
+                // Use the workspace root
+                self.workspace.root().to_path_buf()
             }
         };
 
@@ -140,35 +138,20 @@ impl Tool for GlobTool {
         }
 
         // Sort by modification time (newest first)
-        results.sort_by(|a, b| b.1.cmp(&a.1));
+        results.sort_by_key(|r| std::cmp::Reverse(r.1));
 
-        // Format results with intelligent truncation
+        // Long lists are shortened by the tool's output processor
         let mut output = format!("Found {} files matching pattern '{}':\n\n", 
                                 results.len(), 
                                 params.pattern);
-
-        if results.len() <= 100 {
-            // Show all results for reasonable counts
-            for (path, _) in results {
-                output.push_str(&format!("{}\n", path));
-            }
-        } else {
-            // Intelligent truncation for large result sets
-            output.push_str(&format!("(Showing first 50 and last 10 of {} total files)\n\n", results.len()));
-            
-            // First 50 files
-            for (path, _) in results.iter().take(50) {
-                output.push_str(&format!("{}\n", path));
-            }
-            
-            output.push_str("\n... [TRUNCATED] ...\n\n");
-            
-            // Last 10 files
-            for (path, _) in results.iter().skip(results.len() - 10) {
-                output.push_str(&format!("{}\n", path));
-            }
+        for (path, _) in results {
+            output.push_str(&format!("{}\n", path));
         }
 
         Ok(output)
     }
+
+    fn output_processor(&self) -> Option<&dyn OutputProcessor> {
+        Some(&Excerpt::LISTING)
+    }
 }
\ No newline at end of file
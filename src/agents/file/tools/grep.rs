@@ -1,11 +1,21 @@
+use crate::agents::file::workspace::Workspace;
+use crate::output::{Excerpt, OutputProcessor};
 use crate::tool::Tool;
 use anyhow::Result;
 use serde_json::{json, Value};
 use std::path::Path;
 use std::process::Command;
 
+/// Matching lines kept before grep output is shortened
+const GREP_OUTPUT: Excerpt = Excerpt {
+    max_lines: 30,
+    tail_percent: 20,
+};
+
 /// Intelligent text search with context-aware truncation
-pub struct GrepTool;
+pub struct GrepTool {
+    workspace: Workspace,
+}
 
 #[derive(serde::Deserialize)]
 struct GrepParams {
@@ -39,8 +49,8 @@ fn default_false() -> bool {
 }
 
 impl GrepTool {
-    pub fn new() -> Self {
-        Self
+    pub fn new(workspace: Workspace) -> Self {
+        Self { workspace }
     }
 
     fn get_file_type_extensions(file_type: &str) -> Vec<&'static str> {
@@ -171,6 +181,7 @@ impl GrepTool {
         cmd
     }
 
+    /// Label the output. Long outputs are shortened by the tool's output processor.
     fn process_grep_output(&self, output: String, params: &GrepParams) -> Result<String> {
         let lines: Vec<&str> = output.lines().collect();
 
@@ -178,100 +189,24 @@ impl GrepTool {
             return Ok(format!("No matches found for pattern: {}", params.pattern));
         }
 
-        // Threshold: 30 lines before truncation
-        const TRUNCATE_THRESHOLD: usize = 30;
-
         let output_mode = params
             .output_mode
             .as_deref()
             .unwrap_or("files_with_matches");
 
         match output_mode {
-            "content" => {
-                if lines.len() <= TRUNCATE_THRESHOLD {
-                    return Ok(output);
-                }
-
-                // Smart truncation for content mode
-                let unique_files = self.count_unique_files(&lines);
-                let mut result = format!(
-                    "Found {} matches across {} files.\n\nFirst {} matches:\n",
-                    lines.len(),
-                    unique_files,
-                    TRUNCATE_THRESHOLD.min(lines.len())
-                );
-
-                // Show first matches
-                for line in lines.iter().take(TRUNCATE_THRESHOLD) {
-                    result.push_str(&format!("{}\n", line));
-                }
-
-                if lines.len() > TRUNCATE_THRESHOLD {
-                    result.push_str(&format!(
-                        "\n... [TRUNCATED {} additional matches] ...\n",
-                        lines.len() - TRUNCATE_THRESHOLD
-                    ));
-
-                    // Show last few matches for context
-                    let last_count = 5.min(lines.len().saturating_sub(TRUNCATE_THRESHOLD));
-                    if last_count > 0 {
-                        result.push_str("\nLast few matches:\n");
-                        for line in lines.iter().skip(lines.len() - last_count) {
-                            result.push_str(&format!("{}\n", line));
-                        }
-                    }
-                }
-
-                Ok(result)
-            }
-            "files_with_matches" => {
-                if lines.len() <= 100 {
-                    return Ok(format!(
-                        "Found matches in {} files:\n\n{}",
-                        lines.len(),
-                        output
-                    ));
-                }
-
-                // Truncate file list for large results
-                let mut result = format!("Found matches in {} files:\n\n", lines.len());
-                result.push_str("First 50 files:\n");
-                for line in lines.iter().take(50) {
-                    result.push_str(&format!("{}\n", line));
-                }
-
-                result.push_str(&format!(
-                    "\n... [TRUNCATED {} additional files] ...\n",
-                    lines.len() - 50
-                ));
-
-                // Show last 10 files
-                for line in lines.iter().skip(lines.len() - 10) {
-                    result.push_str(&format!("{}\n", line));
-                }
-
-                Ok(result)
-            }
-            "count" => {
-                if lines.len() <= 50 {
-                    return Ok(format!("Match counts per file:\n\n{}", output));
-                }
-
-                // Truncate count output
-                let mut result = format!("Match counts for {} files:\n\n", lines.len());
-                for line in lines.iter().take(50) {
-                    result.push_str(&format!("{}\n", line));
-                }
-
-                if lines.len() > 50 {
-                    result.push_str(&format!(
-                        "\n... [TRUNCATED {} additional files] ...\n",
-                        lines.len() - 50
-                    ));
-                }
-
-                Ok(result)
-            }
+            "content" => Ok(format!(
+                "Found {} matching lines across {} files:\n\n{}",
+                lines.len(),
+                self.count_unique_files(&lines),
+                output
+            )),
+            "files_with_matches" => Ok(format!(
+                "Found matches in {} files:\n\n{}",
+                lines.len(),
+                output
+            )),
+            "count" => Ok(format!("Match counts per file:\n\n{}", output)),
             _ => Ok(output),
         }
     }
@@ -362,15 +297,8 @@ impl Tool for GrepTool {
 
         // Determine search path
         let search_path = match &params.path {
-            Some(path) => {
-                let path_buf = if Path::new(path).is_absolute() {
-                    Path::new(path).to_path_buf()
-                } else {
-                    std::env::current_dir()?.join(path)
-                };
-                path_buf
-            }
-            None => std::env::current_dir()?,
+            Some(path) => self.workspace.resolve_read(path)?,
+            None => self.workspace.root().to_path_buf(),
         };
 
         if !search_path.exists() {
@@ -397,7 +325,10 @@ impl Tool for GrepTool {
 
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
 
-        // Process output with intelligent truncation
         self.process_grep_output(stdout, &params)
     }
+
+    fn output_processor(&self) -> Option<&dyn OutputProcessor> {
+        Some(&GREP_OUTPUT)
+    }
 }
@@ -1,11 +1,14 @@
+use crate::agents::file::workspace::Workspace;
+use crate::output::{Excerpt, OutputProcessor};
 use crate::tool::Tool;
 use anyhow::Result;
 use serde_json::{json, Value};
 use std::fs;
-use std::path::Path;
 
 /// Smart directory listing with size analysis and filtering
-pub struct LsTool;
+pub struct LsTool {
+    workspace: Workspace,
+}
 
 #[derive(serde::Deserialize)]
 struct LsParams {
@@ -15,8 +18,8 @@ struct LsParams {
 }
 
 impl LsTool {
-    pub fn new() -> Self {
-        Self
+    pub fn new(workspace: Workspace) -> Self {
+        Self { workspace }
     }
 
     fn format_file_size(size: u64) -> String {
@@ -78,13 +81,8 @@ impl Tool for LsTool {
     async fn execute(&self, arguments: &str) -> Result<String> {
         let params: LsParams = serde_json::from_str(arguments)?;
         
-        // Convert relative paths to absolute paths
-        let path = if Path::new(&params.path).is_absolute() {
-            Path::new(&params.path).to_path_buf()
-        } else {
-            // Handle relative paths like "." or ".."
-            std::env::current_dir()?.join(&params.path)
-        };
+        // Resolve relative to the workspace root and enforce the workspace boundary
+        let path = self.workspace.resolve_read(&params.path)?;
         
         let path = path.as_path();
         if !path.exists() {
@@ -124,22 +122,15 @@ impl Tool for LsTool {
         }
 
         // Sort by modification time (newest first)
-        dirs.sort_by(|a, b| b.1.cmp(&a.1));
-        files.sort_by(|a, b| b.2.cmp(&a.2));
+        dirs.sort_by_key(|d| std::cmp::Reverse(d.1));
+        files.sort_by_key(|f| std::cmp::Reverse(f.2));
 
         // Create intelligent summary
         let mut result = format!("Directory: {} ({})\n", params.path, path.display());
         
         if !dirs.is_empty() {
             result.push_str(&format!("\nSubdirectories ({}):\n", dirs.len()));
-            let display_dirs = if dirs.len() > 20 {
-                result.push_str(&format!("(Showing first 20 of {} directories)\n", dirs.len()));
-                &dirs[..20]
-            } else {
-                &dirs
-            };
-            
-            for (name, _) in display_dirs {
+            for (name, _) in &dirs {
                 result.push_str(&format!("  {}/\n", name));
             }
         }
@@ -148,22 +139,15 @@ impl Tool for LsTool {
             result.push_str(&format!("\nFiles ({}) - Total size: {}:\n", 
                                    files.len(), 
                                    Self::format_file_size(total_size)));
-            
-            let display_files = if files.len() > 30 {
-                result.push_str(&format!("(Showing first 30 of {} files)\n", files.len()));
-                &files[..30]
-            } else {
-                &files
-            };
 
-            for (name, size, _) in display_files {
+            for (name, size, _) in &files {
                 result.push_str(&format!("  {} ({})\n", name, Self::format_file_size(*size)));
             }
 
             // Show largest files summary
             if files.len() > 5 {
                 let mut largest_files = files.clone();
-                largest_files.sort_by(|a, b| b.1.cmp(&a.1));
+                largest_files.sort_by_key(|f| std::cmp::Reverse(f.1));
                 result.push_str("\nLargest files:\n");
                 for (name, size, _) in largest_files.iter().take(5) {
                     result.push_str(&format!("  {} ({})\n", name, Self::format_file_size(*size)));
@@ -177,4 +161,8 @@ impl Tool for LsTool {
 
         Ok(result)
     }
+
+    fn output_processor(&self) -> Option<&dyn OutputProcessor> {
+        Some(&Excerpt::LISTING)
+    }
 }
\ No newline at end of file
@@ -19,6 +19,11 @@ pub mod multi_edit;
 
 // Operations tools
 pub mod bash;
+pub mod bash_output;
+pub mod bash_list;
+pub mod bash_kill;
+pub mod read_result;
+pub mod background;
 
 // Re-export all tools
 pub use ls::LsTool;
@@ -30,4 +35,9 @@ pub use read::ReadTool;
 pub use write::WriteTool;
 pub use edit::EditTool;
 pub use multi_edit::MultiEditTool;
-pub use bash::BashTool;
\ No newline at end of file
+pub use bash::{BashTool, ShellSession};
+pub use bash_output::BashOutputTool;
+pub use bash_list::BashListTool;
+pub use bash_kill::BashKillTool;
+pub use read_result::ReadResultTool;
+pub use background::ProcessRegistry;
\ No newline at end of file
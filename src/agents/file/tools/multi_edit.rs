@@ -1,11 +1,13 @@
+use crate::agents::file::workspace::Workspace;
 use crate::tool::Tool;
 use anyhow::Result;
 use serde_json::{json, Value};
 use std::fs;
-use std::path::Path;
 
 /// Atomic batch operations with rollback support. Matches Claude Code behavior.
-pub struct MultiEditTool;
+pub struct MultiEditTool {
+    workspace: Workspace,
+}
 
 #[derive(serde::Deserialize)]
 struct MultiEditParams {
@@ -26,8 +28,8 @@ fn default_false() -> bool {
 }
 
 impl MultiEditTool {
-    pub fn new() -> Self {
-        Self
+    pub fn new(workspace: Workspace) -> Self {
+        Self { workspace }
     }
 
     fn validate_edits(&self, edits: &[EditOperation]) -> Result<()> {
@@ -168,7 +170,7 @@ impl MultiEditTool {
                 preview.push_str(&format!("  Line {}:\n", i + 1));
                 preview.push_str(&format!("  - {}\n", orig_line));
                 preview.push_str(&format!("  + {}\n", new_line));
-                preview.push_str("\n");
+                preview.push('\n');
                 changes_shown += 1;
             }
         }
@@ -245,12 +247,8 @@ impl Tool for MultiEditTool {
         // Validate edits
         self.validate_edits(&params.edits)?;
 
-        // Handle both absolute and relative paths
-        let file_path = if Path::new(&params.file_path).is_absolute() {
-            Path::new(&params.file_path).to_path_buf()
-        } else {
-            std::env::current_dir()?.join(&params.file_path)
-        };
+        // Resolve relative to the workspace root and enforce the workspace boundary
+        let file_path = self.workspace.resolve_write(&params.file_path)?;
 
         if !file_path.exists() {
             return Err(anyhow::anyhow!(
@@ -312,7 +310,7 @@ impl Tool for MultiEditTool {
                 for edit_result in edit_results {
                     result.push_str(&format!("  • {}\n", edit_result));
                 }
-                result.push_str("\n");
+                result.push('\n');
 
                 // Add change preview
                 result.push_str(&self.preview_changes(
@@ -1,11 +1,20 @@
+use crate::agents::file::context_manager::LINE_CHAR_LIMIT;
+use crate::agents::file::workspace::Workspace;
+use crate::output::{FileSample, OutputProcessor};
 use crate::tool::Tool;
+use crate::utils;
 use anyhow::Result;
 use serde_json::{json, Value};
 use std::fs;
 use std::path::Path;
 
+/// File lines kept before a full read is sampled
+const READ_OUTPUT: FileSample = FileSample { max_lines: 2000 };
+
 /// Context-aware file reading with smart sampling
-pub struct ReadTool;
+pub struct ReadTool {
+    workspace: Workspace,
+}
 
 #[derive(serde::Deserialize)]
 struct ReadParams {
@@ -17,8 +26,8 @@ struct ReadParams {
 }
 
 impl ReadTool {
-    pub fn new() -> Self {
-        Self
+    pub fn new(workspace: Workspace) -> Self {
+        Self { workspace }
     }
 
     fn format_line_numbers(&self, content: &str, start_line: usize) -> String {
@@ -27,8 +36,8 @@ impl ReadTool {
             .enumerate()
             .map(|(i, line)| {
                 // Truncate long lines for readability
-                let truncated_line = if line.len() > 2000 {
-                    format!("{}... [TRUNCATED]", &line[..2000])
+                let truncated_line = if line.len() > LINE_CHAR_LIMIT {
+                    format!("{}... [TRUNCATED]", utils::truncate_bytes(line, LINE_CHAR_LIMIT))
                 } else {
                     line.to_string()
                 };
@@ -38,63 +47,10 @@ impl ReadTool {
             .join("\n")
     }
 
-    fn smart_sample_large_file(&self, file_path: &Path) -> Result<String> {
+    /// Whole file with line numbers. Large files are sampled by the tool's output processor.
+    fn read_full_file(&self, file_path: &Path) -> Result<String> {
         let content = fs::read_to_string(file_path)?;
-        let lines: Vec<&str> = content.lines().collect();
-
-        // Threshold: 2000 lines before sampling
-        if lines.len() <= 2000 {
-            return Ok(self.format_line_numbers(&content, 0));
-        }
-
-        // Smart sampling strategy with beginning, middle, and end sections
-        let mut sampled_content = String::new();
-
-        // Header with file info
-        sampled_content.push_str(&format!(
-            "=== FILE PREVIEW (Large file: {} lines, {} KB) ===\n\n",
-            lines.len(),
-            content.len() / 1024
-        ));
-
-        // Beginning section (first 100 lines)
-        sampled_content.push_str("BEGINNING (first 100 lines):\n");
-        let beginning: String = lines
-            .iter()
-            .take(100)
-            .map(|s| *s)
-            .collect::<Vec<_>>()
-            .join("\n");
-        sampled_content.push_str(&self.format_line_numbers(&beginning, 0));
-        sampled_content.push_str("\n\n");
-
-        // Middle section (around line count/2)
-        let middle_start = (lines.len() / 2).saturating_sub(50);
-        let middle_end = (lines.len() / 2 + 50).min(lines.len());
-        sampled_content.push_str(&format!("MIDDLE (around line {}):\n", lines.len() / 2));
-        let middle: String = lines[middle_start..middle_end].join("\n");
-        sampled_content.push_str(&self.format_line_numbers(&middle, middle_start));
-        sampled_content.push_str("\n\n");
-
-        // End section (last 100 lines)
-        let end_start = lines.len().saturating_sub(100);
-        sampled_content.push_str(&format!(
-            "END (last 100 lines, starting from line {}):\n",
-            end_start + 1
-        ));
-        let end: String = lines[end_start..].join("\n");
-        sampled_content.push_str(&self.format_line_numbers(&end, end_start));
-
-        // Footer with usage info
-        sampled_content.push_str(&format!(
-            "\n\n=== SAMPLING SUMMARY ===\n\
-            Total lines: {}\n\
-            Shown: ~300 lines (beginning, middle, end)\n\
-            Use offset/limit parameters to read specific sections",
-            lines.len()
-        ));
-
-        Ok(sampled_content)
+        Ok(self.format_line_numbers(&content, 0))
     }
 
     fn read_with_range(&self, file_path: &Path, offset: usize, limit: usize) -> Result<String> {
@@ -213,12 +169,8 @@ impl Tool for ReadTool {
     async fn execute(&self, arguments: &str) -> Result<String> {
         let params: ReadParams = serde_json::from_str(arguments)?;
 
-        // Handle both absolute and relative paths
-        let file_path = if Path::new(&params.file_path).is_absolute() {
-            Path::new(&params.file_path).to_path_buf()
-        } else {
-            std::env::current_dir()?.join(&params.file_path)
-        };
+        // Resolve relative to the workspace root and enforce the workspace boundary
+        let file_path = self.workspace.resolve_read(&params.file_path)?;
 
         if !file_path.exists() {
             return Err(anyhow::anyhow!("File does not exist: {}", params.file_path));
@@ -257,8 +209,8 @@ impl Tool for ReadTool {
                 result.push_str(&self.read_with_range(&file_path, 0, limit)?);
             }
             (None, None) => {
-                // Full file read with smart sampling
-                match self.smart_sample_large_file(&file_path) {
+                // Full file read, sampled afterwards when it is large
+                match self.read_full_file(&file_path) {
                     Ok(content) => result.push_str(&content),
                     Err(e) => {
                         return Err(anyhow::anyhow!("Failed to read file: {}", e));
@@ -269,4 +221,8 @@ impl Tool for ReadTool {
 
         Ok(result)
     }
+
+    fn output_processor(&self) -> Option<&dyn OutputProcessor> {
+        Some(&READ_OUTPUT)
+    }
 }
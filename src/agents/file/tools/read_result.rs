@@ -0,0 +1,152 @@
+use crate::agents::file::context_manager::LINE_CHAR_LIMIT;
+use crate::agents::file::result_store;
+use crate::tool::Tool;
+use crate::utils;
+use anyhow::Result;
+use regex::Regex;
+use serde_json::{json, Value};
+
+/// Lines returned when no limit is given
+const DEFAULT_LIMIT: usize = 200;
+
+/// Pages through or searches a full tool output that was truncated in the conversation
+pub struct ReadResultTool;
+
+#[derive(serde::Deserialize)]
+struct ReadResultParams {
+    handle: String,
+    #[serde(default)]
+    offset: Option<usize>,
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    pattern: Option<String>,
+}
+
+impl Default for ReadResultTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReadResultTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn format_line(number: usize, line: &str) -> String {
+        let line = if line.len() > LINE_CHAR_LIMIT {
+            format!(
+                "{}... [TRUNCATED]",
+                utils::truncate_bytes(line, LINE_CHAR_LIMIT)
+            )
+        } else {
+            line.to_string()
+        };
+        format!("{:5}→{}", number, line)
+    }
+}
+
+#[async_trait::async_trait]
+impl Tool for ReadResultTool {
+    fn name(&self) -> &str {
+        "read_result"
+    }
+
+    fn description(&self) -> &str {
+        "Read the full output of an earlier tool call that was truncated. Truncated results name a handle; page through it with offset and limit, or pass a regex pattern to get only the matching lines."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "handle": {
+                    "type": "string",
+                    "description": "Handle named in the truncated result, e.g. 004_grep"
+                },
+                "offset": {
+                    "type": "number",
+                    "description": "Line to start from (0-based). With a pattern, the number of matching lines to skip. Optional."
+                },
+                "limit": {
+                    "type": "number",
+                    "description": "Number of lines to return. Optional, defaults to 200."
+                },
+                "pattern": {
+                    "type": "string",
+                    "description": "Optional regex; only lines matching it are returned, with their line numbers."
+                }
+            },
+            "required": ["handle"]
+        })
+    }
+
+    async fn execute(&self, arguments: &str) -> Result<String> {
+        let params: ReadResultParams = serde_json::from_str(arguments)?;
+        let content = result_store::load(&params.handle)?;
+        let offset = params.offset.unwrap_or(0);
+        let limit = params.limit.unwrap_or(DEFAULT_LIMIT).max(1);
+
+        let lines: Vec<(usize, &str)> = content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .collect();
+        let total_lines = lines.len();
+
+        let (selected, header) = match &params.pattern {
+            Some(pattern) => {
+                let regex =
+                    Regex::new(pattern).map_err(|e| anyhow::anyhow!("Invalid pattern: {}", e))?;
+                let matches: Vec<(usize, &str)> = lines
+                    .into_iter()
+                    .filter(|(_, line)| regex.is_match(line))
+                    .collect();
+                let header = format!(
+                    "=== {}: {} of {} lines match '{}' ===",
+                    params.handle,
+                    matches.len(),
+                    total_lines,
+                    pattern
+                );
+                (matches, header)
+            }
+            None => {
+                let header = format!("=== {}: {} lines ===", params.handle, total_lines);
+                (lines, header)
+            }
+        };
+
+        if selected.is_empty() {
+            return Ok(format!("{}\n\nNo lines to show", header));
+        }
+        if offset >= selected.len() {
+            return Ok(format!(
+                "{}\n\nOffset {} is past the last line ({} lines)",
+                header,
+                offset,
+                selected.len()
+            ));
+        }
+
+        let end = (offset + limit).min(selected.len());
+        let mut result = format!("{}\n\n", header);
+        result.push_str(
+            &selected[offset..end]
+                .iter()
+                .map(|(number, line)| Self::format_line(*number, line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        if end < selected.len() {
+            result.push_str(&format!(
+                "\n\n... {} more lines follow, continue with offset {} ...",
+                selected.len() - end,
+                end
+            ));
+        }
+
+        Ok(result)
+    }
+}
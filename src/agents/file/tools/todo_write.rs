@@ -20,6 +20,12 @@ pub struct TodoWriteArgs {
 
 pub struct TodoWriteTool;
 
+impl Default for TodoWriteTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TodoWriteTool {
     pub fn new() -> Self {
         Self
@@ -49,11 +55,7 @@ impl TodoWriteTool {
 
         // Progress indicator
         if total > 1 {
-            let progress_percent = if total > 0 {
-                (completed * 100) / total
-            } else {
-                0
-            };
+            let progress_percent = (completed * 100).checked_div(total).unwrap_or(0);
             summary.push_str(&format!(
                 "Todo Progress: {}/{}  ({}% complete)\n",
                 completed, total, progress_percent
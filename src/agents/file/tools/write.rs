@@ -1,3 +1,4 @@
+use crate::agents::file::workspace::Workspace;
 use crate::tool::Tool;
 use anyhow::Result;
 use serde_json::{json, Value};
@@ -5,7 +6,9 @@ use std::fs;
 use std::path::Path;
 
 /// Safe file creation with validation
-pub struct WriteTool;
+pub struct WriteTool {
+    workspace: Workspace,
+}
 
 #[derive(serde::Deserialize)]
 struct WriteParams {
@@ -20,8 +23,8 @@ fn default_false() -> bool {
 }
 
 impl WriteTool {
-    pub fn new() -> Self {
-        Self
+    pub fn new(workspace: Workspace) -> Self {
+        Self { workspace }
     }
 
     fn validate_file_path(&self, file_path: &Path) -> Result<()> {
@@ -35,30 +38,6 @@ impl WriteTool {
             }
         }
 
-        // Check for dangerous paths
-        let path_str = file_path.to_string_lossy();
-        let dangerous_patterns = [
-            "/etc/",
-            "/usr/",
-            "/bin/",
-            "/sbin/",
-            "/var/log/",
-            "/.ssh/",
-            "/root/",
-            "/home/",
-            "C:\\Windows\\",
-            "C:\\Program Files\\",
-        ];
-
-        for pattern in &dangerous_patterns {
-            if path_str.contains(pattern) {
-                return Err(anyhow::anyhow!(
-                    "Refusing to write to system directory: {}. For safety, only write to project directories.",
-                    file_path.display()
-                ));
-            }
-        }
-
         Ok(())
     }
 
@@ -187,12 +166,8 @@ impl Tool for WriteTool {
     async fn execute(&self, arguments: &str) -> Result<String> {
         let params: WriteParams = serde_json::from_str(arguments)?;
 
-        // Handle both absolute and relative paths
-        let file_path = if Path::new(&params.file_path).is_absolute() {
-            Path::new(&params.file_path).to_path_buf()
-        } else {
-            std::env::current_dir()?.join(&params.file_path)
-        };
+        // Resolve relative to the workspace root and enforce the workspace boundary
+        let file_path = self.workspace.resolve_write(&params.file_path)?;
 
         // Validate path safety
         self.validate_file_path(&file_path)?;
@@ -224,7 +199,7 @@ impl Tool for WriteTool {
                         result.push_str(&format!("{:3}│ {}\n", i + 1, line));
                     }
                 } else {
-                    result.push_str(&format!("\n\nContent preview (first 10 lines):\n"));
+                    result.push_str("\n\nContent preview (first 10 lines):\n");
                     for (i, line) in final_content.lines().take(10).enumerate() {
                         result.push_str(&format!("{:3}│ {}\n", i + 1, line));
                     }
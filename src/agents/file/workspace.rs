@@ -0,0 +1,153 @@
+use crate::config::WorkspaceSettings;
+use anyhow::Result;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+/// Filesystem boundary enforced by all file tools.
+///
+/// Paths are resolved against the workspace root, canonicalized (resolving symlinks) and must
+/// end up inside the root. Read-only roots may additionally be read and searched, but never
+/// modified.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: PathBuf,
+    read_only_roots: Vec<PathBuf>,
+}
+
+impl Workspace {
+    pub fn new(root: impl AsRef<Path>, read_only_roots: &[PathBuf]) -> Result<Self> {
+        let root = root.as_ref();
+        let root = root
+            .canonicalize()
+            .map_err(|e| anyhow::anyhow!("Invalid workspace root {}: {}", root.display(), e))?;
+
+        let read_only_roots = read_only_roots
+            .iter()
+            .map(|path| {
+                path.canonicalize().map_err(|e| {
+                    anyhow::anyhow!("Invalid read-only root {}: {}", path.display(), e)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            root,
+            read_only_roots,
+        })
+    }
+
+    /// Build the workspace from `[workspace]` settings. The root defaults to the current
+    /// directory.
+    pub fn from_settings(settings: &WorkspaceSettings) -> Result<Self> {
+        let root = match &settings.root {
+            Some(root) => root.clone(),
+            None => env::current_dir()?,
+        };
+        Self::new(root, &settings.read_only_roots)
+    }
+
+    /// Canonical workspace root
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Canonical read-only roots
+    pub fn read_only_roots(&self) -> &[PathBuf] {
+        &self.read_only_roots
+    }
+
+    /// Resolve a path that will only be read. It may live in the workspace or a read-only root.
+    pub fn resolve_read(&self, path: &str) -> Result<PathBuf> {
+        let resolved = self.resolve(path)?;
+
+        if resolved.starts_with(&self.root)
+            || self
+                .read_only_roots
+                .iter()
+                .any(|read_only| resolved.starts_with(read_only))
+        {
+            Ok(resolved)
+        } else {
+            Err(self.outside_error(path, &resolved))
+        }
+    }
+
+    /// Resolve a path that will be created or modified. It must live in the workspace root.
+    pub fn resolve_write(&self, path: &str) -> Result<PathBuf> {
+        let resolved = self.resolve(path)?;
+
+        if resolved.starts_with(&self.root) {
+            Ok(resolved)
+        } else if self
+            .read_only_roots
+            .iter()
+            .any(|read_only| resolved.starts_with(read_only))
+        {
+            Err(anyhow::anyhow!(
+                "Path is in a read-only root and cannot be modified: {} (resolved to {})",
+                path,
+                resolved.display()
+            ))
+        } else {
+            Err(self.outside_error(path, &resolved))
+        }
+    }
+
+    /// Join relative paths onto the root and canonicalize. For paths that don't exist yet, the
+    /// deepest existing ancestor is canonicalized and the remaining plain components appended.
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let candidate = if Path::new(path).is_absolute() {
+            PathBuf::from(path)
+        } else {
+            self.root.join(path)
+        };
+
+        if let Ok(canonical) = candidate.canonicalize() {
+            return Ok(canonical);
+        }
+
+        let (existing, tail) = candidate
+            .ancestors()
+            .skip(1)
+            .find(|ancestor| ancestor.exists())
+            .and_then(|ancestor| {
+                candidate
+                    .strip_prefix(ancestor)
+                    .ok()
+                    .map(|tail| (ancestor, tail))
+            })
+            .ok_or_else(|| anyhow::anyhow!("Invalid path: {}", path))?;
+
+        // `..` or `.` in a path that doesn't exist can't be resolved safely
+        if !tail
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid path: {}. Relative components are not allowed in paths that do not exist yet.",
+                path
+            ));
+        }
+
+        // A link whose target doesn't exist would be followed when the file is created
+        if let Some(first) = tail.components().next() {
+            if existing.join(first).symlink_metadata().is_ok() {
+                return Err(anyhow::anyhow!(
+                    "Invalid path: {}. It goes through a symlink whose target does not exist.",
+                    path
+                ));
+            }
+        }
+
+        Ok(existing.canonicalize()?.join(tail))
+    }
+
+    fn outside_error(&self, path: &str, resolved: &Path) -> anyhow::Error {
+        anyhow::anyhow!(
+            "Access denied: {} (resolved to {}) is outside the workspace root {}",
+            path,
+            resolved.display(),
+            self.root.display()
+        )
+    }
+}
@@ -1,29 +1,13 @@
 use crate::agent::Agent;
-use crate::utils;
-use crate::ClaudeConfig;
+use crate::checkpoint::Checkpoint;
+use crate::compaction::CompactionSettings;
+use crate::conversation::{Callable, Conversation, ConversationLimits, EventHandler};
+use crate::llm::{LlmProvider, Message};
+use crate::usage::UsageTracker;
 use anyhow::Result;
-use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::sync::Arc;
 
-pub struct OrchestratorClaude {
-    config: ClaudeConfig,
-}
-
-impl OrchestratorClaude {
-    pub fn new() -> Result<Self> {
-        let config = ClaudeConfig::new()?;
-        Ok(Self { config })
-    }
-
-    /// Call Claude API with agent chaining (following ra-core pattern)
-    pub async fn call_claude_api(
-        &self,
-        task: &str,
-        agents_json: &[Value],
-        agent_map: &HashMap<String, &dyn Agent>,
-    ) -> Result<String> {
-        let system_prompt =
-            "You are a File Operations Orchestrator Agent that routes file-related tasks to the appropriate FileAgent.
+const SYSTEM_PROMPT: &str = "You are a File Operations Orchestrator Agent that routes file-related tasks to the appropriate FileAgent.
 
 Your job is to:
 1. Analyze incoming tasks to determine if they are file-related
@@ -40,6 +24,7 @@ The FileAgent you can delegate to specializes in:
 - Code analysis and refactoring
 
 For any task involving files, directories, code analysis, or file system operations, delegate to the FileAgent.
+When a task splits into independent sub-tasks, delegate them in the same turn so they run in parallel.
 
 Examples of file-related tasks:
 - 'Find all TypeScript files in the project'
@@ -50,149 +35,93 @@ Examples of file-related tasks:
 - 'Analyze the project structure'
 - 'Create a new file with specific content'";
 
-        let mut messages = vec![json!({
-            "role": "user",
-            "content": task
-        })];
-
-        let mut round = 0;
-        let max_rounds = 100;
-        let mut last_message = String::new();
-
-        loop {
-            round += 1;
-            if round > max_rounds {
-                log::warn!("Orchestrator reached maximum rounds: {}", max_rounds);
-                break;
-            }
-
-            let request_payload = json!({
-                "model": self.config.model,
-                "max_tokens": self.config.max_tokens,
-                "temperature": self.config.temperature,
-                "system": system_prompt,
-                "messages": messages,
-                "tools": agents_json
-            });
-
-            log::debug!("Orchestrator calling Claude API - Round {}", round);
-
-            let response = self
-                .config
-                .client
-                .post(&self.config.api_url)
-                .header("x-api-key", &self.config.api_key)
-                .header("anthropic-version", "2023-06-01")
-                .header("content-type", "application/json")
-                .json(&request_payload)
-                .send()
-                .await?;
-
-            if !response.status().is_success() {
-                let error_text = response.text().await?;
-                return Err(anyhow::anyhow!("Claude API error: {}", error_text));
-            }
-
-            let claude_response: Value = response.json().await?;
-
-            // Store the full Claude response
-            utils::store_claude_message("orchestrator", &claude_response)?;
-
-            // Check for tool calls in the response
-            if let Some(content) = claude_response.get("content") {
-                if let Some(array) = content.as_array() {
-                    let mut continue_conversation = false;
-                    let mut assistant_content_blocks = Vec::new();
-
-                    for content_element in array {
-                        assistant_content_blocks.push(content_element.clone());
-
-                        if let Some(content_type) = content_element.get("type") {
-                            if content_type.as_str() == Some("tool_use") {
-                                if let Some(tool_id) =
-                                    content_element.get("id").and_then(|id| id.as_str())
-                                {
-                                    if let Some(agent_name) =
-                                        content_element.get("name").and_then(|name| name.as_str())
-                                    {
-                                        if let Some(input) = content_element.get("input") {
-                                            log::info!(
-                                                "Orchestrator calling agent: {}",
-                                                agent_name
-                                            );
-
-                                            // Call the agent
-                                            let agent_result = if let Some(&agent) =
-                                                agent_map.get(agent_name)
-                                            {
-                                                match input.get("task").and_then(|t| t.as_str()) {
-                                                    Some(agent_task) => {
-                                                        match agent.execute(agent_task).await {
-                                                            Ok(result) => {
-                                                                log::info!("Agent {} completed successfully", agent_name);
-                                                                result
-                                                            }
-                                                            Err(e) => {
-                                                                log::error!(
-                                                                    "Agent {} failed: {}",
-                                                                    agent_name,
-                                                                    e
-                                                                );
-                                                                format!(
-                                                                    "Agent execution failed: {}",
-                                                                    e
-                                                                )
-                                                            }
-                                                        }
-                                                    }
-                                                    None => "No task provided to agent".to_string(),
-                                                }
-                                            } else {
-                                                format!("Agent not found: {}", agent_name)
-                                            };
-
-                                            // Add assistant message with tool_use
-                                            messages.push(json!({
-                                                "role": "assistant",
-                                                "content": assistant_content_blocks
-                                            }));
-
-                                            // Add agent result as user message
-                                            messages.push(json!({
-                                                "role": "user",
-                                                "content": [{
-                                                    "type": "tool_result",
-                                                    "tool_use_id": tool_id,
-                                                    "content": agent_result
-                                                }]
-                                            }));
-
-                                            continue_conversation = true;
-                                        }
-                                    }
-                                }
-                            } else if content_type.as_str() == Some("text") {
-                                if let Some(text) = content_element.get("text") {
-                                    last_message = text.as_str().unwrap_or("").to_string();
-                                }
-                            }
-                        }
-                    }
-
-                    if continue_conversation {
-                        continue;
-                    }
-
-                    // No tool calls, conversation complete
-                    break;
-                }
-            }
+pub struct OrchestratorClaude {
+    provider: Arc<dyn LlmProvider>,
+    events: Option<EventHandler>,
+    usage: Arc<UsageTracker>,
+    limits: ConversationLimits,
+    compaction: CompactionSettings,
+}
+
+impl OrchestratorClaude {
+    pub fn new(provider: Arc<dyn LlmProvider>) -> Self {
+        Self {
+            provider,
+            events: None,
+            usage: Arc::new(UsageTracker::default()),
+            limits: ConversationLimits::default(),
+            compaction: CompactionSettings::default(),
         }
+    }
+
+    pub fn set_limits(&mut self, limits: ConversationLimits) {
+        self.limits = limits;
+    }
+
+    pub fn set_compaction(&mut self, settings: CompactionSettings) {
+        self.compaction = settings;
+    }
+
+    pub fn set_event_handler(&mut self, handler: EventHandler) {
+        self.events = Some(handler);
+    }
+
+    pub fn set_usage_tracker(&mut self, tracker: Arc<UsageTracker>) {
+        self.usage = tracker;
+    }
+
+    pub fn usage(&self) -> &UsageTracker {
+        &self.usage
+    }
+
+    /// Call Claude API with agent chaining (following ra-core pattern). `history` holds the
+    /// session's earlier messages and is extended with this task's, which are also recorded in
+    /// `checkpoint` if there is one.
+    pub async fn call_claude_api(
+        &self,
+        history: &mut Vec<Message>,
+        task: &str,
+        agents: &[&dyn Agent],
+        checkpoint: Option<&dyn Checkpoint>,
+    ) -> Result<String> {
+        self.conversation(agents, checkpoint)
+            .run_continued(history, task)
+            .await
+    }
 
-        if last_message.trim().is_empty() {
-            Ok("Task completed successfully".to_string())
-        } else {
-            Ok(last_message)
+    /// Continue the unfinished task at the end of `history`
+    pub async fn resume(
+        &self,
+        history: &mut Vec<Message>,
+        agents: &[&dyn Agent],
+        checkpoint: Option<&dyn Checkpoint>,
+    ) -> Result<String> {
+        self.conversation(agents, checkpoint).resume(history).await
+    }
+
+    fn conversation<'a>(
+        &'a self,
+        agents: &[&'a dyn Agent],
+        checkpoint: Option<&'a dyn Checkpoint>,
+    ) -> Conversation<'a> {
+        let callables: Vec<Box<dyn Callable + 'a>> = agents
+            .iter()
+            .map(|agent| Box::new(*agent) as Box<dyn Callable + 'a>)
+            .collect();
+
+        // Sub-tasks delegated in the same turn run concurrently, and their results go back to
+        // Claude together in one user message
+        let mut conversation =
+            Conversation::new(self.provider.as_ref(), "orchestrator", SYSTEM_PROMPT, callables)
+                .with_usage(&self.usage)
+                .with_limits(self.limits.clone())
+                .with_compaction(self.compaction.clone());
+        if let Some(handler) = &self.events {
+            conversation = conversation.with_events(handler.as_ref());
+        }
+        if let Some(checkpoint) = checkpoint {
+            conversation = conversation.with_checkpoint(checkpoint);
         }
+        conversation
     }
 }
@@ -1,26 +1,195 @@
-use crate::agent::{Agent, agents_to_json};
+use crate::agent::Agent;
 use crate::agents::file::FileAgent;
+use crate::checkpoint::{Checkpoint, CheckpointEntry};
+use crate::config::{AgentSettings, Settings};
+use crate::conversation::{self, Callable, EventHandler, ToolCall};
+use crate::llm::{self, ContentBlock, LlmProvider, Message, Role};
+use crate::session::Session;
+use crate::usage::UsageTracker;
+use crate::utils;
+use crate::ClaudeConfig;
 use anyhow::Result;
 use log;
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::sync::Arc;
 
 mod claude;
 use claude::OrchestratorClaude;
 
 pub struct OrchestratorAgent {
     claude: OrchestratorClaude,
+    agents: Vec<Box<dyn Agent>>,
 }
 
 impl OrchestratorAgent {
     pub fn new() -> Result<Self> {
-        let claude = OrchestratorClaude::new()?;
+        Self::from_settings(&Settings::load(&Value::Null)?)
+    }
+
+    pub fn from_settings(settings: &Settings) -> Result<Self> {
+        let config = ClaudeConfig::from_settings(&settings.llm_for("orchestrator"), settings.budget()?)?;
+        let provider = llm::create_provider(&config)?;
+        let agents: Vec<Box<dyn Agent>> = vec![Box::new(FileAgent::from_settings(settings)?)];
+
+        let mut orchestrator = Self::with_agents(provider, agents);
+        orchestrator.apply_settings(&settings.agent("orchestrator"));
+        orchestrator.set_usage_tracker(Arc::new(UsageTracker::new(config.budget)));
+        Ok(orchestrator)
+    }
+
+    /// Build the orchestrator from an explicit provider and set of agents to delegate to. The
+    /// agents share the orchestrator's usage tracker, without a budget.
+    pub fn with_agents(provider: Arc<dyn LlmProvider>, agents: Vec<Box<dyn Agent>>) -> Self {
         log::info!("Orchestrator initialized");
-        Ok(Self { claude })
+        let mut orchestrator = Self {
+            claude: OrchestratorClaude::new(provider),
+            agents,
+        };
+        orchestrator.set_usage_tracker(Arc::new(UsageTracker::default()));
+        orchestrator
+    }
+
+    /// Apply the round limit and compaction, and drop agents that aren't enabled in `tools`
+    pub fn apply_settings(&mut self, settings: &AgentSettings) {
+        self.claude.set_limits(settings.limits());
+        self.claude.set_compaction(settings.compaction.clone());
+        self.agents
+            .retain(|agent| settings.tools.is_enabled(agent.name()));
+    }
+
+    /// Run a task as the next message of a session, continuing its conversation. Messages are
+    /// logged to the session's directory, and every step is checkpointed there as it happens.
+    pub async fn execute_in_session(&self, session: &Session, task: &str) -> Result<String> {
+        let mut history = session.load_history()?;
+        log::info!(
+            "Orchestrator processing task in session {}/{} ({} earlier messages)",
+            session.space(),
+            session.id(),
+            history.len()
+        );
+
+        let checkpoint = session.checkpoint()?;
+        let agent_refs: Vec<&dyn Agent> = self.agents.iter().map(|a| a.as_ref()).collect();
+        utils::with_message_dir(
+            session.messages_dir(),
+            session.scope(self.claude.call_claude_api(
+                &mut history,
+                task,
+                &agent_refs,
+                Some(&checkpoint),
+            )),
+        )
+        .await
+    }
+
+    /// Continue a session's task that was cut off, e.g. by a crash or a failed API call.
+    /// Delegations that were running when it stopped continue from the agent's own checkpoint;
+    /// other calls that were running are marked as interrupted, so the model can check their
+    /// effects before going on.
+    pub async fn resume(&self, session: &Session) -> Result<String> {
+        let mut history = session.load_history()?;
+        log::info!(
+            "Orchestrator resuming session {}/{} ({} messages)",
+            session.space(),
+            session.id(),
+            history.len()
+        );
+
+        let checkpoint = session.checkpoint()?;
+        let agent_refs: Vec<&dyn Agent> = self.agents.iter().map(|a| a.as_ref()).collect();
+        utils::with_message_dir(
+            session.messages_dir(),
+            session.scope(async {
+                self.finish_delegations(session, &mut history, &agent_refs, &checkpoint)
+                    .await?;
+                self.claude
+                    .resume(&mut history, &agent_refs, Some(&checkpoint))
+                    .await
+            }),
+        )
+        .await
+    }
+
+    /// Run the interrupted calls of the last round again when the agent checkpointed its
+    /// conversation for them, so it carries on where it stopped, and record the round's results
+    async fn finish_delegations(
+        &self,
+        session: &Session,
+        history: &mut [Message],
+        agents: &[&dyn Agent],
+        checkpoint: &dyn Checkpoint,
+    ) -> Result<()> {
+        let interrupted: Vec<String> = session
+            .interrupted_calls()?
+            .into_iter()
+            .filter(|id| session.has_delegation(id))
+            .collect();
+        if interrupted.is_empty() {
+            return Ok(());
+        }
+        let Some(position) = history.iter().rposition(|m| m.role == Role::Assistant) else {
+            return Ok(());
+        };
+
+        let calls: Vec<ToolCall> = history[position]
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { id, name, input } if interrupted.contains(id) => {
+                    Some(ToolCall {
+                        tool_use_id: id.clone(),
+                        name: name.clone(),
+                        input: input.clone(),
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+        for call in calls {
+            let Some(agent) = agents.iter().find(|agent| agent.name() == call.name) else {
+                continue;
+            };
+            log::info!("Orchestrator resuming delegation {} to {}", call.tool_use_id, call.name);
+            let (output, failed) = match conversation::with_call_id(
+                call.tool_use_id.clone(),
+                agent.invoke(&call.input),
+            )
+            .await
+            {
+                Ok(output) => (output, false),
+                Err(e) => (format!("Tool execution failed: {}", e), true),
+            };
+
+            // The rebuilt results message follows the calls
+            for block in history[position + 1..]
+                .iter_mut()
+                .flat_map(|message| message.content.iter_mut())
+            {
+                if let ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    is_error,
+                } = block
+                {
+                    if *tool_use_id == call.tool_use_id {
+                        *content = output.clone();
+                        *is_error = failed;
+                    }
+                }
+            }
+        }
+
+        if let Some(results) = history.get(position + 1) {
+            checkpoint.record(&CheckpointEntry::Message {
+                message: results.clone(),
+            })?;
+        }
+        Ok(())
     }
 
-    fn get_agents(&self) -> Vec<Box<dyn Agent>> {
-        vec![Box::new(FileAgent::new().unwrap())]
+    /// Token usage of the orchestrator and its agents
+    pub fn usage(&self) -> &UsageTracker {
+        self.claude.usage()
     }
 }
 
@@ -47,27 +216,31 @@ impl Agent for OrchestratorAgent {
         })
     }
 
+    fn set_event_handler(&mut self, handler: EventHandler) {
+        for agent in &mut self.agents {
+            agent.set_event_handler(handler.clone());
+        }
+        self.claude.set_event_handler(handler);
+    }
+
+    fn set_usage_tracker(&mut self, tracker: Arc<UsageTracker>) {
+        for agent in &mut self.agents {
+            agent.set_usage_tracker(tracker.clone());
+        }
+        self.claude.set_usage_tracker(tracker);
+    }
+
     async fn execute(&self, task: &str) -> Result<String> {
         log::info!("Orchestrator processing task: {}", task);
 
         // Get available agents
-        let agents = self.get_agents();
-        let agent_refs: Vec<&dyn Agent> = agents.iter().map(|a| a.as_ref()).collect();
-
-        // Convert agents to JSON for Claude using helper function
-        let agents_json = agents_to_json(&agent_refs);
-
-        // Create agent lookup map
-        let agent_map: HashMap<String, &dyn Agent> = agent_refs
-            .iter()
-            .map(|agent| (agent.name().to_string(), *agent))
-            .collect();
+        let agent_refs: Vec<&dyn Agent> = self.agents.iter().map(|a| a.as_ref()).collect();
 
         // Use async executor to handle the async call_claude_api
         let response = tokio::task::block_in_place(|| {
             tokio::runtime::Handle::current().block_on(async {
                 self.claude
-                    .call_claude_api(task, &agents_json, &agent_map)
+                    .call_claude_api(&mut Vec::new(), task, &agent_refs, None)
                     .await
             })
         })?;
@@ -0,0 +1,180 @@
+use crate::conversation::{close_calls_with, INTERRUPTED_CALL_RESULT};
+use crate::llm::{Message, Role};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// One step of a conversation, recorded as it happens
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CheckpointEntry {
+    /// A message added to the conversation
+    Message { message: Message },
+    /// A call is about to run
+    CallStarted { tool_use_id: String, name: String },
+    /// A call returned. Its output is part of the results message that ends the round.
+    CallFinished {
+        tool_use_id: String,
+        name: String,
+        is_error: bool,
+    },
+}
+
+/// Durable record of a conversation, written while it runs so it can be resumed after a crash
+pub trait Checkpoint: Send + Sync {
+    fn record(&self, entry: &CheckpointEntry) -> Result<()>;
+}
+
+/// Checkpoint in a JSONL file, one entry per line. Every entry is synced to disk before the
+/// conversation moves on.
+pub struct JsonlCheckpoint {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl JsonlCheckpoint {
+    /// Open a checkpoint for appending, creating it if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open checkpoint {}: {}", path.display(), e))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Read every entry of a checkpoint, none when the file doesn't exist. A last line cut off by
+    /// a crash is skipped.
+    pub fn load(path: &Path) -> Result<Vec<CheckpointEntry>> {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read checkpoint {}: {}", path.display(), e))?;
+
+        let lines: Vec<&str> = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        let mut entries = Vec::with_capacity(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) if i + 1 == lines.len() && !content.ends_with('\n') => {
+                    log::warn!(
+                        "Skipping incomplete last entry of checkpoint {}: {}",
+                        path.display(),
+                        e
+                    );
+                }
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Invalid entry on line {} of checkpoint {}: {}",
+                        i + 1,
+                        path.display(),
+                        e
+                    ))
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+impl Checkpoint for JsonlCheckpoint {
+    fn record(&self, entry: &CheckpointEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+            .map_err(|e| {
+                anyhow::anyhow!("Failed to write checkpoint {}: {}", self.path.display(), e)
+            })
+    }
+}
+
+/// Rebuild the messages of a checkpointed conversation.
+///
+/// Consecutive user messages are merged like the conversation merged them. A round that never
+/// got its results message is closed with one error result per call, saying whether the call
+/// finished, was interrupted while running, or never started.
+pub fn rebuild(entries: &[CheckpointEntry]) -> Vec<Message> {
+    let mut messages: Vec<Message> = Vec::new();
+    // Calls of the current round
+    let mut started: Vec<String> = Vec::new();
+    let mut finished: Vec<(String, bool)> = Vec::new();
+
+    for entry in entries {
+        match entry {
+            CheckpointEntry::Message { message } => {
+                if message.role == Role::Assistant {
+                    close_round(&mut messages, &started, &finished);
+                    started.clear();
+                    finished.clear();
+                }
+                match messages.last_mut() {
+                    Some(last) if last.role == Role::User && message.role == Role::User => {
+                        last.content.extend(message.content.iter().cloned())
+                    }
+                    _ => messages.push(message.clone()),
+                }
+            }
+            CheckpointEntry::CallStarted { tool_use_id, .. } => started.push(tool_use_id.clone()),
+            CheckpointEntry::CallFinished {
+                tool_use_id,
+                is_error,
+                ..
+            } => finished.push((tool_use_id.clone(), *is_error)),
+        }
+    }
+    close_round(&mut messages, &started, &finished);
+
+    messages
+}
+
+/// Calls of the last round that were started but never finished. None once the round's results
+/// were recorded.
+pub fn interrupted_calls(entries: &[CheckpointEntry]) -> Vec<String> {
+    let mut running: Vec<String> = Vec::new();
+    for entry in entries {
+        match entry {
+            CheckpointEntry::Message { .. } => running.clear(),
+            CheckpointEntry::CallStarted { tool_use_id, .. } => running.push(tool_use_id.clone()),
+            CheckpointEntry::CallFinished { tool_use_id, .. } => {
+                running.retain(|running| running != tool_use_id)
+            }
+        }
+    }
+    running
+}
+
+fn close_round(messages: &mut Vec<Message>, started: &[String], finished: &[(String, bool)]) {
+    close_calls_with(messages, |id, name| {
+        if let Some((_, is_error)) = finished.iter().find(|(finished, _)| finished == id) {
+            let outcome = if *is_error { "failed" } else { "finished" };
+            format!(
+                "{}: {}, but its output was lost when the task stopped. Check its effects before relying on it or running it again.",
+                name, outcome
+            )
+        } else if started.iter().any(|started| started == id) {
+            format!("{}: {}", name, INTERRUPTED_CALL_RESULT)
+        } else {
+            format!(
+                "{}: not run, because the task stopped before it started.",
+                name
+            )
+        }
+    });
+}
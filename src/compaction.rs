@@ -0,0 +1,262 @@
+use crate::agents::file::result_store;
+use crate::llm::{ContentBlock, LlmRequest, Message, Role};
+use crate::tokens;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Tool whose latest call holds the agent's todo list, which compaction keeps
+pub const TODO_TOOL: &str = "todo_write";
+
+/// Results shorter than this are left alone, stubs included
+const MIN_ELIDED_CHARS: usize = 200;
+
+/// Characters of a call's argument shown in its stub
+const DESCRIPTION_CHARS: usize = 80;
+
+/// Tokens of each call result shown to the summarizing model
+const TRANSCRIPT_RESULT_TOKENS: usize = 500;
+
+/// Starts the summary added to the first message
+const SUMMARY_HEADER: &str =
+    "Summary of the conversation so far, which was compacted to save context:";
+
+const SUMMARY_SYSTEM_PROMPT: &str = "You summarize the progress of an agent working on a task, so it can continue without the full conversation. Keep every fact it still needs: files read or changed and how, decisions made, errors hit and what fixed them, and what is left to do. Be concise and leave out tool output that no longer matters.";
+
+/// `[agents.<name>.compaction]`: how a conversation is shrunk when it nears the context window
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompactionSettings {
+    pub enabled: bool,
+    /// Compact once the estimated request passes this many tokens
+    pub threshold_tokens: usize,
+    /// Most recent rounds kept exactly as they are
+    pub keep_rounds: usize,
+    /// Summarize the older rounds with a model call when eliding their results isn't enough
+    pub summarize: bool,
+}
+
+impl Default for CompactionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold_tokens: 150_000,
+            keep_rounds: 4,
+            summarize: false,
+        }
+    }
+}
+
+/// Replace the results of calls before the last `keep_rounds` rounds with short stubs, e.g.
+/// "read of src/x.rs, 400 lines, elided". The result of the latest todo_write call is kept.
+/// Returns how many results were elided.
+pub fn elide_old_results(messages: &mut [Message], keep_rounds: usize) -> usize {
+    let split = kept_from(messages, keep_rounds);
+    let calls: HashMap<String, (String, Value)> = messages
+        .iter()
+        .flat_map(|message| &message.content)
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse { id, name, input } => {
+                Some((id.clone(), (name.clone(), input.clone())))
+            }
+            _ => None,
+        })
+        .collect();
+    let todos = latest_call(messages, TODO_TOOL);
+
+    let mut elided = 0;
+    for message in &mut messages[..split] {
+        for block in &mut message.content {
+            let ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                ..
+            } = block
+            else {
+                continue;
+            };
+            if content.chars().count() < MIN_ELIDED_CHARS || todos.as_ref() == Some(tool_use_id) {
+                continue;
+            }
+
+            let description = match calls.get(tool_use_id) {
+                Some((name, input)) => describe(name, input),
+                None => "call".to_string(),
+            };
+            // A cut output already has its full version stored
+            let lines = content.lines().count();
+            *content = match result_store::handle_in(content) {
+                Some(handle) => format!(
+                    "{}, {} lines, elided to save context. Its full output is stored as {}. Use read_result with handle \"{}\" if you still need it.",
+                    description, lines, handle, handle
+                ),
+                None => format!(
+                    "{}, {} lines, elided to save context. Run it again if you still need the output.",
+                    description, lines
+                ),
+            };
+            elided += 1;
+        }
+    }
+    elided
+}
+
+/// Request asking a model to summarize everything before the last `keep_rounds` rounds, or
+/// None when there's nothing to summarize
+pub fn summary_request(messages: &[Message], keep_rounds: usize) -> Option<LlmRequest> {
+    let split = kept_from(messages, keep_rounds);
+    if split <= 1 {
+        return None;
+    }
+
+    let calls: HashMap<&str, &str> = messages[..split]
+        .iter()
+        .flat_map(|message| &message.content)
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse { id, name, .. } => Some((id.as_str(), name.as_str())),
+            _ => None,
+        })
+        .collect();
+
+    let mut transcript = String::new();
+    for message in &messages[..split] {
+        for block in &message.content {
+            let line = match (message.role, block) {
+                (Role::User, ContentBlock::Text { text }) => format!("User: {}", text),
+                (Role::Assistant, ContentBlock::Text { text }) => format!("Agent: {}", text),
+                (_, ContentBlock::ToolUse { name, input, .. }) => {
+                    format!("Agent called {}", describe(name, input))
+                }
+                (
+                    _,
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        is_error,
+                    },
+                ) => {
+                    let name = calls.get(tool_use_id.as_str()).copied().unwrap_or("call");
+                    let status = if *is_error { "error" } else { "result" };
+                    let shown = tokens::prefix(content, TRANSCRIPT_RESULT_TOKENS);
+                    let cut = if shown.len() < content.len() {
+                        "\n..."
+                    } else {
+                        ""
+                    };
+                    format!("{} {}:\n{}{}", name, status, shown, cut)
+                }
+            };
+            transcript.push_str(&line);
+            transcript.push_str("\n\n");
+        }
+    }
+
+    Some(LlmRequest {
+        system: SUMMARY_SYSTEM_PROMPT.to_string(),
+        messages: vec![Message::user_text(&format!(
+            "Summarize this conversation:\n\n{}",
+            transcript.trim_end()
+        ))],
+        tools: Vec::new(),
+    })
+}
+
+/// Replace everything between the first message and the last `keep_rounds` rounds with
+/// `summary`, added to the first message next to the task. A todo list that would be dropped
+/// is added to the summary as it was.
+pub fn replace_with_summary(messages: &mut Vec<Message>, keep_rounds: usize, summary: &str) {
+    let split = kept_from(messages, keep_rounds);
+    if split <= 1 || messages[0].role != Role::User {
+        return;
+    }
+
+    let mut text = format!("{}\n{}", SUMMARY_HEADER, summary.trim());
+    if latest_call(&messages[split..], TODO_TOOL).is_none() {
+        if let Some(todos) = latest_call(&messages[..split], TODO_TOOL)
+            .and_then(|id| result_of(&messages[..split], &id))
+        {
+            text.push_str(&format!("\n\nCurrent todo list:\n{}", todos));
+        }
+    }
+
+    let first = &mut messages[0];
+    // An earlier summary is part of the new one
+    first.content.retain(
+        |block| !matches!(block, ContentBlock::Text { text } if text.starts_with(SUMMARY_HEADER)),
+    );
+    first.content.push(ContentBlock::Text { text });
+    messages.drain(1..split);
+}
+
+/// Index of the first message kept as it is: the assistant message starting the last
+/// `keep_rounds` rounds, or 0 when there are no more rounds than that
+fn kept_from(messages: &[Message], keep_rounds: usize) -> usize {
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| message.role == Role::Assistant)
+        .map(|(i, _)| i)
+        .rev()
+        .nth(keep_rounds.saturating_sub(1))
+        .unwrap_or(0)
+}
+
+/// ID of the latest call to `name`
+fn latest_call(messages: &[Message], name: &str) -> Option<String> {
+    messages
+        .iter()
+        .rev()
+        .flat_map(|message| message.content.iter().rev())
+        .find_map(|block| match block {
+            ContentBlock::ToolUse {
+                id, name: called, ..
+            } if called == name => Some(id.clone()),
+            _ => None,
+        })
+}
+
+fn result_of(messages: &[Message], tool_use_id: &str) -> Option<String> {
+    messages
+        .iter()
+        .flat_map(|message| &message.content)
+        .find_map(|block| match block {
+            ContentBlock::ToolResult {
+                tool_use_id: id,
+                content,
+                ..
+            } if id == tool_use_id => Some(content.clone()),
+            _ => None,
+        })
+}
+
+/// Short description of a call from its main argument, e.g. "read of src/x.rs"
+fn describe(name: &str, input: &Value) -> String {
+    let field = |key: &str| input.get(key).and_then(Value::as_str).map(clip);
+
+    if let Some(path) = field("file_path") {
+        format!("{} of {}", name, path)
+    } else if let Some(command) = field("command") {
+        format!("{} `{}`", name, command)
+    } else if let Some(pattern) = field("pattern") {
+        match field("path") {
+            Some(path) => format!("{} for '{}' in {}", name, pattern, path),
+            None => format!("{} for '{}'", name, pattern),
+        }
+    } else if let Some(path) = field("path") {
+        format!("{} of {}", name, path)
+    } else if let Some(task) = field("task") {
+        format!("{} task '{}'", name, task)
+    } else {
+        name.to_string()
+    }
+}
+
+/// First line of `text`, cut to `DESCRIPTION_CHARS`
+fn clip(text: &str) -> String {
+    let line = text.lines().next().unwrap_or("");
+    match line.char_indices().nth(DESCRIPTION_CHARS) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None if line.len() < text.trim_end().len() => format!("{}...", line),
+        None => line.to_string(),
+    }
+}
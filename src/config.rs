@@ -0,0 +1,869 @@
+use crate::agents::file::context_manager::ContextLimits;
+use crate::agents::file::sandbox::SandboxConfig;
+use crate::compaction::CompactionSettings;
+use crate::conversation::ConversationLimits;
+use crate::llm::ProviderKind;
+use crate::retry::RetryConfig;
+use crate::usage::{BudgetConfig, Pricing};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Settings file looked up in the workspace root
+pub const PROJECT_CONFIG_FILE: &str = "file-agent.toml";
+
+/// Agents that can be configured, with the tools each of them can enable or disable
+pub const AGENTS: &[(&str, &[&str])] = &[
+    ("orchestrator", &["file_agent"]),
+    ("file_agent", crate::agents::file::agent::TOOL_NAMES),
+];
+
+/// Environment variables that override a setting, with the kind of value they hold
+const ENV_SETTINGS: &[(&str, &str, EnvKind)] = &[
+    ("LLM_PROVIDER", "llm.provider", EnvKind::Text),
+    ("CLAUDE_MAX_TOKENS", "llm.max_tokens", EnvKind::Integer),
+    ("CLAUDE_TEMPERATURE", "llm.temperature", EnvKind::Number),
+    ("CLAUDE_TIMEOUT", "llm.timeout_seconds", EnvKind::Integer),
+    ("CLAUDE_STREAM", "llm.stream", EnvKind::Flag),
+    ("CLAUDE_PROMPT_CACHING", "llm.prompt_caching", EnvKind::Flag),
+    (
+        "CLAUDE_MAX_RETRIES",
+        "llm.retry.max_retries",
+        EnvKind::Integer,
+    ),
+    (
+        "CLAUDE_RETRY_BASE_DELAY_MS",
+        "llm.retry.base_delay_ms",
+        EnvKind::Integer,
+    ),
+    (
+        "CLAUDE_RETRY_MAX_DELAY_MS",
+        "llm.retry.max_delay_ms",
+        EnvKind::Integer,
+    ),
+    (
+        "ORCHESTRATOR_MODEL",
+        "agents.orchestrator.model",
+        EnvKind::Text,
+    ),
+    ("FILE_AGENT_MODEL", "agents.file_agent.model", EnvKind::Text),
+    (
+        "FILE_AGENT_ESCALATION_MODEL",
+        "agents.file_agent.escalation.model",
+        EnvKind::Text,
+    ),
+    ("FILE_AGENT_SESSIONS_DIR", "session.dir", EnvKind::Text),
+    ("FILE_AGENT_WORKSPACE_ROOT", "workspace.root", EnvKind::Text),
+    (
+        "FILE_AGENT_READ_ONLY_ROOTS",
+        "workspace.read_only_roots",
+        EnvKind::Paths,
+    ),
+    ("FILE_AGENT_POLICY", "workspace.policy", EnvKind::Text),
+    ("FILE_AGENT_SANDBOX", "sandbox.level", EnvKind::Text),
+    (
+        "FILE_AGENT_SANDBOX_CPU_SECONDS",
+        "sandbox.limits.cpu_seconds",
+        EnvKind::Integer,
+    ),
+    (
+        "FILE_AGENT_SANDBOX_MEMORY_MB",
+        "sandbox.limits.memory_mb",
+        EnvKind::Integer,
+    ),
+    (
+        "FILE_AGENT_SANDBOX_FILE_SIZE_MB",
+        "sandbox.limits.file_size_mb",
+        EnvKind::Integer,
+    ),
+    (
+        "FILE_AGENT_SANDBOX_MAX_PROCESSES",
+        "sandbox.limits.max_processes",
+        EnvKind::Integer,
+    ),
+    ("TASK_MAX_TOKENS", "budget.max_tokens", EnvKind::Integer),
+    ("TASK_MAX_COST_USD", "budget.max_cost_usd", EnvKind::Number),
+    (
+        "LLM_INPUT_PRICE_PER_MTOK",
+        "budget.input_price_per_mtok",
+        EnvKind::Number,
+    ),
+    (
+        "LLM_OUTPUT_PRICE_PER_MTOK",
+        "budget.output_price_per_mtok",
+        EnvKind::Number,
+    ),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum EnvKind {
+    Text,
+    Integer,
+    Number,
+    Flag,
+    /// PATH-style list of directories
+    Paths,
+}
+
+/// Agent settings, merged from these layers (later ones win):
+///
+/// 1. built-in defaults
+/// 2. `$XDG_CONFIG_HOME/file-agent/config.toml` (default `~/.config/file-agent/config.toml`)
+/// 3. `file-agent.toml` in the workspace root, or the file named by `FILE_AGENT_CONFIG`.
+///    The root is the one set by `FILE_AGENT_WORKSPACE_ROOT` or the user file, else the
+///    current directory. A file inside the workspace can't set `[workspace]`, `[sandbox]`,
+///    `[session]` or `llm.api_url`.
+/// 4. environment variables such as `CLAUDE_MODEL` or `TASK_MAX_TOKENS`
+/// 5. the `config` object of the Raworc message context
+///
+/// API keys are only read from the environment.
+///
+/// ```toml
+/// [llm]
+/// temperature = 0.2
+///
+/// [budget]
+/// max_cost_usd = 2.0
+///
+/// [workspace]
+/// root = "/srv/project"
+/// read_only_roots = ["/usr/share/doc"]
+/// policy = "/etc/file-agent/policy.toml"
+///
+/// [sandbox]
+/// level = "workspace"
+///
+/// [sandbox.limits]
+/// memory_mb = 4096
+///
+/// [agents.orchestrator]
+/// model = "claude-3-5-haiku-latest"
+/// max_rounds = 20
+///
+/// [agents.file_agent.context.lines]
+/// read = 500
+///
+/// # Rounds after an edit use a stronger model
+/// [[agents.file_agent.phases]]
+/// tools = ["edit", "multi_edit", "write"]
+/// model = "claude-opus-4-1-20250805"
+///
+/// # Run the task again with this model if the first attempt runs out of rounds or is refused
+/// [agents.file_agent.escalation]
+/// model = "claude-opus-4-1-20250805"
+///
+/// [agents.file_agent.tools]
+/// disabled = ["bash", "bash_kill"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub llm: LlmSettings,
+    pub budget: BudgetSettings,
+    pub session: SessionSettings,
+    pub workspace: WorkspaceSettings,
+    pub sandbox: SandboxConfig,
+    /// Overrides for one agent, keyed by agent name
+    pub agents: BTreeMap<String, AgentSettings>,
+}
+
+/// `[llm]` settings shared by every agent
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlmSettings {
+    pub provider: ProviderKind,
+    /// Defaults to the provider's public API
+    pub api_url: Option<String>,
+    /// Defaults to the provider's default model
+    pub model: Option<String>,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    pub timeout_seconds: u64,
+    pub stream: bool,
+    pub prompt_caching: bool,
+    pub retry: RetryConfig,
+}
+
+impl Default for LlmSettings {
+    fn default() -> Self {
+        Self {
+            provider: ProviderKind::Anthropic,
+            api_url: None,
+            model: None,
+            max_tokens: 8192,
+            temperature: 0.7,
+            timeout_seconds: 300,
+            stream: false,
+            prompt_caching: true,
+            retry: RetryConfig::default(),
+        }
+    }
+}
+
+/// `[budget]` settings for a whole task
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BudgetSettings {
+    pub max_tokens: Option<u64>,
+    pub max_cost_usd: Option<f64>,
+    /// USD per million input tokens, for models without built-in pricing
+    pub input_price_per_mtok: Option<f64>,
+    /// USD per million output tokens, for models without built-in pricing
+    pub output_price_per_mtok: Option<f64>,
+}
+
+/// `[session]` settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionSettings {
+    /// Holds one directory per space and session. Defaults to `sessions` in the user state
+    /// dir, outside the workspace the agent can write to.
+    pub dir: PathBuf,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        let dir = user_state_dir().unwrap_or_else(|| env::temp_dir().join("file-agent"));
+        Self {
+            dir: dir.join("sessions"),
+        }
+    }
+}
+
+/// `[workspace]` settings: where the file tools may read and write
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceSettings {
+    /// Defaults to the current directory
+    pub root: Option<PathBuf>,
+    /// Directories that may be read and searched, but never modified
+    pub read_only_roots: Vec<PathBuf>,
+    /// Permission policy file outside the workspace. Defaults to `policy.toml` in the user
+    /// config dir or a policy file next to `raworc.json`, if any.
+    pub policy: Option<PathBuf>,
+}
+
+/// `[agents.<name>]` settings. Unset values fall back to `[llm]` and the built-in defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentSettings {
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub max_rounds: Option<usize>,
+    pub context: ContextLimits,
+    pub compaction: CompactionSettings,
+    pub tools: ToolSettings,
+    /// Only supported by the file agent
+    pub phases: Vec<PhaseSettings>,
+    /// Only supported by the file agent
+    pub escalation: Option<EscalationSettings>,
+}
+
+/// `[[agents.<name>.phases]]`: model for the rounds that follow a call to any of `tools`.
+/// Unset values fall back to the agent's settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PhaseSettings {
+    pub tools: Vec<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+/// `[agents.<name>.escalation]`: model that runs the task again when the first attempt runs
+/// out of rounds or is refused. Errors from the provider itself are not retried this way.
+/// Unset values fall back to the agent's settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EscalationSettings {
+    pub model: String,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+/// Which tools an agent offers the model. Agents count as tools of the orchestrator.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolSettings {
+    /// Only these tools, when set
+    pub enabled: Option<Vec<String>>,
+    pub disabled: Vec<String>,
+}
+
+impl ToolSettings {
+    pub fn is_enabled(&self, tool: &str) -> bool {
+        let enabled = match &self.enabled {
+            Some(enabled) => enabled.iter().any(|name| name == tool),
+            None => true,
+        };
+        enabled && !self.disabled.iter().any(|name| name == tool)
+    }
+}
+
+impl AgentSettings {
+    pub fn limits(&self) -> ConversationLimits {
+        let mut limits = ConversationLimits::default();
+        if let Some(max_rounds) = self.max_rounds {
+            limits.max_rounds = max_rounds;
+        }
+        limits
+    }
+}
+
+impl Settings {
+    /// Load every layer. `context` is the Raworc message context; its `config` object, when
+    /// present, overrides everything else.
+    pub fn load(context: &Value) -> Result<Self> {
+        let mut layers = Vec::new();
+        if let Some(path) = user_config_file() {
+            layers.push(Layer::file(&path)?);
+        }
+
+        // The project file is looked up in the root chosen by the layers the agent can't edit
+        let root = match env::var("FILE_AGENT_WORKSPACE_ROOT") {
+            Ok(root) if !root.trim().is_empty() => PathBuf::from(root),
+            _ => match merged(&layers).pointer("/workspace/root") {
+                Some(Value::String(root)) => PathBuf::from(root),
+                _ => env::current_dir()?,
+            },
+        };
+        if let Some(path) = project_config_file(&root)? {
+            if is_inside(&path, &root) {
+                layers.push(Layer::workspace_file(&path)?);
+            } else {
+                layers.push(Layer::file(&path)?);
+            }
+        }
+
+        // Which model variables apply depends on the provider chosen so far
+        let provider = match merged(&layers).pointer("/llm/provider") {
+            Some(provider) => serde_json::from_value(provider.clone())?,
+            None => LlmSettings::default().provider,
+        };
+        layers.push(Layer::env(env::vars(), provider)?);
+        layers.extend(Layer::context(context)?);
+
+        let settings = Self::from_layers(&layers)?;
+        let sources: Vec<&str> = layers.iter().map(|layer| layer.source.as_str()).collect();
+        log::info!("Loaded settings from defaults, {}", sources.join(", "));
+        Ok(settings)
+    }
+
+    /// Merge layers over the built-in defaults, lowest precedence first
+    pub fn from_layers(layers: &[Layer]) -> Result<Self> {
+        let settings: Settings = serde_json::from_value(merged(layers))
+            .map_err(|e| anyhow::anyhow!("Invalid settings: {}", e))?;
+        settings
+            .check()
+            .and_then(|_| settings.budget().map(|_| ()))
+            .map_err(|e| anyhow::anyhow!("Invalid settings: {}", e))?;
+        Ok(settings)
+    }
+
+    /// Settings of one agent, or the defaults when it has none
+    pub fn agent(&self, name: &str) -> AgentSettings {
+        self.agents.get(name).cloned().unwrap_or_default()
+    }
+
+    /// `[llm]` with the agent's overrides applied
+    pub fn llm_for(&self, agent: &str) -> LlmSettings {
+        let overrides = self.agent(agent);
+        with_overrides(
+            self.llm.clone(),
+            overrides.model.as_deref(),
+            overrides.temperature,
+            overrides.max_tokens,
+        )
+    }
+
+    /// The agent's model settings with a phase's overrides applied
+    pub fn llm_for_phase(&self, agent: &str, phase: &PhaseSettings) -> LlmSettings {
+        with_overrides(
+            self.llm_for(agent),
+            phase.model.as_deref(),
+            phase.temperature,
+            phase.max_tokens,
+        )
+    }
+
+    /// The agent's model settings with its escalation model applied
+    pub fn llm_for_escalation(&self, agent: &str, escalation: &EscalationSettings) -> LlmSettings {
+        with_overrides(
+            self.llm_for(agent),
+            Some(&escalation.model),
+            escalation.temperature,
+            escalation.max_tokens,
+        )
+    }
+
+    pub fn budget(&self) -> Result<BudgetConfig> {
+        let budget = &self.budget;
+        let pricing = match (budget.input_price_per_mtok, budget.output_price_per_mtok) {
+            (Some(input), Some(output)) => Some(Pricing::new(input, output)),
+            (None, None) => None,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "budget.input_price_per_mtok and budget.output_price_per_mtok (LLM_INPUT_PRICE_PER_MTOK and LLM_OUTPUT_PRICE_PER_MTOK) must be set together"
+                ))
+            }
+        };
+
+        Ok(BudgetConfig {
+            max_tokens: budget.max_tokens,
+            max_cost_usd: budget.max_cost_usd,
+            pricing,
+        })
+    }
+
+    /// Range and name checks that hold for every layer on its own
+    fn check(&self) -> Result<()> {
+        let llm = &self.llm;
+        check_model("llm.model", llm.model.as_deref())?;
+        check_positive("llm.max_tokens", llm.max_tokens as f64)?;
+        check_temperature("llm.temperature", llm.temperature)?;
+        check_positive("llm.timeout_seconds", llm.timeout_seconds as f64)?;
+
+        if let Some(max_tokens) = self.budget.max_tokens {
+            check_positive("budget.max_tokens", max_tokens as f64)?;
+        }
+        for (key, value) in [
+            ("budget.max_cost_usd", self.budget.max_cost_usd),
+            (
+                "budget.input_price_per_mtok",
+                self.budget.input_price_per_mtok,
+            ),
+            (
+                "budget.output_price_per_mtok",
+                self.budget.output_price_per_mtok,
+            ),
+        ] {
+            if let Some(value) = value {
+                if !value.is_finite() || value < 0.0 {
+                    return Err(anyhow::anyhow!(
+                        "{} must be zero or more, got {}",
+                        key,
+                        value
+                    ));
+                }
+            }
+        }
+
+        for (name, agent) in &self.agents {
+            let Some((_, tools)) = AGENTS.iter().find(|(known, _)| known == name) else {
+                let known: Vec<&str> = AGENTS.iter().map(|(known, _)| *known).collect();
+                return Err(anyhow::anyhow!(
+                    "Unknown agent '{}' in agents. Expected one of: {}",
+                    name,
+                    known.join(", ")
+                ));
+            };
+            let key = |field: &str| format!("agents.{}.{}", name, field);
+
+            check_model(&key("model"), agent.model.as_deref())?;
+            if let Some(temperature) = agent.temperature {
+                check_temperature(&key("temperature"), temperature)?;
+            }
+            if let Some(max_tokens) = agent.max_tokens {
+                check_positive(&key("max_tokens"), max_tokens as f64)?;
+            }
+            if let Some(max_rounds) = agent.max_rounds {
+                check_positive(&key("max_rounds"), max_rounds as f64)?;
+            }
+
+            let context = &agent.context;
+            let limited: Vec<String> = context.lines.keys().cloned().collect();
+            check_tools(&key("context.lines"), &limited, tools)?;
+            for (tool, lines) in &context.lines {
+                check_positive(&key(&format!("context.lines.{}", tool)), *lines as f64)?;
+            }
+            for (field, value) in [
+                ("context.output_bytes", context.output_bytes),
+                ("context.context_tokens", context.context_tokens),
+                ("context.turn_tokens", context.turn_tokens),
+            ] {
+                check_positive(&key(field), value as f64)?;
+            }
+            check_positive(
+                &key("compaction.threshold_tokens"),
+                agent.compaction.threshold_tokens as f64,
+            )?;
+            check_positive(
+                &key("compaction.keep_rounds"),
+                agent.compaction.keep_rounds as f64,
+            )?;
+
+            for (i, phase) in agent.phases.iter().enumerate() {
+                let key = |field: &str| key(&format!("phases[{}].{}", i, field));
+                if phase.tools.is_empty() {
+                    return Err(anyhow::anyhow!("{} must not be empty", key("tools")));
+                }
+                check_tools(&key("tools"), &phase.tools, tools)?;
+                check_model(&key("model"), phase.model.as_deref())?;
+                if let Some(temperature) = phase.temperature {
+                    check_temperature(&key("temperature"), temperature)?;
+                }
+                if let Some(max_tokens) = phase.max_tokens {
+                    check_positive(&key("max_tokens"), max_tokens as f64)?;
+                }
+            }
+            if let Some(escalation) = &agent.escalation {
+                check_model(&key("escalation.model"), Some(&escalation.model))?;
+                if let Some(temperature) = escalation.temperature {
+                    check_temperature(&key("escalation.temperature"), temperature)?;
+                }
+                if let Some(max_tokens) = escalation.max_tokens {
+                    check_positive(&key("escalation.max_tokens"), max_tokens as f64)?;
+                }
+            }
+            if name != "file_agent" {
+                if !agent.phases.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "{} is only supported for file_agent",
+                        key("phases")
+                    ));
+                }
+                if agent.escalation.is_some() {
+                    return Err(anyhow::anyhow!(
+                        "{} is only supported for file_agent",
+                        key("escalation")
+                    ));
+                }
+            }
+
+            if let Some(enabled) = &agent.tools.enabled {
+                check_tools(&key("tools.enabled"), enabled, tools)?;
+            }
+            check_tools(&key("tools.disabled"), &agent.tools.disabled, tools)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn with_overrides(
+    mut llm: LlmSettings,
+    model: Option<&str>,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+) -> LlmSettings {
+    if let Some(model) = model {
+        llm.model = Some(model.to_string());
+    }
+    if let Some(temperature) = temperature {
+        llm.temperature = temperature;
+    }
+    if let Some(max_tokens) = max_tokens {
+        llm.max_tokens = max_tokens;
+    }
+    llm
+}
+
+fn check_model(key: &str, model: Option<&str>) -> Result<()> {
+    match model {
+        Some(model) if model.trim().is_empty() => Err(anyhow::anyhow!("{} must not be empty", key)),
+        _ => Ok(()),
+    }
+}
+
+fn check_tools(key: &str, names: &[String], known: &[&str]) -> Result<()> {
+    match names.iter().find(|name| !known.contains(&name.as_str())) {
+        Some(name) => Err(anyhow::anyhow!(
+            "Unknown tool '{}' in {}. Expected one of: {}",
+            name,
+            key,
+            known.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+fn check_positive(key: &str, value: f64) -> Result<()> {
+    if value > 0.0 {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "{} must be greater than 0, got {}",
+            key,
+            value
+        ))
+    }
+}
+
+fn check_temperature(key: &str, temperature: f32) -> Result<()> {
+    if (0.0..=2.0).contains(&temperature) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "{} must be between 0 and 2, got {}",
+            key,
+            temperature
+        ))
+    }
+}
+
+/// One source of settings, held as a JSON tree in the shape of [`Settings`]
+#[derive(Debug, Clone)]
+pub struct Layer {
+    /// Where the settings came from, for errors and logs
+    pub source: String,
+    value: Value,
+}
+
+impl Layer {
+    /// Check that `value` is valid on its own, so errors name the layer they come from
+    fn new(source: String, value: Value) -> Result<Self> {
+        let settings: Settings = serde_json::from_value(value.clone())
+            .map_err(|e| anyhow::anyhow!("Invalid settings in {}: {}", source, e))?;
+        settings
+            .check()
+            .map_err(|e| anyhow::anyhow!("Invalid settings in {}: {}", source, e))?;
+        Ok(Self { source, value })
+    }
+
+    /// Settings in TOML
+    pub fn toml(source: &str, content: &str) -> Result<Self> {
+        // Parsing straight into `Settings` first gives errors with the offending key
+        toml::from_str::<Settings>(content)
+            .map_err(|e| anyhow::anyhow!("Invalid settings in {}: {}", source, e))?;
+        let value: Value = toml::from_str(content)
+            .map_err(|e| anyhow::anyhow!("Invalid settings in {}: {}", source, e))?;
+        Self::new(source.to_string(), value)
+    }
+
+    pub fn file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!("Failed to read settings file {}: {}", path.display(), e)
+        })?;
+        Self::toml(&path.display().to_string(), &content)
+    }
+
+    /// Settings from a file inside the workspace, which the agent can edit. The keys in
+    /// [`OPERATOR_KEYS`] must not be set there.
+    pub fn workspace_file(path: &Path) -> Result<Self> {
+        let layer = Self::file(path)?;
+        if let Some(key) = OPERATOR_KEYS
+            .iter()
+            .find(|key| layer.value.pointer(&format!("/{}", key.replace('.', "/"))).is_some())
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid settings in {}: {} can only be set outside the workspace, \
+                 in the user settings file, FILE_AGENT_CONFIG or the environment",
+                layer.source,
+                key
+            ));
+        }
+        Ok(layer)
+    }
+
+    /// Settings from environment variables. `provider` is the provider chosen by earlier
+    /// layers; `LLM_PROVIDER` overrides it and picks between the `CLAUDE_*` and `OPENAI_*`
+    /// URL and model variables.
+    pub fn env<I>(vars: I, provider: ProviderKind) -> Result<Self>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let vars: BTreeMap<String, String> = vars
+            .into_iter()
+            .filter(|(_, value)| !value.trim().is_empty())
+            .collect();
+
+        let provider = match vars.get("LLM_PROVIDER") {
+            Some(provider) => provider.parse()?,
+            None => provider,
+        };
+        let (url_var, model_var) = match provider {
+            ProviderKind::OpenAi => ("OPENAI_API_URL", "OPENAI_MODEL"),
+            _ => ("CLAUDE_API_URL", "CLAUDE_MODEL"),
+        };
+
+        let mut value = Value::Object(Map::new());
+        let provider_settings = [
+            (url_var, "llm.api_url", EnvKind::Text),
+            (model_var, "llm.model", EnvKind::Text),
+        ];
+        for (name, key, kind) in ENV_SETTINGS.iter().chain(&provider_settings) {
+            if let Some(raw) = vars.get(*name) {
+                let parsed = parse_env(name, raw, *kind)?;
+                merge(&mut value, nested(key, parsed));
+            }
+        }
+
+        Self::new("environment".to_string(), value)
+    }
+
+    /// The `config` object of a Raworc message context, if it has one. Only the keys in
+    /// [`CONTEXT_KEYS`] may be set there.
+    pub fn context(context: &Value) -> Result<Option<Self>> {
+        match context.get("config") {
+            None | Some(Value::Null) => Ok(None),
+            Some(config @ Value::Object(_)) => {
+                check_context_keys("", "", config).map_err(|e| {
+                    anyhow::anyhow!("Invalid settings in Raworc context: {}", e)
+                })?;
+                Self::new("Raworc context".to_string(), config.clone()).map(Some)
+            }
+            Some(other) => Err(anyhow::anyhow!(
+                "Invalid settings in Raworc context: config must be an object, got {}",
+                other
+            )),
+        }
+    }
+}
+
+/// Settings a file inside the workspace may not change, since the agent could rewrite them to
+/// leave the workspace, lift the sandbox or send the API key elsewhere
+const OPERATOR_KEYS: &[&str] = &["workspace", "sandbox", "session", "llm.api_url"];
+
+/// Settings a message context may change. Anything else, such as `llm.api_url` (which receives
+/// the API key) or the workspace and sandbox, stays under the operator's control.
+const CONTEXT_KEYS: &[&str] = &[
+    "llm.model",
+    "llm.max_tokens",
+    "llm.temperature",
+    "budget.max_tokens",
+    "budget.max_cost_usd",
+    "agents.*.model",
+    "agents.*.max_tokens",
+    "agents.*.temperature",
+    "agents.*.max_rounds",
+];
+
+/// Check every key set in a context `config` against [`CONTEXT_KEYS`]. `pattern` is the dotted
+/// path with agent names replaced by `*`; `path` is the path as written, for errors.
+fn check_context_keys(pattern: &str, path: &str, value: &Value) -> Result<()> {
+    let Value::Object(map) = value else {
+        if CONTEXT_KEYS.contains(&pattern) {
+            return Ok(());
+        }
+        return Err(anyhow::anyhow!(
+            "{} can't be set from a message context. Allowed keys: {}",
+            path,
+            CONTEXT_KEYS.join(", ")
+        ));
+    };
+
+    let join = |prefix: &str, key: &str| match prefix {
+        "" => key.to_string(),
+        prefix => format!("{}.{}", prefix, key),
+    };
+    for (key, child) in map {
+        // Unknown agent names are rejected when the settings are checked
+        let pattern_key = if pattern == "agents" { "*" } else { key.as_str() };
+        check_context_keys(&join(pattern, pattern_key), &join(path, key), child)?;
+    }
+    Ok(())
+}
+
+fn parse_env(name: &str, raw: &str, kind: EnvKind) -> Result<Value> {
+    let value = raw.trim();
+    let invalid = |expected: &str| {
+        anyhow::anyhow!("Invalid value for {}: '{}' is not {}", name, raw, expected)
+    };
+
+    match kind {
+        EnvKind::Text => Ok(Value::String(value.to_string())),
+        EnvKind::Integer => value
+            .parse::<u64>()
+            .map(Value::from)
+            .map_err(|_| invalid("a whole number")),
+        EnvKind::Number => value
+            .parse::<f64>()
+            .ok()
+            .filter(|number| number.is_finite())
+            .map(Value::from)
+            .ok_or_else(|| invalid("a number")),
+        EnvKind::Flag => match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(Value::Bool(true)),
+            "0" | "false" | "no" | "off" => Ok(Value::Bool(false)),
+            _ => Err(invalid("true or false")),
+        },
+        EnvKind::Paths => Ok(Value::from(
+            env::split_paths(value)
+                .filter(|path| !path.as_os_str().is_empty())
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>(),
+        )),
+    }
+}
+
+/// `value` nested under a dotted key, e.g. `llm.retry.max_retries`
+fn nested(key: &str, value: Value) -> Value {
+    key.rsplit('.').fold(value, |value, part| {
+        let mut object = Map::new();
+        object.insert(part.to_string(), value);
+        Value::Object(object)
+    })
+}
+
+fn merged(layers: &[Layer]) -> Value {
+    let mut merged = Value::Object(Map::new());
+    for layer in layers {
+        merge(&mut merged, layer.value.clone());
+    }
+    merged
+}
+
+/// Merge `overlay` into `base`. Objects merge key by key, anything else is replaced.
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// `$XDG_CONFIG_HOME/file-agent` (default `~/.config/file-agent`)
+pub fn user_config_dir() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("file-agent"))
+}
+
+/// `$XDG_STATE_HOME/file-agent` (default `~/.local/state/file-agent`)
+pub fn user_state_dir() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local").join("state"),
+    };
+    Some(dir.join("file-agent"))
+}
+
+/// The user's settings file, if it exists
+fn user_config_file() -> Option<PathBuf> {
+    user_config_dir()
+        .map(|dir| dir.join("config.toml"))
+        .filter(|path| path.exists())
+}
+
+/// The file named by `FILE_AGENT_CONFIG`, or else [`PROJECT_CONFIG_FILE`] in `root`, if it exists
+fn project_config_file(root: &Path) -> Result<Option<PathBuf>> {
+    match env::var("FILE_AGENT_CONFIG") {
+        Ok(path) if !path.trim().is_empty() => {
+            let path = PathBuf::from(path);
+            if !path.exists() {
+                return Err(anyhow::anyhow!(
+                    "FILE_AGENT_CONFIG names {}, which doesn't exist",
+                    path.display()
+                ));
+            }
+            Ok(Some(path))
+        }
+        _ => Ok(Some(root.join(PROJECT_CONFIG_FILE)).filter(|path| path.exists())),
+    }
+}
+
+/// Whether `path` lies under `root`, comparing canonical paths where they exist
+fn is_inside(path: &Path, root: &Path) -> bool {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    canonical(path).starts_with(canonical(root))
+}
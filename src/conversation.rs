@@ -0,0 +1,842 @@
+use crate::agent::Agent;
+use crate::checkpoint::{Checkpoint, CheckpointEntry};
+use crate::compaction::{self, CompactionSettings};
+use crate::llm::{
+    ContentBlock, LlmProvider, LlmRequest, Message, Role, StopReason, StreamEvent, ToolSchema,
+};
+use crate::policy::{Decision, PermissionPolicy};
+use crate::tokens;
+use crate::tool::Tool;
+use crate::usage::UsageTracker;
+use crate::utils;
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use serde_json::{json, Value};
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Characters of each call result kept in a partial result
+const PARTIAL_RESULT_PREVIEW_CHARS: usize = 500;
+
+/// Sent after a response was cut off at max_tokens
+const CONTINUE_PROMPT: &str =
+    "Your response was cut off because it reached the output token limit. Continue exactly where you left off, without repeating anything.";
+
+/// Result of a call that was started but never finished, e.g. because the process stopped
+pub const INTERRUPTED_CALL_RESULT: &str =
+    "interrupted before it finished. It may or may not have taken effect, so check before relying on it or running it again.";
+
+tokio::task_local! {
+    /// tool_use_id of the call being run
+    static CALL_ID: String;
+}
+
+/// Run `future` as the call `tool_use_id`, so an agent it delegates to can key its checkpoint
+/// by the call
+pub async fn with_call_id<F: Future>(tool_use_id: String, future: F) -> F::Output {
+    CALL_ID.scope(tool_use_id, future).await
+}
+
+/// tool_use_id of the call currently being run, if any
+pub fn current_call_id() -> Option<String> {
+    CALL_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Something the model can call: a tool, or an agent it delegates to
+#[async_trait::async_trait]
+pub trait Callable: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    fn input_schema(&self) -> Value;
+
+    /// Run the call with the model-provided input
+    async fn invoke(&self, input: &Value) -> Result<String>;
+}
+
+#[async_trait::async_trait]
+impl Callable for &dyn Tool {
+    fn name(&self) -> &str {
+        Tool::name(*self)
+    }
+
+    fn description(&self) -> &str {
+        Tool::description(*self)
+    }
+
+    fn input_schema(&self) -> Value {
+        self.parameters()
+    }
+
+    async fn invoke(&self, input: &Value) -> Result<String> {
+        let arguments = input.to_string();
+        let result = self.execute(&arguments).await?;
+
+        // Store tool-specific message using tool name
+        let tool_message = json!({
+            "tool": Tool::name(*self),
+            "arguments": arguments,
+            "result": result,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        });
+        utils::store_claude_message(Tool::name(*self), &tool_message)?;
+
+        Ok(result)
+    }
+}
+
+#[async_trait::async_trait]
+impl Callable for &dyn Agent {
+    fn name(&self) -> &str {
+        Agent::name(*self)
+    }
+
+    fn description(&self) -> &str {
+        Agent::description(*self)
+    }
+
+    fn input_schema(&self) -> Value {
+        Agent::input_schema(*self)
+    }
+
+    async fn invoke(&self, input: &Value) -> Result<String> {
+        let task = input
+            .get("task")
+            .and_then(|t| t.as_str())
+            .ok_or_else(|| anyhow::anyhow!("No task provided to agent"))?;
+        self.execute(task).await
+    }
+}
+
+/// A tool_use block requested by the model
+#[derive(Debug, Clone)]
+pub struct ToolCall {
+    pub tool_use_id: String,
+    pub name: String,
+    pub input: Value,
+}
+
+/// Output of a single call, sent back to the model as a tool_result block
+#[derive(Debug, Clone)]
+pub struct CallResult {
+    pub tool_use_id: String,
+    pub name: String,
+    pub output: String,
+    pub is_error: bool,
+}
+
+/// Receives stream events from an agent's model responses, with the name of the agent
+pub type AgentEventFn = dyn Fn(&str, &StreamEvent) + Send + Sync;
+
+/// Event handler shared by an agent and the agents it delegates to
+pub type EventHandler = Arc<AgentEventFn>;
+
+/// Post-processing applied to a round's results before they are sent back to the model
+pub trait ResultProcessor: Send + Sync {
+    /// `conversation_tokens` is the estimated size of the request so far, without the results
+    fn process(
+        &self,
+        results: Vec<CallResult>,
+        conversation_tokens: usize,
+    ) -> Result<Vec<CallResult>>;
+}
+
+/// Limits for a single conversation
+#[derive(Debug, Clone)]
+pub struct ConversationLimits {
+    /// Model requests before the conversation is stopped
+    pub max_rounds: usize,
+    /// Calls from one response that may run at the same time. 1 runs them in order.
+    pub max_parallel_calls: usize,
+    /// Times in a row a response cut off at max_tokens is continued
+    pub max_continuations: usize,
+}
+
+impl Default for ConversationLimits {
+    fn default() -> Self {
+        Self {
+            max_rounds: 100,
+            max_parallel_calls: 16,
+            max_continuations: 3,
+        }
+    }
+}
+
+/// Ways a conversation can end without an answer
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConversationError {
+    /// The model was still calling tools after `max_rounds` requests
+    MaxRoundsReached {
+        agent: String,
+        max_rounds: usize,
+        /// Latest text from the model, if any
+        last_text: String,
+    },
+    /// The model declined the request (`stop_reason` "refusal")
+    Refused { agent: String, text: String },
+}
+
+impl fmt::Display for ConversationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversationError::MaxRoundsReached {
+                agent, max_rounds, ..
+            } => write!(
+                f,
+                "{} stopped after reaching the maximum of {} rounds without finishing the task",
+                agent, max_rounds
+            ),
+            ConversationError::Refused { agent, text } if text.trim().is_empty() => {
+                write!(f, "{} model refused the request", agent)
+            }
+            ConversationError::Refused { agent, text } => {
+                write!(f, "{} model refused the request: {}", agent, text)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConversationError {}
+
+/// Conversation loop shared by all agents.
+///
+/// Sends the task to the model, runs the tool_use blocks it asks for and feeds the results back
+/// until the model answers without calling anything. Agents only supply a system prompt and
+/// their callables.
+pub struct Conversation<'a> {
+    provider: &'a dyn LlmProvider,
+    /// Agent name used for logging and stored messages
+    name: String,
+    system_prompt: String,
+    callables: Vec<Box<dyn Callable + 'a>>,
+    limits: ConversationLimits,
+    policy: Option<&'a PermissionPolicy>,
+    result_processor: Option<&'a dyn ResultProcessor>,
+    events: Option<&'a AgentEventFn>,
+    usage: Option<&'a UsageTracker>,
+    /// Providers for the rounds after calls to certain tools, first match wins
+    phases: Vec<(&'a [String], &'a dyn LlmProvider)>,
+    checkpoint: Option<&'a dyn Checkpoint>,
+    compaction: Option<CompactionSettings>,
+}
+
+impl<'a> Conversation<'a> {
+    pub fn new(
+        provider: &'a dyn LlmProvider,
+        name: &str,
+        system_prompt: &str,
+        callables: Vec<Box<dyn Callable + 'a>>,
+    ) -> Self {
+        Self {
+            provider,
+            name: name.to_string(),
+            system_prompt: system_prompt.to_string(),
+            callables,
+            limits: ConversationLimits::default(),
+            policy: None,
+            result_processor: None,
+            events: None,
+            usage: None,
+            phases: Vec::new(),
+            checkpoint: None,
+            compaction: None,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: ConversationLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Check every call against a permission policy before running it
+    pub fn with_policy(mut self, policy: &'a PermissionPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    pub fn with_result_processor(mut self, processor: &'a dyn ResultProcessor) -> Self {
+        self.result_processor = Some(processor);
+        self
+    }
+
+    /// Stream model responses and report their events as they arrive
+    pub fn with_events(mut self, handler: &'a AgentEventFn) -> Self {
+        self.events = Some(handler);
+        self
+    }
+
+    /// Record token usage of every response, and stop once the tracker's budget is used up
+    pub fn with_usage(mut self, tracker: &'a UsageTracker) -> Self {
+        self.usage = Some(tracker);
+        self
+    }
+
+    /// Use `provider` for the rounds that follow a round calling any of `tools`, e.g. a stronger
+    /// model once the agent starts editing
+    pub fn with_phase(mut self, tools: &'a [String], provider: &'a dyn LlmProvider) -> Self {
+        self.phases.push((tools, provider));
+        self
+    }
+
+    /// Record every message and call in `checkpoint` as the conversation goes, so it can be
+    /// resumed after a crash
+    pub fn with_checkpoint(mut self, checkpoint: &'a dyn Checkpoint) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Shrink the conversation before a request once it nears the context window
+    pub fn with_compaction(mut self, settings: CompactionSettings) -> Self {
+        self.compaction = settings.enabled.then_some(settings);
+        self
+    }
+
+    /// Provider for the round after `calls`
+    fn provider_after(&self, calls: &[ToolCall]) -> &'a dyn LlmProvider {
+        self.phases
+            .iter()
+            .find(|(tools, _)| calls.iter().any(|call| tools.contains(&call.name)))
+            .map(|(_, provider)| *provider)
+            .unwrap_or(self.provider)
+    }
+
+    /// Run the conversation for a task and return the model's final answer
+    pub async fn run(&self, task: &str) -> Result<String> {
+        self.run_continued(&mut Vec::new(), task).await
+    }
+
+    /// Run a task as the next turn of an earlier conversation. `history` holds the earlier
+    /// messages and is extended with this task's messages, up to the final answer. Calls an error
+    /// left without a result are closed with an error result, so the history can always be
+    /// continued.
+    pub async fn run_continued(&self, history: &mut Vec<Message>, task: &str) -> Result<String> {
+        let mut request = self.request(std::mem::take(history));
+        let result = match self.push(&mut request.messages, Message::user_text(task)) {
+            Ok(()) => self.run_request(&mut request).await,
+            Err(e) => Err(e),
+        };
+        close_unfinished_calls(&mut request.messages);
+        *history = request.messages;
+        result
+    }
+
+    /// Continue a conversation that stopped before the model answered, e.g. one rebuilt from a
+    /// checkpoint after a crash. `history` must end with a user message: the task, or the
+    /// results of the last calls.
+    pub async fn resume(&self, history: &mut Vec<Message>) -> Result<String> {
+        if history.last().map(|message| message.role) != Some(Role::User) {
+            return Err(anyhow::anyhow!(
+                "{} has no unfinished task to resume",
+                self.name
+            ));
+        }
+        log::info!(
+            "{} resuming conversation after {} messages",
+            self.name,
+            history.len()
+        );
+
+        let mut request = self.request(std::mem::take(history));
+        let result = self.run_request(&mut request).await;
+        close_unfinished_calls(&mut request.messages);
+        *history = request.messages;
+        result
+    }
+
+    fn request(&self, messages: Vec<Message>) -> LlmRequest {
+        let tools: Vec<ToolSchema> = self
+            .callables
+            .iter()
+            .map(|callable| ToolSchema {
+                name: callable.name().to_string(),
+                description: callable.description().to_string(),
+                input_schema: callable.input_schema(),
+            })
+            .collect();
+
+        LlmRequest {
+            system: self.system_prompt.clone(),
+            messages,
+            tools,
+        }
+    }
+
+    async fn run_request(&self, request: &mut LlmRequest) -> Result<String> {
+        let mut last_text = String::new();
+        let mut last_results: Vec<CallResult> = Vec::new();
+        // Text of responses cut off at max_tokens, waiting for the rest
+        let mut cut_off_text = String::new();
+        let mut continuations = 0;
+        let mut provider = self.provider;
+
+        for round in 1..=self.limits.max_rounds {
+            // Agents this conversation delegated to may have used up the budget
+            if let Some(reason) = self.budget_exceeded() {
+                return Ok(partial_result(&reason, &last_text, &last_results));
+            }
+
+            self.compact(request, provider).await;
+
+            log::debug!(
+                "{} calling {} model {} - Round {}",
+                self.name,
+                provider.name(),
+                provider.model(),
+                round
+            );
+
+            let mut response = match self.events {
+                Some(handler) => {
+                    let on_event = |event: &StreamEvent| handler(&self.name, event);
+                    provider.send_streaming(request, &on_event).await?
+                }
+                None => provider.send(request).await?,
+            };
+
+            // Store the model response for debugging/analysis
+            utils::store_claude_message(&self.name, &response.raw)?;
+
+            if let Some(usage) = self.usage {
+                usage.record(&self.name, provider.model(), &response.usage);
+            }
+
+            let text = std::mem::take(&mut cut_off_text) + &response.text();
+            if !text.trim().is_empty() {
+                last_text = text.clone();
+            }
+
+            let mut tool_calls: Vec<ToolCall> = response
+                .content
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::ToolUse { id, name, input } => Some(ToolCall {
+                        tool_use_id: id.clone(),
+                        name: name.clone(),
+                        input: input.clone(),
+                    }),
+                    _ => None,
+                })
+                .collect();
+
+            match &response.stop_reason {
+                Some(StopReason::Refusal) => {
+                    self.push_answer(&mut request.messages, response.content)?;
+                    return Err(ConversationError::Refused {
+                        agent: self.name.clone(),
+                        text,
+                    }
+                    .into());
+                }
+                Some(StopReason::MaxTokens) if tool_calls.is_empty() => {
+                    if continuations < self.limits.max_continuations {
+                        continuations += 1;
+                        log::info!(
+                            "{} response was cut off at max_tokens, asking the model to continue ({}/{})",
+                            self.name,
+                            continuations,
+                            self.limits.max_continuations
+                        );
+                        cut_off_text = text;
+                        self.push(
+                            &mut request.messages,
+                            Message {
+                                role: Role::Assistant,
+                                content: response.content,
+                            },
+                        )?;
+                        self.push(&mut request.messages, Message::user_text(CONTINUE_PROMPT))?;
+                        continue;
+                    }
+                    log::warn!(
+                        "{} response was still cut off at max_tokens after {} continuations",
+                        self.name,
+                        continuations
+                    );
+                }
+                Some(StopReason::Other(reason)) => {
+                    log::warn!("{} got unknown stop_reason: {}", self.name, reason);
+                }
+                // end_turn, stop_sequence and tool_use are handled by the presence of tool calls
+                _ => {}
+            }
+            continuations = 0;
+
+            if tool_calls.is_empty() {
+                self.push_answer(&mut request.messages, response.content)?;
+                return Ok(final_answer(&last_text));
+            }
+
+            let next = self.provider_after(&tool_calls);
+            if next.model() != provider.model() {
+                log::info!(
+                    "{} switching from model {} to {}",
+                    self.name,
+                    provider.model(),
+                    next.model()
+                );
+            }
+            provider = next;
+
+            // A tool_use block that ends a cut-off response has incomplete input
+            let incomplete = (response.stop_reason == Some(StopReason::MaxTokens)
+                && matches!(response.content.last(), Some(ContentBlock::ToolUse { .. })))
+            .then(|| tool_calls.pop())
+            .flatten();
+            // The API rejects a tool_use whose input isn't an object, so the truncated input
+            // isn't sent back
+            if incomplete.is_some() {
+                if let Some(ContentBlock::ToolUse { input, .. }) = response.content.last_mut() {
+                    *input = json!({});
+                }
+            }
+
+            // Don't start calls the budget no longer covers
+            if let Some(reason) = self.budget_exceeded() {
+                return Ok(partial_result(&reason, &last_text, &last_results));
+            }
+
+            self.push(
+                &mut request.messages,
+                Message {
+                    role: Role::Assistant,
+                    content: response.content,
+                },
+            )?;
+
+            let conversation_tokens = tokens::estimate_request(request);
+            let mut results = self.execute_calls(tool_calls, conversation_tokens).await?;
+            if let Some(call) = incomplete {
+                log::warn!(
+                    "{} call {} was cut off at max_tokens and not run",
+                    self.name,
+                    call.name
+                );
+                results.push(CallResult {
+                    tool_use_id: call.tool_use_id,
+                    output: format!("Tool input was incomplete because the response reached the output token limit, so {} was not run. Call it again with shorter input, for example by splitting a large write into several smaller edits.", call.name),
+                    name: call.name,
+                    is_error: true,
+                });
+            }
+
+            // All results of a turn go back in a single user message
+            self.push(
+                &mut request.messages,
+                Message {
+                    role: Role::User,
+                    content: results
+                        .iter()
+                        .map(|result| ContentBlock::ToolResult {
+                            tool_use_id: result.tool_use_id.clone(),
+                            content: result.output.clone(),
+                            is_error: result.is_error,
+                        })
+                        .collect(),
+                },
+            )?;
+            last_results = results;
+        }
+
+        log::warn!(
+            "{} reached maximum rounds: {}",
+            self.name,
+            self.limits.max_rounds
+        );
+        Err(ConversationError::MaxRoundsReached {
+            agent: self.name.clone(),
+            max_rounds: self.limits.max_rounds,
+            last_text,
+        }
+        .into())
+    }
+
+    /// Elide old call results once the request passes the compaction threshold, and summarize
+    /// older rounds if that isn't enough and summaries are enabled. Only the request sent to the
+    /// model changes; the checkpoint keeps every message.
+    async fn compact(&self, request: &mut LlmRequest, provider: &dyn LlmProvider) {
+        let Some(settings) = &self.compaction else {
+            return;
+        };
+        let before = tokens::estimate_request(request);
+        if before <= settings.threshold_tokens {
+            return;
+        }
+
+        let elided = compaction::elide_old_results(&mut request.messages, settings.keep_rounds);
+        let mut summarized = false;
+        if settings.summarize && tokens::estimate_request(request) > settings.threshold_tokens {
+            match self.summarize(request, provider, settings.keep_rounds).await {
+                Ok(done) => summarized = done,
+                Err(e) => log::warn!("{} failed to summarize older rounds: {}", self.name, e),
+            }
+        }
+
+        let after = tokens::estimate_request(request);
+        if elided > 0 || summarized {
+            log::info!(
+                "{} compacted the conversation from about {} to {} tokens ({} results elided{})",
+                self.name,
+                before,
+                after,
+                elided,
+                if summarized { ", older rounds summarized" } else { "" }
+            );
+        } else {
+            log::warn!(
+                "{} conversation is about {} tokens, over the compaction threshold of {}, but nothing more can be compacted",
+                self.name,
+                after,
+                settings.threshold_tokens
+            );
+        }
+    }
+
+    /// Replace the rounds before the last `keep_rounds` with a summary written by `provider`.
+    /// Returns whether there was anything to summarize.
+    async fn summarize(
+        &self,
+        request: &mut LlmRequest,
+        provider: &dyn LlmProvider,
+        keep_rounds: usize,
+    ) -> Result<bool> {
+        let Some(summary_request) = compaction::summary_request(&request.messages, keep_rounds)
+        else {
+            return Ok(false);
+        };
+
+        let response = provider.send(&summary_request).await?;
+        utils::store_claude_message(&self.name, &response.raw)?;
+        if let Some(usage) = self.usage {
+            usage.record(&self.name, provider.model(), &response.usage);
+        }
+
+        let summary = response.text();
+        if summary.trim().is_empty() {
+            return Err(anyhow::anyhow!("the model returned an empty summary"));
+        }
+        compaction::replace_with_summary(&mut request.messages, keep_rounds, &summary);
+        Ok(true)
+    }
+
+    /// Add a message, merged into the last message when both are from the user (e.g. a task
+    /// following the tool results of a conversation stopped by its budget). The message is
+    /// checkpointed first, so a crash never loses a message the model has seen.
+    fn push(&self, messages: &mut Vec<Message>, message: Message) -> Result<()> {
+        if let Some(checkpoint) = self.checkpoint {
+            checkpoint.record(&CheckpointEntry::Message {
+                message: message.clone(),
+            })?;
+        }
+        match messages.last_mut() {
+            Some(last) if last.role == Role::User && message.role == Role::User => {
+                last.content.extend(message.content)
+            }
+            _ => messages.push(message),
+        }
+        Ok(())
+    }
+
+    /// Keep the final response in the history. Empty responses are dropped, since APIs reject
+    /// empty messages.
+    fn push_answer(&self, messages: &mut Vec<Message>, content: Vec<ContentBlock>) -> Result<()> {
+        if content.is_empty() {
+            return Ok(());
+        }
+        self.push(
+            messages,
+            Message {
+                role: Role::Assistant,
+                content,
+            },
+        )
+    }
+
+    /// Checkpoint the progress of a call. The call has already run or is about to, so a failed
+    /// write is only logged.
+    fn record_call(&self, entry: CheckpointEntry) {
+        if let Some(checkpoint) = self.checkpoint {
+            if let Err(e) = checkpoint.record(&entry) {
+                log::error!("{} failed to checkpoint call: {}", self.name, e);
+            }
+        }
+    }
+
+    fn budget_exceeded(&self) -> Option<String> {
+        let reason = self.usage?.exceeded()?;
+        log::warn!("{} stopping: {}", self.name, reason);
+        Some(reason)
+    }
+
+    /// Run a round's calls, up to `max_parallel_calls` at a time, keeping their order
+    async fn execute_calls(
+        &self,
+        calls: Vec<ToolCall>,
+        conversation_tokens: usize,
+    ) -> Result<Vec<CallResult>> {
+        log::info!("{} executing {} calls", self.name, calls.len());
+
+        let results: Vec<CallResult> = stream::iter(calls)
+            .map(|call| self.execute_call(call))
+            .buffered(self.limits.max_parallel_calls.max(1))
+            .collect()
+            .await;
+
+        for result in &results {
+            if result.is_error {
+                log::error!("Call {} had error: {}", result.name, result.output);
+            } else {
+                log::info!("Call {} completed successfully", result.name);
+            }
+        }
+
+        match self.result_processor {
+            Some(processor) => processor.process(results, conversation_tokens),
+            None => Ok(results),
+        }
+    }
+
+    async fn execute_call(&self, call: ToolCall) -> CallResult {
+        let result = |output: String, is_error: bool| CallResult {
+            tool_use_id: call.tool_use_id.clone(),
+            name: call.name.clone(),
+            output,
+            is_error,
+        };
+
+        let Some(callable) = self.callables.iter().find(|c| c.name() == call.name) else {
+            return result(format!("Tool not found: {}", call.name), true);
+        };
+
+        // Check the permission policy before anything runs
+        if let Some(policy) = self.policy {
+            let outcome = policy.evaluate(&call.name, &call.input.to_string());
+            match outcome.decision {
+                Decision::Allow => {}
+                Decision::Ask => {
+                    return result(format!("Permission denied: {} requires user approval ({}). Ask the user to approve it or choose a different approach.", call.name, outcome.reason), true);
+                }
+                Decision::Deny => {
+                    return result(format!("Permission denied: {} is not allowed by policy ({}).", call.name, outcome.reason), true);
+                }
+            }
+        }
+
+        self.record_call(CheckpointEntry::CallStarted {
+            tool_use_id: call.tool_use_id.clone(),
+            name: call.name.clone(),
+        });
+        let finished = match with_call_id(call.tool_use_id.clone(), callable.invoke(&call.input)).await {
+            Ok(output) => result(output, false),
+            Err(e) => result(format!("Tool execution failed: {}", e), true),
+        };
+        self.record_call(CheckpointEntry::CallFinished {
+            tool_use_id: call.tool_use_id.clone(),
+            name: call.name.clone(),
+            is_error: finished.is_error,
+        });
+        finished
+    }
+}
+
+/// Give every tool_use block of the last assistant message a result, marking calls that never
+/// returned one as interrupted. Returns how many calls were marked.
+pub fn close_unfinished_calls(messages: &mut Vec<Message>) -> usize {
+    close_calls_with(messages, |_, name| {
+        format!("{}: {}", name, INTERRUPTED_CALL_RESULT)
+    })
+}
+
+/// Give every tool_use block of the last assistant message without a result the error result
+/// `result_for(tool_use_id, name)`. Returns how many calls were closed.
+pub(crate) fn close_calls_with(
+    messages: &mut Vec<Message>,
+    result_for: impl Fn(&str, &str) -> String,
+) -> usize {
+    let Some(position) = messages.iter().rposition(|m| m.role == Role::Assistant) else {
+        return 0;
+    };
+
+    let answered: Vec<String> = messages[position + 1..]
+        .iter()
+        .flat_map(|message| &message.content)
+        .filter_map(|block| match block {
+            ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.clone()),
+            _ => None,
+        })
+        .collect();
+    let unfinished: Vec<ContentBlock> = messages[position]
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::ToolUse { id, name, .. } if !answered.contains(id) => {
+                log::warn!("Closing unfinished call {} ({})", id, name);
+                Some(ContentBlock::ToolResult {
+                    tool_use_id: id.clone(),
+                    content: result_for(id, name),
+                    is_error: true,
+                })
+            }
+            _ => None,
+        })
+        .collect();
+    if unfinished.is_empty() {
+        return 0;
+    }
+
+    let count = unfinished.len();
+    if position + 1 == messages.len() {
+        messages.push(Message {
+            role: Role::User,
+            content: unfinished,
+        });
+    } else {
+        // Results go before any text in the message that follows the calls
+        let results = &mut messages[position + 1].content;
+        let at = results
+            .iter()
+            .position(|block| !matches!(block, ContentBlock::ToolResult { .. }))
+            .unwrap_or(results.len());
+        results.splice(at..at, unfinished);
+    }
+    count
+}
+
+fn final_answer(text: &str) -> String {
+    if text.trim().is_empty() {
+        "Task completed successfully".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Answer for a conversation stopped by its budget: the reason, the model's latest text and the
+/// results of the last calls
+fn partial_result(reason: &str, last_text: &str, last_results: &[CallResult]) -> String {
+    let mut summary = format!("Stopped before finishing the task: {}.", reason);
+
+    if !last_text.trim().is_empty() {
+        summary.push_str(&format!("\n\nProgress so far:\n{}", last_text));
+    }
+    if !last_results.is_empty() {
+        summary.push_str("\n\nResults of the last calls:");
+        for result in last_results {
+            let status = if result.is_error { "failed" } else { "ok" };
+            summary.push_str(&format!(
+                "\n- {} ({}): {}",
+                result.name,
+                status,
+                preview(&result.output, PARTIAL_RESULT_PREVIEW_CHARS)
+            ));
+        }
+    }
+    summary
+}
+
+fn preview(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
@@ -1,63 +1,122 @@
 use anyhow::Result;
 use reqwest::Client;
 use serde_json::Value;
+use config::{LlmSettings, Settings};
+use llm::{CassetteConfig, CassetteMode, ProviderKind};
+use retry::RetryConfig;
+use session::Session;
+use usage::BudgetConfig;
 use std::env;
+use std::path::PathBuf;
 
 pub mod agent;
 pub mod agents;
+pub mod checkpoint;
+pub mod compaction;
+pub mod config;
+pub mod conversation;
+pub mod llm;
+pub mod output;
+pub mod policy;
+pub mod retry;
+pub mod session;
+pub mod shell_parser;
+pub mod tokens;
 pub mod tool;
+pub mod usage;
 pub mod utils;
 
 #[derive(Debug, Clone)]
 pub struct ClaudeConfig {
+    /// Which API to talk to, from `llm.provider` or `LLM_PROVIDER` (anthropic or openai)
+    pub provider: ProviderKind,
+    /// Turns replayed by the scripted provider, from `LLM_SCRIPT`
+    pub script_path: Option<PathBuf>,
+    /// Record to or replay from a cassette file, from `LLM_CASSETTE`
+    pub cassette: Option<CassetteConfig>,
     pub api_key: String,
     pub api_url: String,
     pub model: String,
     pub max_tokens: u32,
     pub temperature: f32,
     pub timeout_seconds: u64,
+    /// Stream Anthropic responses over server-sent events, from `llm.stream`. Responses are
+    /// always streamed when a host registers an event handler.
+    pub stream: bool,
+    /// Mark the tools, system prompt and conversation prefix as cacheable on Anthropic, from
+    /// `llm.prompt_caching`. On by default.
+    pub prompt_caching: bool,
+    pub retry: RetryConfig,
+    /// Per-task token and cost limits
+    pub budget: BudgetConfig,
     pub client: Client,
 }
 
 impl ClaudeConfig {
+    /// Config from the settings files and environment, without per-agent overrides
     pub fn new() -> Result<Self> {
-        let api_key = env::var("ANTHROPIC_API_KEY")
-            .map_err(|_| anyhow::anyhow!("ANTHROPIC_API_KEY environment variable not set"))?;
-        let api_url = env::var("CLAUDE_API_URL")
-            .unwrap_or_else(|_| "https://api.anthropic.com/v1/messages".to_string());
-        let model =
-            env::var("CLAUDE_MODEL").unwrap_or_else(|_| "claude-sonnet-4-20250514".to_string());
-        let max_tokens = env::var("CLAUDE_MAX_TOKENS")
-            .unwrap_or_else(|_| "8192".to_string())
-            .parse()
-            .unwrap_or(8192);
-        let temperature = env::var("CLAUDE_TEMPERATURE")
-            .unwrap_or_else(|_| "0.7".to_string())
-            .parse()
-            .unwrap_or(0.7);
-        let timeout_seconds = env::var("CLAUDE_TIMEOUT")
-            .unwrap_or_else(|_| "300".to_string())
-            .parse()
-            .unwrap_or(300);
+        let settings = Settings::load(&Value::Null)?;
+        Self::from_settings(&settings.llm, settings.budget()?)
+    }
+
+    /// Config for one agent, e.g. from `Settings::llm_for`. API keys, `LLM_SCRIPT` and
+    /// `LLM_CASSETTE` are read from the environment.
+    pub fn from_settings(llm: &LlmSettings, budget: BudgetConfig) -> Result<Self> {
+        let provider = llm.provider;
+        let cassette = CassetteConfig::from_env()?;
+        // Replaying a cassette never reaches the API
+        let replaying = matches!(&cassette, Some(c) if c.mode == CassetteMode::Replay);
 
+        let (api_key, default_url, default_model) = match provider {
+            ProviderKind::Anthropic => (
+                match env::var("ANTHROPIC_API_KEY") {
+                    Ok(key) => key,
+                    Err(_) if replaying => String::new(),
+                    Err(_) => {
+                        return Err(anyhow::anyhow!(
+                            "ANTHROPIC_API_KEY environment variable not set"
+                        ))
+                    }
+                },
+                llm::anthropic::DEFAULT_API_URL,
+                llm::anthropic::DEFAULT_MODEL,
+            ),
+            // Local model servers usually run without a key
+            ProviderKind::OpenAi => (
+                env::var("OPENAI_API_KEY").unwrap_or_default(),
+                llm::openai::DEFAULT_API_URL,
+                llm::openai::DEFAULT_MODEL,
+            ),
+            ProviderKind::Scripted => (String::new(), "", "scripted"),
+        };
+        let script_path = env::var_os("LLM_SCRIPT").map(PathBuf::from);
+
+        // Requests that aren't streamed set the total timeout themselves. A stream may take
+        // longer as long as chunks keep arriving.
         let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(timeout_seconds))
+            .read_timeout(std::time::Duration::from_secs(llm.timeout_seconds))
             .build()?;
 
         Ok(Self {
+            provider,
+            script_path,
+            cassette,
             api_key,
-            api_url,
-            model,
-            max_tokens,
-            temperature,
-            timeout_seconds,
+            api_url: llm.api_url.clone().unwrap_or_else(|| default_url.to_string()),
+            model: llm.model.clone().unwrap_or_else(|| default_model.to_string()),
+            max_tokens: llm.max_tokens,
+            temperature: llm.temperature,
+            timeout_seconds: llm.timeout_seconds,
+            stream: llm.stream,
+            prompt_caching: llm.prompt_caching,
+            retry: llm.retry.clone(),
+            budget,
             client,
         })
     }
 }
 
 // Re-export main agents for external use
-use agent::Agent;
 pub use agents::orchestrator::OrchestratorAgent;
 
 /// Main handler function called by Raworc (sync wrapper)
@@ -66,19 +125,61 @@ pub fn process_message_sync(message: &str, context: &Value) -> String {
     rt.block_on(process_message(message, context))
 }
 
-/// Process message handler for raworc integration
-async fn process_message(task: &str, context: &Value) -> String {
-    // Clear bin directory and reset counter for new request
-    if let Err(e) = utils::clear_bin_directory() {
-        log::warn!("Failed to clear bin directory: {}", e);
+/// Resume handler called by Raworc after a crash (sync wrapper)
+pub fn resume_sync(session_id: &str, context: &Value) -> String {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(resume(session_id, context))
+}
+
+/// Continue the unfinished task of a session, in the context's `space`. Calls that were
+/// running when it stopped are marked as interrupted in the rebuilt conversation.
+async fn resume(session_id: &str, context: &Value) -> String {
+    log::info!("Resuming session: {}", session_id);
+
+    let mut context = context.clone();
+    if let Value::Object(fields) = &mut context {
+        fields.insert("session_id".to_string(), Value::from(session_id));
+    } else {
+        context = serde_json::json!({"session_id": session_id});
     }
 
+    let (orchestrator, session) = match open_session(&context) {
+        Ok(setup) => setup,
+        Err(e) => {
+            log::error!("Failed to initialize: {}", e);
+            return e.to_string();
+        }
+    };
+
+    let response = match orchestrator.resume(&session).await {
+        Ok(result) => {
+            log::info!("Resumed task completed successfully");
+            result
+        }
+        Err(e) => {
+            log::error!("Resumed task failed: {}", e);
+            e.to_string()
+        }
+    };
+
+    with_usage(&orchestrator, response)
+}
+
+/// Process message handler for raworc integration. The context's `session_id` and `space` pick
+/// the session, so follow-up messages continue its conversation.
+async fn process_message(task: &str, context: &Value) -> String {
     log::info!("Processing message: {}", task);
 
-    // Initialize orchestrator and process task
-    let orchestrator = OrchestratorAgent::new().unwrap();
+    // Bad settings or session names are reported instead of run with
+    let (orchestrator, session) = match open_session(context) {
+        Ok(setup) => setup,
+        Err(e) => {
+            log::error!("Failed to initialize: {}", e);
+            return e.to_string();
+        }
+    };
 
-    match orchestrator.call(task).await {
+    let response = match orchestrator.execute_in_session(&session, task).await {
         Ok(result) => {
             log::info!("Task completed successfully");
             result.to_string()
@@ -87,5 +188,21 @@ async fn process_message(task: &str, context: &Value) -> String {
             log::error!("Task failed: {}", e);
             e.to_string()
         }
-    }
+    };
+
+    with_usage(&orchestrator, response)
+}
+
+/// Orchestrator and session for a Raworc context
+fn open_session(context: &Value) -> Result<(OrchestratorAgent, Session)> {
+    let settings = Settings::load(context)?;
+    let session = Session::from_context(&settings.session.dir, context)?;
+    Ok((OrchestratorAgent::from_settings(&settings)?, session))
+}
+
+/// Report token usage alongside the result
+fn with_usage(orchestrator: &OrchestratorAgent, response: String) -> String {
+    let usage = orchestrator.usage().summary();
+    log::info!("{}", usage);
+    format!("{}\n\n{}", response, usage)
 }
@@ -0,0 +1,454 @@
+use super::stream::{OnEvent, SseEvent, SseParser, StreamEvent};
+use super::{ContentBlock, LlmProvider, LlmRequest, LlmResponse, Message, Role, StopReason, Usage};
+use crate::retry::{self, ApiRequest};
+use crate::ClaudeConfig;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+pub const DEFAULT_API_URL: &str = "https://api.anthropic.com/v1/messages";
+pub const DEFAULT_MODEL: &str = "claude-sonnet-4-20250514";
+
+/// Anthropic Messages API
+pub struct AnthropicProvider {
+    config: ClaudeConfig,
+}
+
+impl AnthropicProvider {
+    pub fn new(config: ClaudeConfig) -> Self {
+        Self { config }
+    }
+
+    fn build_payload(&self, request: &LlmRequest) -> Value {
+        let mut tools: Vec<Value> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "input_schema": tool.input_schema
+                })
+            })
+            .collect();
+        let mut messages: Vec<Value> = request.messages.iter().map(message_to_json).collect();
+
+        let mut system = json!(request.system);
+
+        // Breakpoints (at most 4) go after the tools, after the system prompt, on the last
+        // message, and on the previous round's last message so this round can read what that
+        // round wrote even when many tool results were added since
+        if self.config.prompt_caching {
+            if let Some(tool) = tools.last_mut() {
+                tool["cache_control"] = cache_control();
+            }
+            system = json!([{
+                "type": "text",
+                "text": request.system,
+                "cache_control": cache_control()
+            }]);
+
+            let user_messages = request
+                .messages
+                .iter()
+                .enumerate()
+                .filter(|(_, message)| message.role == Role::User)
+                .map(|(i, _)| i)
+                .rev()
+                .take(2);
+            for i in user_messages {
+                if let Some(block) = messages[i]["content"]
+                    .as_array_mut()
+                    .and_then(|c| c.last_mut())
+                {
+                    block["cache_control"] = cache_control();
+                }
+            }
+        }
+
+        json!({
+            "model": self.config.model,
+            "max_tokens": self.config.max_tokens,
+            "temperature": self.config.temperature,
+            "system": system,
+            "messages": messages,
+            "tools": tools
+        })
+    }
+
+    fn log_cache_stats(&self, response: &LlmResponse) {
+        if !self.config.prompt_caching {
+            return;
+        }
+
+        let usage = &response.usage;
+        let input =
+            usage.input_tokens + usage.cache_creation_input_tokens + usage.cache_read_input_tokens;
+        let hit_rate = if input == 0 {
+            0.0
+        } else {
+            usage.cache_read_input_tokens as f64 * 100.0 / input as f64
+        };
+        log::info!(
+            "Claude prompt cache: {} tokens read, {} written, {} uncached ({:.0}% of input from cache)",
+            usage.cache_read_input_tokens,
+            usage.cache_creation_input_tokens,
+            usage.input_tokens,
+            hit_rate
+        );
+    }
+
+    fn headers(&self) -> [(&'static str, String); 2] {
+        [
+            ("x-api-key", self.config.api_key.clone()),
+            ("anthropic-version", "2023-06-01".to_string()),
+        ]
+    }
+
+    /// Send the request with `"stream": true` and assemble the response from its events
+    async fn stream(&self, request: &LlmRequest, on_event: &OnEvent<'_>) -> Result<LlmResponse> {
+        let mut payload = self.build_payload(request);
+        payload["stream"] = json!(true);
+        let headers = self.headers();
+
+        let mut response = retry::open_with_retry(
+            &ApiRequest {
+                client: &self.config.client,
+                service: "Claude API",
+                url: &self.config.api_url,
+                headers: &headers,
+                payload: &payload,
+                timeout: None,
+            },
+            &self.config.retry,
+        )
+        .await?;
+
+        let mut parser = SseParser::default();
+        let mut message = StreamedMessage::default();
+        while !message.done {
+            let chunk = response
+                .chunk()
+                .await
+                .map_err(|e| anyhow::anyhow!("Claude API stream failed: {}", e))?;
+            let Some(chunk) = chunk else {
+                return Err(anyhow::anyhow!(
+                    "Claude API stream ended before the message was complete"
+                ));
+            };
+            for event in parser.push(&chunk) {
+                message.apply(&event, on_event)?;
+            }
+        }
+
+        let response = parse_response(message.into_raw())?;
+        self.log_cache_stats(&response);
+        on_event(&StreamEvent::MessageStop {
+            stop_reason: response.stop_reason.clone(),
+        });
+        Ok(response)
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    async fn send(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        if self.config.stream {
+            return self.stream(request, &|_| {}).await;
+        }
+
+        let payload = self.build_payload(request);
+        let headers = self.headers();
+
+        let raw = retry::send_with_retry(
+            &ApiRequest {
+                client: &self.config.client,
+                service: "Claude API",
+                url: &self.config.api_url,
+                headers: &headers,
+                payload: &payload,
+                timeout: Some(Duration::from_secs(self.config.timeout_seconds)),
+            },
+            &self.config.retry,
+        )
+        .await?;
+
+        let response = parse_response(raw)?;
+        self.log_cache_stats(&response);
+        Ok(response)
+    }
+
+    async fn send_streaming(
+        &self,
+        request: &LlmRequest,
+        on_event: &OnEvent<'_>,
+    ) -> Result<LlmResponse> {
+        self.stream(request, on_event).await
+    }
+}
+
+/// A message being assembled from Messages API stream events. The result has the same shape as
+/// a non-streamed response body.
+#[derive(Default)]
+struct StreamedMessage {
+    message: Value,
+    blocks: Vec<Value>,
+    /// Tool input JSON received so far, by block index
+    partial_inputs: Vec<String>,
+    done: bool,
+}
+
+impl StreamedMessage {
+    fn apply(&mut self, event: &SseEvent, on_event: &OnEvent<'_>) -> Result<()> {
+        let data: Value = serde_json::from_str(&event.data).map_err(|e| {
+            anyhow::anyhow!(
+                "Claude API stream sent invalid event data: {} ({})",
+                event.data,
+                e
+            )
+        })?;
+        let index = data.get("index").and_then(|i| i.as_u64()).unwrap_or(0) as usize;
+
+        match data
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or_default()
+        {
+            "message_start" => {
+                self.message = data.get("message").cloned().unwrap_or_else(|| json!({}));
+            }
+            "content_block_start" => {
+                let block = data
+                    .get("content_block")
+                    .cloned()
+                    .unwrap_or_else(|| json!({}));
+                if self.blocks.len() <= index {
+                    self.blocks.resize(index + 1, Value::Null);
+                    self.partial_inputs.resize(index + 1, String::new());
+                }
+
+                match block.get("type").and_then(|t| t.as_str()) {
+                    Some("tool_use") => on_event(&StreamEvent::ToolUseStart {
+                        id: string_field(&block, "id"),
+                        name: string_field(&block, "name"),
+                    }),
+                    Some("text") => {
+                        let text = string_field(&block, "text");
+                        if !text.is_empty() {
+                            on_event(&StreamEvent::TextDelta { text });
+                        }
+                    }
+                    _ => {}
+                }
+                self.blocks[index] = block;
+            }
+            "content_block_delta" => {
+                let delta = data.get("delta").cloned().unwrap_or_default();
+                let Some(block) = self.blocks.get_mut(index) else {
+                    return Err(anyhow::anyhow!(
+                        "Claude API stream sent a delta for unknown content block {}",
+                        index
+                    ));
+                };
+
+                match delta.get("type").and_then(|t| t.as_str()) {
+                    Some("text_delta") => {
+                        let text = string_field(&delta, "text");
+                        append_field(block, "text", &text);
+                        on_event(&StreamEvent::TextDelta { text });
+                    }
+                    Some("input_json_delta") => {
+                        let partial_json = string_field(&delta, "partial_json");
+                        self.partial_inputs[index].push_str(&partial_json);
+                        on_event(&StreamEvent::ToolInputDelta {
+                            id: string_field(block, "id"),
+                            partial_json,
+                        });
+                    }
+                    Some("thinking_delta") => {
+                        append_field(block, "thinking", &string_field(&delta, "thinking"));
+                    }
+                    Some("signature_delta") => {
+                        block["signature"] = json!(string_field(&delta, "signature"));
+                    }
+                    _ => {}
+                }
+            }
+            "content_block_stop" => {
+                if let Some(block) = self.blocks.get_mut(index) {
+                    if block.get("type").and_then(|t| t.as_str()) == Some("tool_use") {
+                        let partial = self.partial_inputs[index].trim();
+                        // Input cut off at max_tokens isn't valid JSON. It is kept as a string,
+                        // and the conversation reports the incomplete call to the model and
+                        // sends the turn back with empty input.
+                        let input = if partial.is_empty() {
+                            json!({})
+                        } else {
+                            serde_json::from_str(partial)
+                                .unwrap_or_else(|_| Value::String(partial.to_string()))
+                        };
+                        block["input"] = input;
+                    }
+                }
+            }
+            "message_delta" => {
+                if let Some(delta) = data.get("delta").and_then(|d| d.as_object()) {
+                    for (key, value) in delta {
+                        self.message[key] = value.clone();
+                    }
+                }
+                if let Some(usage) = data.get("usage").and_then(|u| u.as_object()) {
+                    for (key, value) in usage {
+                        self.message["usage"][key] = value.clone();
+                    }
+                }
+            }
+            "message_stop" => self.done = true,
+            "error" => {
+                return Err(anyhow::anyhow!(
+                    "Claude API stream error: {}",
+                    data.get("error").unwrap_or(&data)
+                ));
+            }
+            // ping and event types added later
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn into_raw(mut self) -> Value {
+        self.message["content"] = Value::Array(
+            self.blocks
+                .into_iter()
+                .filter(|block| !block.is_null())
+                .collect(),
+        );
+        self.message
+    }
+}
+
+fn string_field(value: &Value, field: &str) -> String {
+    value
+        .get(field)
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn append_field(block: &mut Value, field: &str, text: &str) {
+    let current = string_field(block, field);
+    block[field] = json!(current + text);
+}
+
+fn cache_control() -> Value {
+    json!({"type": "ephemeral"})
+}
+
+fn message_to_json(message: &Message) -> Value {
+    let role = match message.role {
+        Role::User => "user",
+        Role::Assistant => "assistant",
+    };
+
+    let content: Vec<Value> = message
+        .content
+        .iter()
+        .map(|block| match block {
+            ContentBlock::Text { text } => json!({
+                "type": "text",
+                "text": text
+            }),
+            ContentBlock::ToolUse { id, name, input } => json!({
+                "type": "tool_use",
+                "id": id,
+                "name": name,
+                "input": input
+            }),
+            ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+            } => json!({
+                "type": "tool_result",
+                "tool_use_id": tool_use_id,
+                "content": content,
+                "is_error": is_error
+            }),
+        })
+        .collect();
+
+    json!({
+        "role": role,
+        "content": content
+    })
+}
+
+pub(crate) fn parse_response(raw: Value) -> Result<LlmResponse> {
+    let blocks = raw
+        .get("content")
+        .and_then(|c| c.as_array())
+        .ok_or_else(|| anyhow::anyhow!("Claude API response has no content: {}", raw))?;
+
+    let mut content = Vec::new();
+    for block in blocks {
+        match block.get("type").and_then(|t| t.as_str()) {
+            Some("text") => {
+                if let Some(text) = block.get("text").and_then(|t| t.as_str()) {
+                    content.push(ContentBlock::Text {
+                        text: text.to_string(),
+                    });
+                }
+            }
+            Some("tool_use") => {
+                let id = block.get("id").and_then(|id| id.as_str());
+                let name = block.get("name").and_then(|n| n.as_str());
+                if let (Some(id), Some(name)) = (id, name) {
+                    content.push(ContentBlock::ToolUse {
+                        id: id.to_string(),
+                        name: name.to_string(),
+                        input: block.get("input").cloned().unwrap_or_else(|| json!({})),
+                    });
+                }
+            }
+            // Thinking and other block types are not sent back
+            _ => {}
+        }
+    }
+
+    let stop_reason = raw
+        .get("stop_reason")
+        .and_then(|r| r.as_str())
+        .map(|reason| match reason {
+            "end_turn" => StopReason::EndTurn,
+            "tool_use" => StopReason::ToolUse,
+            "max_tokens" => StopReason::MaxTokens,
+            "stop_sequence" => StopReason::StopSequence,
+            "refusal" => StopReason::Refusal,
+            other => StopReason::Other(other.to_string()),
+        });
+
+    let usage = raw.get("usage").cloned().unwrap_or_default();
+    let tokens = |field: &str| usage.get(field).and_then(|t| t.as_u64()).unwrap_or(0);
+    let usage = Usage {
+        input_tokens: tokens("input_tokens"),
+        output_tokens: tokens("output_tokens"),
+        cache_creation_input_tokens: tokens("cache_creation_input_tokens"),
+        cache_read_input_tokens: tokens("cache_read_input_tokens"),
+    };
+
+    Ok(LlmResponse {
+        content,
+        stop_reason,
+        usage,
+        raw,
+    })
+}
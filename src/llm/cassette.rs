@@ -0,0 +1,375 @@
+use super::{LlmProvider, LlmRequest, LlmResponse, OnEvent};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Recorders and replayers are shared per cassette file, so that every agent in the process
+/// appends to (or replays from) the same sequence of interactions
+static RECORDERS: OnceLock<Mutex<HashMap<PathBuf, Arc<Recorder>>>> = OnceLock::new();
+static REPLAYERS: OnceLock<Mutex<HashMap<PathBuf, Arc<ReplayProvider>>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Forward requests to the real provider and write every exchange to the cassette
+    Record,
+    /// Serve responses from the cassette without any network access
+    Replay,
+}
+
+impl FromStr for CassetteMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "record" => Ok(CassetteMode::Record),
+            "replay" => Ok(CassetteMode::Replay),
+            other => Err(anyhow::anyhow!(
+                "Invalid cassette mode '{}'. Expected one of: record, replay",
+                other
+            )),
+        }
+    }
+}
+
+/// How replayed requests are matched to recorded ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchBy {
+    /// The n-th request gets the n-th recorded response
+    Order,
+    /// A request gets the first unused response recorded for an identical request
+    Hash,
+}
+
+impl FromStr for MatchBy {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "order" => Ok(MatchBy::Order),
+            "hash" => Ok(MatchBy::Hash),
+            other => Err(anyhow::anyhow!(
+                "Invalid cassette match mode '{}'. Expected one of: order, hash",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MatchBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchBy::Order => write!(f, "order"),
+            MatchBy::Hash => write!(f, "hash"),
+        }
+    }
+}
+
+/// Cassette settings, from `LLM_CASSETTE`, `LLM_CASSETTE_MODE` and `LLM_CASSETTE_MATCH`
+#[derive(Debug, Clone)]
+pub struct CassetteConfig {
+    pub path: PathBuf,
+    pub mode: CassetteMode,
+    pub match_by: MatchBy,
+}
+
+impl CassetteConfig {
+    /// None unless `LLM_CASSETTE` is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(path) = env::var_os("LLM_CASSETTE").filter(|path| !path.is_empty()) else {
+            return Ok(None);
+        };
+
+        let mode = match env::var("LLM_CASSETTE_MODE") {
+            Ok(mode) if !mode.trim().is_empty() => mode.parse()?,
+            _ => CassetteMode::Replay,
+        };
+        let match_by = match env::var("LLM_CASSETTE_MATCH") {
+            Ok(match_by) if !match_by.trim().is_empty() => match_by.parse()?,
+            _ => MatchBy::Order,
+        };
+
+        Ok(Some(Self {
+            path: PathBuf::from(path),
+            mode,
+            match_by,
+        }))
+    }
+}
+
+/// One recorded exchange. Cassettes are JSONL files with one entry per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteEntry {
+    pub index: usize,
+    /// Hash of the request, used by `MatchBy::Hash`
+    pub hash: String,
+    pub provider: String,
+    pub model: String,
+    pub request: LlmRequest,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<LlmResponse>,
+    /// Set instead of `response` when the provider returned an error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Stable hash of a request: FNV-1a over its JSON serialization
+pub fn request_hash(request: &LlmRequest) -> String {
+    let json = serde_json::to_vec(request).unwrap_or_default();
+    let hash = json.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Read all entries of a cassette file
+pub fn load_cassette(path: &Path) -> Result<Vec<CassetteEntry>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read cassette {}: {}", path.display(), e))?;
+
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid cassette entry on line {} of {}: {}",
+                    i + 1,
+                    path.display(),
+                    e
+                )
+            })
+        })
+        .collect()
+}
+
+/// Cassette file being written. The file is truncated when recording starts.
+struct Recorder {
+    path: PathBuf,
+    state: Mutex<(File, usize)>,
+}
+
+impl Recorder {
+    fn shared(path: &Path) -> Result<Arc<Self>> {
+        let mut recorders = RECORDERS
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap();
+
+        if let Some(recorder) = recorders.get(path) {
+            return Ok(recorder.clone());
+        }
+
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create cassette {}: {}", path.display(), e))?;
+        log::info!("Recording LLM traffic to cassette {}", path.display());
+
+        let recorder = Arc::new(Self {
+            path: path.to_path_buf(),
+            state: Mutex::new((file, 0)),
+        });
+        recorders.insert(path.to_path_buf(), recorder.clone());
+        Ok(recorder)
+    }
+
+    fn write(&self, mut entry: CassetteEntry) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let (file, count) = &mut *state;
+        entry.index = *count;
+        *count += 1;
+
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        file.flush()?;
+        log::debug!(
+            "Recorded cassette entry {} to {}",
+            entry.index,
+            self.path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Wraps a provider and writes every request and response to a cassette
+pub struct RecordingProvider {
+    inner: Arc<dyn LlmProvider>,
+    recorder: Arc<Recorder>,
+}
+
+impl RecordingProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, path: &Path) -> Result<Self> {
+        Ok(Self {
+            inner,
+            recorder: Recorder::shared(path)?,
+        })
+    }
+
+    fn record(&self, request: &LlmRequest, result: &Result<LlmResponse>) {
+        let entry = CassetteEntry {
+            index: 0,
+            hash: request_hash(request),
+            provider: self.inner.name().to_string(),
+            model: self.inner.model().to_string(),
+            request: request.clone(),
+            response: result.as_ref().ok().cloned(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+        // A failed write shouldn't fail the run being recorded
+        if let Err(e) = self.recorder.write(entry) {
+            log::warn!("Failed to write cassette entry: {}", e);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmProvider for RecordingProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn send(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        let result = self.inner.send(request).await;
+        self.record(request, &result);
+        result
+    }
+
+    async fn send_streaming(
+        &self,
+        request: &LlmRequest,
+        on_event: &OnEvent<'_>,
+    ) -> Result<LlmResponse> {
+        let result = self.inner.send_streaming(request, on_event).await;
+        self.record(request, &result);
+        result
+    }
+}
+
+/// Serves responses from a recorded cassette
+pub struct ReplayProvider {
+    entries: Vec<CassetteEntry>,
+    match_by: MatchBy,
+    /// Which entries were already served
+    used: Mutex<Vec<bool>>,
+}
+
+impl ReplayProvider {
+    pub fn new(entries: Vec<CassetteEntry>, match_by: MatchBy) -> Self {
+        Self {
+            used: Mutex::new(vec![false; entries.len()]),
+            entries,
+            match_by,
+        }
+    }
+
+    pub fn from_file(path: &Path, match_by: MatchBy) -> Result<Self> {
+        let entries = load_cassette(path)?;
+        log::info!(
+            "Replaying {} LLM exchanges from cassette {} (matching by {})",
+            entries.len(),
+            path.display(),
+            match_by
+        );
+        Ok(Self::new(entries, match_by))
+    }
+
+    /// Replayer for a cassette file, shared by every caller in this process
+    pub fn shared(config: &CassetteConfig) -> Result<Arc<Self>> {
+        let mut replayers = REPLAYERS
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap();
+
+        if let Some(replayer) = replayers.get(&config.path) {
+            return Ok(replayer.clone());
+        }
+
+        let replayer = Arc::new(Self::from_file(&config.path, config.match_by)?);
+        replayers.insert(config.path.clone(), replayer.clone());
+        Ok(replayer)
+    }
+
+    /// Entries not yet served
+    pub fn remaining(&self) -> usize {
+        self.used
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|used| !**used)
+            .count()
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmProvider for ReplayProvider {
+    fn name(&self) -> &str {
+        "replay"
+    }
+
+    fn model(&self) -> &str {
+        "replay"
+    }
+
+    async fn send(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        let hash = request_hash(request);
+        let mut used = self.used.lock().unwrap();
+
+        let index = match self.match_by {
+            MatchBy::Order => {
+                let index = used.iter().position(|used| !used).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Cassette has no recorded response left for request {}",
+                        hash
+                    )
+                })?;
+                if self.entries[index].hash != hash {
+                    log::warn!(
+                        "Replayed request differs from cassette entry {} (hash {} vs {})",
+                        self.entries[index].index,
+                        hash,
+                        self.entries[index].hash
+                    );
+                }
+                index
+            }
+            MatchBy::Hash => self
+                .entries
+                .iter()
+                .enumerate()
+                .position(|(i, entry)| !used[i] && entry.hash == hash)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Cassette has no unused recorded response for request {}",
+                        hash
+                    )
+                })?,
+        };
+        used[index] = true;
+
+        let entry = &self.entries[index];
+        log::debug!("Replaying cassette entry {}", entry.index);
+        match (&entry.response, &entry.error) {
+            (Some(response), _) => Ok(response.clone()),
+            (None, Some(error)) => Err(anyhow::anyhow!("{}", error)),
+            (None, None) => Err(anyhow::anyhow!(
+                "Cassette entry {} has neither a response nor an error",
+                entry.index
+            )),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::ClaudeConfig;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::ops::AddAssign;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub mod anthropic;
+pub mod cassette;
+pub mod openai;
+pub mod scripted;
+pub mod stream;
+
+pub use anthropic::AnthropicProvider;
+pub use cassette::{CassetteConfig, CassetteMode, MatchBy, RecordingProvider, ReplayProvider};
+pub use openai::OpenAiProvider;
+pub use scripted::ScriptedProvider;
+pub use stream::{OnEvent, StreamEvent};
+
+/// Which LLM API the agents talk to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderKind {
+    /// Anthropic Messages API
+    Anthropic,
+    /// OpenAI-compatible chat completions (OpenAI, llama.cpp, vLLM, Ollama, ...)
+    OpenAi,
+    /// Replays a script of turns offline, for tests
+    Scripted,
+}
+
+impl FromStr for ProviderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "anthropic" | "claude" => Ok(ProviderKind::Anthropic),
+            "openai" | "openai-compatible" => Ok(ProviderKind::OpenAi),
+            "scripted" => Ok(ProviderKind::Scripted),
+            other => Err(anyhow::anyhow!(
+                "Invalid LLM provider '{}'. Expected one of: anthropic, openai, scripted",
+                other
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ProviderKind {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderKind::Anthropic => write!(f, "anthropic"),
+            ProviderKind::OpenAi => write!(f, "openai"),
+            ProviderKind::Scripted => write!(f, "scripted"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// Provider-neutral message content, modeled on Anthropic content blocks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        is_error: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: Role,
+    pub content: Vec<ContentBlock>,
+}
+
+impl Message {
+    pub fn user_text(text: &str) -> Self {
+        Self {
+            role: Role::User,
+            content: vec![ContentBlock::Text {
+                text: text.to_string(),
+            }],
+        }
+    }
+}
+
+/// A tool (or agent) the model may call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolSchema {
+    pub name: String,
+    pub description: String,
+    pub input_schema: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmRequest {
+    pub system: String,
+    pub messages: Vec<Message>,
+    pub tools: Vec<ToolSchema>,
+}
+
+/// Why the model stopped generating
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    EndTurn,
+    ToolUse,
+    MaxTokens,
+    StopSequence,
+    /// The model declined to answer
+    Refusal,
+    Other(String),
+}
+
+/// Tokens billed for a response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Usage {
+    /// Uncached input tokens
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Input tokens written to the prompt cache
+    pub cache_creation_input_tokens: u64,
+    /// Input tokens read from the prompt cache
+    pub cache_read_input_tokens: u64,
+}
+
+impl Usage {
+    /// All input, output and cache tokens
+    pub fn total(&self) -> u64 {
+        self.input_tokens
+            + self.output_tokens
+            + self.cache_creation_input_tokens
+            + self.cache_read_input_tokens
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmResponse {
+    pub content: Vec<ContentBlock>,
+    pub stop_reason: Option<StopReason>,
+    #[serde(default)]
+    pub usage: Usage,
+    /// Response body as returned by the provider, kept for debugging
+    pub raw: Value,
+}
+
+impl LlmResponse {
+    /// All text blocks joined by blank lines
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// True if the response contains at least one tool_use block
+    pub fn has_tool_calls(&self) -> bool {
+        self.content
+            .iter()
+            .any(|block| matches!(block, ContentBlock::ToolUse { .. }))
+    }
+}
+
+/// A chat model backend: sends messages plus tool schemas, returns text and tool calls
+#[async_trait::async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Provider name for logs
+    fn name(&self) -> &str;
+
+    /// Model used for requests
+    fn model(&self) -> &str;
+
+    async fn send(&self, request: &LlmRequest) -> Result<LlmResponse>;
+
+    /// Send a request and report the response through `on_event` as it is generated.
+    /// Providers that can't stream report the complete response once it arrives.
+    async fn send_streaming(
+        &self,
+        request: &LlmRequest,
+        on_event: &OnEvent<'_>,
+    ) -> Result<LlmResponse> {
+        let response = self.send(request).await?;
+        for event in stream::response_events(&response) {
+            on_event(&event);
+        }
+        Ok(response)
+    }
+}
+
+/// Create the provider selected by `config.provider`, recording to or replaying from a cassette
+/// if one is configured
+pub fn create_provider(config: &ClaudeConfig) -> Result<Arc<dyn LlmProvider>> {
+    if let Some(cassette) = &config.cassette {
+        if cassette.mode == CassetteMode::Replay {
+            return Ok(ReplayProvider::shared(cassette)?);
+        }
+    }
+
+    let provider: Arc<dyn LlmProvider> = match config.provider {
+        ProviderKind::Anthropic => Arc::new(AnthropicProvider::new(config.clone())),
+        ProviderKind::OpenAi => Arc::new(OpenAiProvider::new(config.clone())),
+        ProviderKind::Scripted => {
+            let path = config.script_path.as_ref().ok_or_else(|| {
+                anyhow::anyhow!("LLM_SCRIPT must be set when LLM_PROVIDER is scripted")
+            })?;
+            ScriptedProvider::shared(path)?
+        }
+    };
+
+    match &config.cassette {
+        Some(cassette) => Ok(Arc::new(RecordingProvider::new(provider, &cassette.path)?)),
+        None => Ok(provider),
+    }
+}
@@ -0,0 +1,239 @@
+use super::{
+    ContentBlock, LlmProvider, LlmRequest, LlmResponse, Message, Role, StopReason, Usage,
+};
+use crate::retry::{self, ApiRequest};
+use crate::ClaudeConfig;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+pub const DEFAULT_API_URL: &str = "https://api.openai.com/v1/chat/completions";
+pub const DEFAULT_MODEL: &str = "gpt-4o";
+
+/// OpenAI-compatible chat completions API, as served by OpenAI, llama.cpp, vLLM or Ollama
+pub struct OpenAiProvider {
+    config: ClaudeConfig,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: ClaudeConfig) -> Self {
+        Self { config }
+    }
+
+    fn build_payload(&self, request: &LlmRequest) -> Value {
+        let mut messages = vec![json!({
+            "role": "system",
+            "content": request.system
+        })];
+        for message in &request.messages {
+            messages.extend(message_to_json(message));
+        }
+
+        let mut payload = json!({
+            "model": self.config.model,
+            "max_tokens": self.config.max_tokens,
+            "temperature": self.config.temperature,
+            "messages": messages
+        });
+
+        // Some servers reject an empty tools array
+        if !request.tools.is_empty() {
+            payload["tools"] = request
+                .tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.name,
+                            "description": tool.description,
+                            "parameters": tool.input_schema
+                        }
+                    })
+                })
+                .collect();
+        }
+
+        payload
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    async fn send(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        let payload = self.build_payload(request);
+        let mut headers = Vec::new();
+        // Local model servers usually don't need a key
+        if !self.config.api_key.is_empty() {
+            headers.push(("authorization", format!("Bearer {}", self.config.api_key)));
+        }
+
+        let raw = retry::send_with_retry(
+            &ApiRequest {
+                client: &self.config.client,
+                service: "OpenAI-compatible API",
+                url: &self.config.api_url,
+                headers: &headers,
+                payload: &payload,
+                timeout: Some(Duration::from_secs(self.config.timeout_seconds)),
+            },
+            &self.config.retry,
+        )
+        .await?;
+
+        parse_response(raw)
+    }
+}
+
+/// Convert one message. Tool results become separate `tool` role messages.
+fn message_to_json(message: &Message) -> Vec<Value> {
+    let text = message
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    match message.role {
+        Role::Assistant => {
+            let tool_calls: Vec<Value> = message
+                .content
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::ToolUse { id, name, input } => Some(json!({
+                        "id": id,
+                        "type": "function",
+                        "function": {
+                            "name": name,
+                            "arguments": input.to_string()
+                        }
+                    })),
+                    _ => None,
+                })
+                .collect();
+
+            let mut assistant = json!({
+                "role": "assistant",
+                "content": if text.is_empty() { Value::Null } else { Value::String(text) }
+            });
+            if !tool_calls.is_empty() {
+                assistant["tool_calls"] = Value::Array(tool_calls);
+            }
+            vec![assistant]
+        }
+        Role::User => {
+            let mut messages: Vec<Value> = message
+                .content
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        ..
+                    } => Some(json!({
+                        "role": "tool",
+                        "tool_call_id": tool_use_id,
+                        "content": content
+                    })),
+                    _ => None,
+                })
+                .collect();
+            if !text.is_empty() {
+                messages.push(json!({
+                    "role": "user",
+                    "content": text
+                }));
+            }
+            messages
+        }
+    }
+}
+
+fn parse_response(raw: Value) -> Result<LlmResponse> {
+    let choice = raw
+        .get("choices")
+        .and_then(|c| c.get(0))
+        .ok_or_else(|| anyhow::anyhow!("OpenAI-compatible API response has no choices: {}", raw))?;
+    let message = choice.get("message").cloned().unwrap_or(Value::Null);
+
+    let mut content = Vec::new();
+    if let Some(text) = message.get("content").and_then(|c| c.as_str()) {
+        if !text.is_empty() {
+            content.push(ContentBlock::Text {
+                text: text.to_string(),
+            });
+        }
+    }
+
+    for call in message
+        .get("tool_calls")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let id = call.get("id").and_then(|id| id.as_str());
+        let function = call.get("function");
+        let name = function.and_then(|f| f.get("name")).and_then(|n| n.as_str());
+        let (Some(id), Some(name)) = (id, name) else {
+            continue;
+        };
+
+        // Arguments arrive as a JSON string. Unparseable arguments are passed through as a
+        // string so the tool reports the error to the model.
+        let arguments = function
+            .and_then(|f| f.get("arguments"))
+            .and_then(|a| a.as_str())
+            .unwrap_or("{}");
+        let input = serde_json::from_str(arguments)
+            .unwrap_or_else(|_| Value::String(arguments.to_string()));
+
+        content.push(ContentBlock::ToolUse {
+            id: id.to_string(),
+            name: name.to_string(),
+            input,
+        });
+    }
+
+    let stop_reason = choice
+        .get("finish_reason")
+        .and_then(|r| r.as_str())
+        .map(|reason| match reason {
+            "stop" => StopReason::EndTurn,
+            "tool_calls" | "function_call" => StopReason::ToolUse,
+            "length" => StopReason::MaxTokens,
+            "content_filter" => StopReason::Refusal,
+            other => StopReason::Other(other.to_string()),
+        });
+
+    // prompt_tokens includes cached tokens
+    let usage = raw.get("usage").cloned().unwrap_or_default();
+    let prompt_tokens = usage.get("prompt_tokens").and_then(|t| t.as_u64()).unwrap_or(0);
+    let cached_tokens = usage
+        .pointer("/prompt_tokens_details/cached_tokens")
+        .and_then(|t| t.as_u64())
+        .unwrap_or(0);
+    let usage = Usage {
+        input_tokens: prompt_tokens.saturating_sub(cached_tokens),
+        output_tokens: usage.get("completion_tokens").and_then(|t| t.as_u64()).unwrap_or(0),
+        cache_creation_input_tokens: 0,
+        cache_read_input_tokens: cached_tokens,
+    };
+
+    Ok(LlmResponse {
+        content,
+        stop_reason,
+        usage,
+        raw,
+    })
+}
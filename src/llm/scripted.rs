@@ -0,0 +1,153 @@
+use super::{ContentBlock, LlmProvider, LlmRequest, LlmResponse, StopReason, Usage};
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Providers loaded from script files, shared so that every agent in the process consumes the
+/// same sequence of turns
+static SHARED: OnceLock<Mutex<HashMap<PathBuf, Arc<ScriptedProvider>>>> = OnceLock::new();
+
+/// Offline provider that replays a predefined sequence of assistant turns and records the
+/// requests it receives. Used for deterministic tests without an API key.
+///
+/// Select it with `LLM_PROVIDER=scripted` and point `LLM_SCRIPT` at a JSON file holding an
+/// array of turns in Anthropic response format:
+///
+/// ```json
+/// [
+///   {"content": [{"type": "tool_use", "id": "t1", "name": "read", "input": {"file_path": "a.txt"}}]},
+///   {"content": [{"type": "text", "text": "Done"}]}
+/// ]
+/// ```
+pub struct ScriptedProvider {
+    turns: Mutex<VecDeque<LlmResponse>>,
+    requests: Mutex<Vec<LlmRequest>>,
+}
+
+impl ScriptedProvider {
+    pub fn new(turns: Vec<LlmResponse>) -> Self {
+        Self {
+            turns: Mutex::new(turns.into()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Load turns from a JSON script file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!("Failed to read LLM script {}: {}", path.display(), e)
+        })?;
+        let turns: Vec<Value> = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid LLM script {}: {}", path.display(), e))?;
+
+        let turns = turns
+            .into_iter()
+            .enumerate()
+            .map(|(i, turn)| {
+                super::anthropic::parse_response(turn).map_err(|e| {
+                    anyhow::anyhow!("Invalid turn #{} in LLM script {}: {}", i + 1, path.display(), e)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self::new(turns))
+    }
+
+    /// Provider for a script file, shared by every caller in this process
+    pub fn shared(path: &Path) -> Result<Arc<Self>> {
+        let mut shared = SHARED
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap();
+
+        if let Some(provider) = shared.get(path) {
+            return Ok(provider.clone());
+        }
+
+        let provider = Arc::new(Self::from_file(path)?);
+        shared.insert(path.to_path_buf(), provider.clone());
+        Ok(provider)
+    }
+
+    /// Requests received so far, in order
+    pub fn requests(&self) -> Vec<LlmRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Turns not yet replayed
+    pub fn remaining(&self) -> usize {
+        self.turns.lock().unwrap().len()
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmProvider for ScriptedProvider {
+    fn name(&self) -> &str {
+        "scripted"
+    }
+
+    fn model(&self) -> &str {
+        "scripted"
+    }
+
+    async fn send(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        let received = {
+            let mut requests = self.requests.lock().unwrap();
+            requests.push(request.clone());
+            requests.len()
+        };
+
+        self.turns.lock().unwrap().pop_front().ok_or_else(|| {
+            anyhow::anyhow!(
+                "Scripted provider has no turn left for request #{}",
+                received
+            )
+        })
+    }
+}
+
+/// Scripted turn that answers with text and ends the conversation
+pub fn text_turn(text: &str) -> LlmResponse {
+    LlmResponse {
+        content: vec![ContentBlock::Text {
+            text: text.to_string(),
+        }],
+        stop_reason: Some(StopReason::EndTurn),
+        usage: Usage::default(),
+        raw: json!({
+            "content": [{"type": "text", "text": text}],
+            "stop_reason": "end_turn"
+        }),
+    }
+}
+
+/// Scripted turn that calls tools, given as (id, name, input)
+pub fn tool_use_turn(calls: &[(&str, &str, Value)]) -> LlmResponse {
+    let content: Vec<ContentBlock> = calls
+        .iter()
+        .map(|(id, name, input)| ContentBlock::ToolUse {
+            id: id.to_string(),
+            name: name.to_string(),
+            input: input.clone(),
+        })
+        .collect();
+    let raw_content: Vec<Value> = calls
+        .iter()
+        .map(|(id, name, input)| {
+            json!({"type": "tool_use", "id": id, "name": name, "input": input})
+        })
+        .collect();
+
+    LlmResponse {
+        content,
+        stop_reason: Some(StopReason::ToolUse),
+        usage: Usage::default(),
+        raw: json!({
+            "content": raw_content,
+            "stop_reason": "tool_use"
+        }),
+    }
+}
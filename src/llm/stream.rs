@@ -0,0 +1,104 @@
+use super::{ContentBlock, LlmResponse, StopReason};
+
+/// Incremental output of a model response, delivered while it is generated
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    /// A piece of assistant text
+    TextDelta { text: String },
+    /// The model started a tool call
+    ToolUseStart { id: String, name: String },
+    /// A fragment of a tool call's JSON input
+    ToolInputDelta { id: String, partial_json: String },
+    /// The response is complete
+    MessageStop { stop_reason: Option<StopReason> },
+}
+
+/// Callback receiving a response's stream events
+pub type OnEvent<'a> = dyn Fn(&StreamEvent) + Send + Sync + 'a;
+
+/// Events describing a complete response, for providers that don't stream
+pub fn response_events(response: &LlmResponse) -> Vec<StreamEvent> {
+    let mut events = Vec::new();
+    for block in &response.content {
+        match block {
+            ContentBlock::Text { text } => {
+                events.push(StreamEvent::TextDelta { text: text.clone() })
+            }
+            ContentBlock::ToolUse { id, name, input } => {
+                events.push(StreamEvent::ToolUseStart {
+                    id: id.clone(),
+                    name: name.clone(),
+                });
+                events.push(StreamEvent::ToolInputDelta {
+                    id: id.clone(),
+                    partial_json: input.to_string(),
+                });
+            }
+            ContentBlock::ToolResult { .. } => {}
+        }
+    }
+    events.push(StreamEvent::MessageStop {
+        stop_reason: response.stop_reason.clone(),
+    });
+    events
+}
+
+/// A server-sent event
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    /// The `event:` field, if any
+    pub event: Option<String>,
+    /// All `data:` lines, joined by newlines
+    pub data: String,
+}
+
+/// Splits a `text/event-stream` body into events. Chunks may end anywhere, including in the
+/// middle of a line or a UTF-8 character.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Feed the next chunk of the body and return the events it completes
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let mut line: Vec<u8> = self.buffer.drain(..=end).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            let line = String::from_utf8_lossy(&line);
+
+            if line.is_empty() {
+                // A blank line dispatches the event
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: self.event.take(),
+                        data: self.data.join("\n"),
+                    });
+                }
+                self.event = None;
+                self.data.clear();
+                continue;
+            }
+            if line.starts_with(':') {
+                continue;
+            }
+
+            let (field, value) = line.split_once(':').unwrap_or((&line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+        events
+    }
+}
@@ -1,7 +1,7 @@
 // Binary target required for cargo run
 // This binary can call library functions when RAWORC_HANDLER is set
 
-use file_agent::process_message_sync;
+use file_agent::{process_message_sync, resume_sync};
 use serde_json::{json, Value};
 
 fn main() {
@@ -23,6 +23,21 @@ fn main() {
             println!("{}", response);
             return;
         }
+
+        if handler == "lib.resume_sync" {
+            // The session to resume is the first argument
+            let args: Vec<String> = std::env::args().collect();
+            let session_id = if args.len() > 1 { &args[1] } else { "" };
+
+            let context: Value = std::env::var("AGENT_CONTEXT")
+                .ok()
+                .and_then(|context_str| serde_json::from_str(&context_str).ok())
+                .unwrap_or_else(|| json!({"space": "default"}));
+
+            let response = resume_sync(session_id, &context);
+            println!("{}", response);
+            return;
+        }
     }
 
     // Standalone execution (not called by Raworc)
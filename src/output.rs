@@ -0,0 +1,95 @@
+/// Shortens a tool's output before it is sent back to the model. Tools supply one through
+/// `Tool::output_processor`; outputs are shortened only there, so each is cut once.
+pub trait OutputProcessor: Send + Sync {
+    /// Lines kept as they are, unless `[agents.<name>.context.lines]` sets the tool's threshold
+    fn max_lines(&self) -> usize;
+
+    /// Shorten an output with more than `max_lines` lines
+    fn shorten(&self, output: &str, max_lines: usize) -> String;
+}
+
+/// Keeps the first and last lines of an output, e.g. a listing or a command's output
+#[derive(Debug, Clone, Copy)]
+pub struct Excerpt {
+    pub max_lines: usize,
+    /// Share of the kept lines taken from the end, in percent
+    pub tail_percent: usize,
+}
+
+impl Excerpt {
+    /// Listings and search results, where the first entries matter most
+    pub const LISTING: Excerpt = Excerpt {
+        max_lines: 100,
+        tail_percent: 20,
+    };
+
+    /// Command output, where errors and summaries are usually at the end
+    pub const COMMAND: Excerpt = Excerpt {
+        max_lines: 200,
+        tail_percent: 50,
+    };
+}
+
+impl OutputProcessor for Excerpt {
+    fn max_lines(&self) -> usize {
+        self.max_lines
+    }
+
+    fn shorten(&self, output: &str, max_lines: usize) -> String {
+        let lines: Vec<&str> = output.lines().collect();
+        if lines.len() <= max_lines {
+            return output.to_string();
+        }
+
+        let tail = max_lines * self.tail_percent.min(100) / 100;
+        let head = max_lines - tail;
+        let mut result = lines[..head].join("\n");
+        result.push_str(&format!(
+            "\n\n... [TRUNCATED {} of {} lines] ...\n\n",
+            lines.len() - head - tail,
+            lines.len()
+        ));
+        result.push_str(&lines[lines.len() - tail..].join("\n"));
+        result
+    }
+}
+
+/// Keeps the beginning, middle and end of a file
+#[derive(Debug, Clone, Copy)]
+pub struct FileSample {
+    pub max_lines: usize,
+}
+
+/// Lines shown of each part of a sampled file
+const SAMPLE_SECTION_LINES: usize = 100;
+
+impl OutputProcessor for FileSample {
+    fn max_lines(&self) -> usize {
+        self.max_lines
+    }
+
+    fn shorten(&self, output: &str, max_lines: usize) -> String {
+        let lines: Vec<&str> = output.lines().collect();
+        if lines.len() <= max_lines {
+            return output.to_string();
+        }
+
+        let section = SAMPLE_SECTION_LINES.min(max_lines / 3).max(1);
+        let middle_start = (lines.len() / 2).saturating_sub(section / 2);
+        let end_start = lines.len() - section;
+        let part = |start: usize, end: usize| lines[start..end].join("\n");
+
+        format!(
+            "=== FILE PREVIEW (Large output: {} lines) ===\n\nBEGINNING (first {} lines):\n{}\n\nMIDDLE (around line {}):\n{}\n\nEND (last {} lines):\n{}\n\n=== Shown: {} of {} lines. Use offset/limit to read specific sections ===",
+            lines.len(),
+            section,
+            part(0, section),
+            lines.len() / 2,
+            part(middle_start, middle_start + section),
+            section,
+            part(end_start, lines.len()),
+            section * 3,
+            lines.len()
+        )
+    }
+}
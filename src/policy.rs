@@ -0,0 +1,339 @@
+use crate::config;
+use crate::shell_parser;
+use anyhow::Result;
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+use serde_json::Value;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Policy file names looked up in the user config dir
+const USER_POLICY_FILE_NAMES: &[&str] = &["policy.toml", "policy.json"];
+
+/// Policy file names looked up next to `raworc.json`
+const POLICY_FILE_NAMES: &[&str] = &["file-agent-policy.toml", "file-agent-policy.json"];
+
+/// Outcome of a policy rule. Ordered from least to most restrictive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Allow,
+    /// Requires approval. There is no interactive approval channel in Raworc sessions, so the
+    /// call is rejected with a message telling the model that approval is needed.
+    Ask,
+    Deny,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decision::Allow => write!(f, "allow"),
+            Decision::Ask => write!(f, "ask"),
+            Decision::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+/// A single allow/deny/ask rule. Every condition that is set must match for the rule to apply.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PolicyRule {
+    /// Tool name glob, e.g. "write" or "bash*"
+    #[serde(default)]
+    pub tool: Option<String>,
+    /// Glob matched against the `file_path`/`path` argument. `*` stops at `/`, `**` matches
+    /// any number of directories.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Prefix matched word by word against the bash `command` argument, and against every
+    /// command it runs, e.g. the `git push` in `cd repo && sudo git push`
+    #[serde(default)]
+    pub command_prefix: Option<String>,
+    pub decision: Decision,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PolicyFile {
+    #[serde(default = "default_decision")]
+    default: Decision,
+    #[serde(default)]
+    rules: Vec<PolicyRule>,
+}
+
+fn default_decision() -> Decision {
+    Decision::Allow
+}
+
+/// Result of evaluating a tool call against the policy
+#[derive(Debug, Clone)]
+pub struct PolicyOutcome {
+    pub decision: Decision,
+    pub reason: String,
+}
+
+/// Declarative permission policy evaluated before every tool call.
+///
+/// Loaded from the file named by `workspace.policy` (`FILE_AGENT_POLICY`), or from
+/// `policy.toml` / `policy.json` in `$XDG_CONFIG_HOME/file-agent`, or from
+/// `file-agent-policy.toml` / `file-agent-policy.json` next to `raworc.json`. Without a policy
+/// file every call is allowed. The file must be outside the workspace.
+/// When several rules match, the most restrictive decision wins.
+///
+/// ```toml
+/// default = "allow"
+///
+/// [[rules]]
+/// tool = "bash"
+/// command_prefix = "git push"
+/// decision = "ask"
+/// reason = "Pushing requires approval"
+///
+/// [[rules]]
+/// tool = "write"
+/// path = "**/.env*"
+/// decision = "deny"
+/// ```
+#[derive(Debug, Clone)]
+pub struct PermissionPolicy {
+    default: Decision,
+    rules: Vec<PolicyRule>,
+    /// Used to match relative path arguments and path globs against each other
+    workspace_root: PathBuf,
+    source: Option<PathBuf>,
+}
+
+impl PermissionPolicy {
+    /// Policy that allows every call
+    pub fn allow_all(workspace_root: &Path) -> Self {
+        Self {
+            default: Decision::Allow,
+            rules: Vec::new(),
+            workspace_root: workspace_root.to_path_buf(),
+            source: None,
+        }
+    }
+
+    /// Load the policy file at `path`, or look for one when no path is set. Without a policy
+    /// file every call is allowed. A policy file inside the workspace is refused, since the
+    /// agent could rewrite it.
+    pub fn load(path: Option<&Path>, workspace_root: &Path) -> Result<Self> {
+        match path.map(Path::to_path_buf).or_else(Self::find_policy_file) {
+            Some(path) => {
+                let canonical =
+                    |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+                if canonical(&path).starts_with(canonical(workspace_root)) {
+                    return Err(anyhow::anyhow!(
+                        "Policy file {} is inside the workspace {}, where the agent can change it. Move it outside the workspace.",
+                        path.display(),
+                        workspace_root.display()
+                    ));
+                }
+                Self::from_file(&path, workspace_root)
+            }
+            None => {
+                log::info!("No permission policy file found, allowing all tool calls");
+                Ok(Self::allow_all(workspace_root))
+            }
+        }
+    }
+
+    /// Load a policy from a TOML or JSON file (chosen by extension)
+    pub fn from_file(path: &Path, workspace_root: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!("Failed to read policy file {}: {}", path.display(), e)
+        })?;
+
+        let file: PolicyFile = if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+            serde_json::from_str(&content).map_err(|e| {
+                anyhow::anyhow!("Invalid policy file {}: {}", path.display(), e)
+            })?
+        } else {
+            toml::from_str(&content).map_err(|e| {
+                anyhow::anyhow!("Invalid policy file {}: {}", path.display(), e)
+            })?
+        };
+
+        // Reject bad globs up front instead of silently never matching
+        for (i, rule) in file.rules.iter().enumerate() {
+            for pattern in [&rule.tool, &rule.path].into_iter().flatten() {
+                Pattern::new(pattern).map_err(|e| {
+                    anyhow::anyhow!(
+                        "Invalid glob '{}' in policy rule #{} of {}: {}",
+                        pattern,
+                        i + 1,
+                        path.display(),
+                        e
+                    )
+                })?;
+            }
+        }
+
+        log::info!(
+            "Loaded permission policy from {} ({} rules, default: {})",
+            path.display(),
+            file.rules.len(),
+            file.default
+        );
+
+        Ok(Self {
+            default: file.default,
+            rules: file.rules,
+            workspace_root: workspace_root.to_path_buf(),
+            source: Some(path.to_path_buf()),
+        })
+    }
+
+    /// `policy.toml` / `policy.json` in the user config dir, else a policy file next to
+    /// `raworc.json`
+    fn find_policy_file() -> Option<PathBuf> {
+        let user_files = config::user_config_dir()
+            .into_iter()
+            .flat_map(|dir| USER_POLICY_FILE_NAMES.iter().map(move |name| dir.join(name)));
+        let raworc_files = env::current_dir()
+            .ok()
+            .filter(|dir| dir.join("raworc.json").exists())
+            .into_iter()
+            .flat_map(|dir| POLICY_FILE_NAMES.iter().map(move |name| dir.join(name)));
+
+        user_files.chain(raworc_files).find(|path| path.exists())
+    }
+
+    /// Evaluate a tool call and log the decision
+    pub fn evaluate(&self, tool_name: &str, arguments: &str) -> PolicyOutcome {
+        let args: Value = serde_json::from_str(arguments).unwrap_or(Value::Null);
+
+        let mut outcome = PolicyOutcome {
+            decision: self.default,
+            reason: "default policy".to_string(),
+        };
+        let mut matched_any = false;
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            if !self.rule_matches(rule, tool_name, &args) {
+                continue;
+            }
+
+            if !matched_any || rule.decision > outcome.decision {
+                outcome = PolicyOutcome {
+                    decision: rule.decision,
+                    reason: rule
+                        .reason
+                        .clone()
+                        .unwrap_or_else(|| format!("policy rule #{}", i + 1)),
+                };
+            }
+            matched_any = true;
+        }
+
+        let source = self
+            .source
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|| "built-in".to_string());
+        match outcome.decision {
+            Decision::Allow => log::info!(
+                "Policy decision: allow {} ({}; {})",
+                tool_name,
+                outcome.reason,
+                source
+            ),
+            decision => log::warn!(
+                "Policy decision: {} {} with args {} ({}; {})",
+                decision,
+                tool_name,
+                arguments,
+                outcome.reason,
+                source
+            ),
+        }
+
+        outcome
+    }
+
+    fn rule_matches(&self, rule: &PolicyRule, tool_name: &str, args: &Value) -> bool {
+        if let Some(tool) = &rule.tool {
+            if !glob_matches(tool, tool_name) {
+                return false;
+            }
+        }
+
+        if let Some(pattern) = &rule.path {
+            let path = args
+                .get("file_path")
+                .or_else(|| args.get("path"))
+                .and_then(|p| p.as_str());
+            match path {
+                Some(path) if self.path_matches(pattern, path) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(prefix) = &rule.command_prefix {
+            match args.get("command").and_then(|c| c.as_str()) {
+                Some(command) if command_has_prefix(command, prefix) => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Match the path as given, as an absolute path, and relative to the workspace root.
+    /// `.` and `..` are resolved first, so `docs/../.env` can't slip past a rule for `.env`.
+    fn path_matches(&self, pattern: &str, path: &str) -> bool {
+        let absolute = normalize(&self.workspace_root.join(path));
+        let relative = absolute.strip_prefix(&self.workspace_root).ok();
+
+        glob_matches(pattern, path)
+            || glob_matches(pattern, &absolute.to_string_lossy())
+            || relative.is_some_and(|rel| glob_matches(pattern, &rel.to_string_lossy()))
+    }
+}
+
+fn glob_matches(pattern: &str, value: &str) -> bool {
+    Pattern::new(pattern)
+        .map(|p| {
+            p.matches_with(
+                value,
+                MatchOptions {
+                    require_literal_separator: true,
+                    ..Default::default()
+                },
+            )
+        })
+        .unwrap_or(false)
+}
+
+/// Word-wise prefix match, so "rm  -rf x" matches "rm -rf" but "git pushy" doesn't match "git push".
+/// Every command the command line runs is matched as well.
+fn command_has_prefix(command: &str, prefix: &str) -> bool {
+    let prefix: Vec<&str> = prefix.split_whitespace().collect();
+    let starts_with = |words: &mut dyn Iterator<Item = &str>| {
+        prefix.iter().all(|expected| words.next() == Some(*expected))
+    };
+
+    starts_with(&mut command.split_whitespace())
+        || shell_parser::invocations(command).is_some_and(|invocations| {
+            invocations
+                .iter()
+                .any(|words| starts_with(&mut words.iter().map(String::as_str)))
+        })
+}
+
+/// Resolve `.` and `..` components without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,231 @@
+use anyhow::Result;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Retry settings for LLM API requests
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Retries after the first attempt. 0 disables retrying.
+    pub max_retries: u32,
+    /// Backoff before the first retry. Doubles on every further attempt.
+    pub base_delay_ms: u64,
+    /// Upper bound for every delay, including a `retry-after` from the server
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            base_delay_ms: 1_000,
+            max_delay_ms: 60_000,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Jittered exponential backoff for a 1-based retry attempt: a random delay between half
+    /// and all of `base * 2^(attempt - 1)`, capped at `max_delay_ms`
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay_ms
+            .saturating_mul(1u64 << attempt.saturating_sub(1).min(32));
+        let capped = exponential.min(self.max_delay_ms);
+        let half = capped / 2;
+        let jitter = if half == 0 { 0 } else { random_u64() % (half + 1) };
+        Duration::from_millis(half + jitter)
+    }
+
+    /// Delay before a 1-based retry attempt: the server's `retry-after` when it gave one, else
+    /// the backoff. Never longer than `max_delay_ms`, so a server can't stall the agent.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let max_delay = Duration::from_millis(self.max_delay_ms);
+        match retry_after {
+            Some(retry_after) => retry_after.min(max_delay),
+            None => self.backoff(attempt),
+        }
+    }
+}
+
+/// A JSON POST request to an LLM API
+pub struct ApiRequest<'a> {
+    pub client: &'a Client,
+    /// Name used in errors and logs, e.g. "Claude API"
+    pub service: &'a str,
+    pub url: &'a str,
+    pub headers: &'a [(&'a str, String)],
+    pub payload: &'a Value,
+    /// Limit for the whole request, including reading the body. Streamed requests leave it unset
+    /// and rely on the client's read timeout between chunks.
+    pub timeout: Option<Duration>,
+}
+
+/// POST a request, retrying transient failures.
+///
+/// Connect errors, timeouts and 429 and 5xx (including 529 "overloaded") responses are retried
+/// with jittered exponential backoff, or after the server's `retry-after` when one is given.
+/// Other errors, including responses that can't be read or decoded, are returned immediately.
+pub async fn send_with_retry(request: &ApiRequest<'_>, retry: &RetryConfig) -> Result<Value> {
+    with_retry(request, retry, || send_once(request)).await
+}
+
+/// POST a request, retrying transient failures like `send_with_retry`, and return the successful
+/// response without reading its body. Used for streamed responses.
+pub async fn open_with_retry(request: &ApiRequest<'_>, retry: &RetryConfig) -> Result<Response> {
+    with_retry(request, retry, || open_once(request)).await
+}
+
+async fn with_retry<T, F, Fut>(request: &ApiRequest<'_>, retry: &RetryConfig, send: F) -> Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, RequestError>>,
+{
+    let mut attempt = 0;
+
+    loop {
+        let (error, retry_after) = match send().await {
+            Ok(response) => return Ok(response),
+            Err(RequestError::Fatal(e)) => return Err(e),
+            Err(RequestError::Transient { error, retry_after }) => (error, retry_after),
+        };
+
+        attempt += 1;
+        if attempt > retry.max_retries {
+            if retry.max_retries > 0 {
+                log::error!(
+                    "{} request failed after {} retries: {}",
+                    request.service,
+                    retry.max_retries,
+                    error
+                );
+            }
+            return Err(error);
+        }
+
+        let delay = retry.delay(attempt, retry_after);
+        log::warn!(
+            "{} request failed: {}. Retrying in {:?} (attempt {}/{})",
+            request.service,
+            error,
+            delay,
+            attempt,
+            retry.max_retries
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+enum RequestError {
+    Transient {
+        error: anyhow::Error,
+        retry_after: Option<Duration>,
+    },
+    Fatal(anyhow::Error),
+}
+
+async fn send_once(request: &ApiRequest<'_>) -> Result<Value, RequestError> {
+    let body = open_once(request)
+        .await?
+        .bytes()
+        .await
+        // reqwest reports a connection lost mid-body as a decode error, but nothing is
+        // decoded yet, so every failure here is a transfer failure
+        .map_err(|e| RequestError::Transient {
+            error: anyhow::anyhow!("{} response was cut off: {}", request.service, e),
+            retry_after: None,
+        })?;
+    serde_json::from_slice(&body).map_err(|e| {
+        RequestError::Fatal(anyhow::anyhow!(
+            "{} returned an invalid response body: {}",
+            request.service,
+            e
+        ))
+    })
+}
+
+/// Send the request and check the status, leaving the body unread
+async fn open_once(request: &ApiRequest<'_>) -> Result<Response, RequestError> {
+    let mut builder = request
+        .client
+        .post(request.url)
+        .header("content-type", "application/json");
+    for (name, value) in request.headers {
+        builder = builder.header(*name, value);
+    }
+    if let Some(timeout) = request.timeout {
+        builder = builder.timeout(timeout);
+    }
+
+    let response = builder
+        .json(request.payload)
+        .send()
+        .await
+        .map_err(|e| classify_network_error(request.service, e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let retry_after = parse_retry_after(response.headers());
+        let error_text = response.text().await.unwrap_or_default();
+        let error = anyhow::anyhow!("{} error ({}): {}", request.service, status, error_text);
+
+        return Err(if is_retryable_status(status) {
+            RequestError::Transient { error, retry_after }
+        } else {
+            RequestError::Fatal(error)
+        });
+    }
+
+    Ok(response)
+}
+
+/// 408, 429 and every 5xx, which includes 529 "overloaded"
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status.is_server_error()
+}
+
+fn classify_network_error(service: &str, e: reqwest::Error) -> RequestError {
+    let error = anyhow::anyhow!("{} request failed: {}", service, e);
+    // Failures to send the request or read the response body are transient; a body that
+    // arrived but isn't valid JSON will be the same on every attempt
+    if e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() {
+        RequestError::Transient {
+            error,
+            retry_after: None,
+        }
+    } else {
+        RequestError::Fatal(error)
+    }
+}
+
+/// `retry-after` as delay-seconds or an HTTP date
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<f64>() {
+        return (seconds.is_finite() && seconds >= 0.0).then(|| Duration::from_secs_f64(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    hasher.finish()
+}
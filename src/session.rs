@@ -0,0 +1,168 @@
+use crate::checkpoint::{self, JsonlCheckpoint};
+use crate::llm::Message;
+use anyhow::Result;
+use serde_json::Value;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+/// Used when the Raworc context has no `session_id`
+pub const DEFAULT_SESSION_ID: &str = "default";
+/// Used when the Raworc context has no `space`
+pub const DEFAULT_SPACE: &str = "default";
+
+const CHECKPOINT_FILE: &str = "conversation.jsonl";
+/// Checkpoints of the conversations agents run for the orchestrator's calls
+const DELEGATIONS_DIR: &str = "delegations";
+
+tokio::task_local! {
+    /// Session whose task is being processed
+    static CURRENT: Session;
+}
+
+/// A Raworc session: the checkpointed conversations of the orchestrator and of the agents it
+/// delegates to, and the message logs of one `session_id`, namespaced by `space`. Everything
+/// lives in `<sessions dir>/<space>/<session_id>/`, so follow-up messages can continue where
+/// the last one ended.
+#[derive(Debug, Clone)]
+pub struct Session {
+    id: String,
+    space: String,
+    dir: PathBuf,
+}
+
+impl Session {
+    /// Open a session under `root`, creating its directory on first use
+    pub fn open(root: &Path, space: &str, id: &str) -> Result<Self> {
+        check_name("space", space)?;
+        check_name("session_id", id)?;
+
+        let dir = root.join(space).join(id);
+        fs::create_dir_all(&dir).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to create session directory {}: {}",
+                dir.display(),
+                e
+            )
+        })?;
+
+        Ok(Self {
+            id: id.to_string(),
+            space: space.to_string(),
+            dir,
+        })
+    }
+
+    /// Open the session named by the `session_id` and `space` of a Raworc context
+    pub fn from_context(root: &Path, context: &Value) -> Result<Self> {
+        let field = |name: &str, default: &'static str| match context.get(name) {
+            None | Some(Value::Null) => Ok(default.to_string()),
+            Some(Value::String(value)) if value.trim().is_empty() => Ok(default.to_string()),
+            Some(Value::String(value)) => Ok(value.clone()),
+            Some(other) => Err(anyhow::anyhow!(
+                "Invalid {} in Raworc context: expected a string, got {}",
+                name,
+                other
+            )),
+        };
+
+        Self::open(
+            root,
+            &field("space", DEFAULT_SPACE)?,
+            &field("session_id", DEFAULT_SESSION_ID)?,
+        )
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn space(&self) -> &str {
+        &self.space
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where the session's model responses and tool calls are logged
+    pub fn messages_dir(&self) -> PathBuf {
+        self.dir.join("messages")
+    }
+
+    /// The orchestrator conversation so far, empty for a new session. Calls a crash left
+    /// without a result are closed with an error result saying how far they got.
+    pub fn load_history(&self) -> Result<Vec<Message>> {
+        let entries = JsonlCheckpoint::load(&self.checkpoint_path())?;
+        Ok(checkpoint::rebuild(&entries))
+    }
+
+    /// Checkpoint the orchestrator conversation is recorded to as it runs
+    pub fn checkpoint(&self) -> Result<JsonlCheckpoint> {
+        JsonlCheckpoint::open(&self.checkpoint_path())
+    }
+
+    /// Calls the orchestrator had started when its task stopped, without a recorded result
+    pub fn interrupted_calls(&self) -> Result<Vec<String>> {
+        let entries = JsonlCheckpoint::load(&self.checkpoint_path())?;
+        Ok(checkpoint::interrupted_calls(&entries))
+    }
+
+    fn checkpoint_path(&self) -> PathBuf {
+        self.dir.join(CHECKPOINT_FILE)
+    }
+
+    /// The conversation an agent ran for the delegation `id` so far, empty if it never started
+    pub fn load_delegation(&self, id: &str) -> Result<Vec<Message>> {
+        let entries = JsonlCheckpoint::load(&self.delegation_path(id)?)?;
+        Ok(checkpoint::rebuild(&entries))
+    }
+
+    /// Whether an agent checkpointed a conversation for the delegation `id`
+    pub fn has_delegation(&self, id: &str) -> bool {
+        self.delegation_path(id).is_ok_and(|path| path.exists())
+    }
+
+    /// Checkpoint an agent's conversation for the delegation `id` is recorded to as it runs
+    pub fn delegation_checkpoint(&self, id: &str) -> Result<JsonlCheckpoint> {
+        JsonlCheckpoint::open(&self.delegation_path(id)?)
+    }
+
+    fn delegation_path(&self, id: &str) -> Result<PathBuf> {
+        check_name("delegation id", id)?;
+        Ok(self
+            .dir
+            .join(DELEGATIONS_DIR)
+            .join(format!("{}.jsonl", id)))
+    }
+
+    /// Run `future` as part of this session's task, so agents it delegates to can find it
+    pub async fn scope<F: Future>(&self, future: F) -> F::Output {
+        CURRENT.scope(self.clone(), future).await
+    }
+
+    /// Session whose task is currently being processed, if any
+    pub fn current() -> Option<Session> {
+        CURRENT.try_with(|session| session.clone()).ok()
+    }
+}
+
+/// Session IDs and spaces become directory names, so only allow safe characters
+fn check_name(field: &str, value: &str) -> Result<()> {
+    let valid = !value.is_empty()
+        && value.len() <= 128
+        && !value.starts_with('.')
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Invalid {} '{}': use up to 128 letters, digits, '-', '_' or '.', not starting with '.'",
+            field,
+            value
+        ))
+    }
+}
@@ -0,0 +1,1085 @@
+use anyhow::Result;
+
+/// Maximum nesting of command substitutions before parsing gives up
+const MAX_NESTING_DEPTH: usize = 16;
+
+/// Maximum depth of `sh -c` / `eval` nesting that is followed
+pub const MAX_SHELL_NESTING: usize = 8;
+
+/// Operators recognized by the tokenizer, longest first
+const OPERATORS: &[&str] = &[
+    "&>>", "<<-", "&&", "||", ";;", "|&", "&>", "<<", ">>", "<&", ">&", "<>", ">|", "&", "|", ";",
+    "(", ")", "<", ">",
+];
+
+/// A shell word after quote removal
+#[derive(Debug, Clone, Default)]
+pub struct Word {
+    /// Value with quotes removed. Expansions are kept verbatim (e.g. `$HOME`).
+    pub value: String,
+    /// True if the word contains parameter, command or arithmetic expansions
+    pub dynamic: bool,
+    /// True if any part of the word was quoted or escaped
+    pub quoted: bool,
+    /// Scripts run by `$(...)`, backtick and process substitutions inside the word
+    pub substitutions: Vec<Script>,
+}
+
+impl Word {
+    /// Plain unquoted literal, i.e. something that can be a reserved word
+    fn is_literal(&self, value: &str) -> bool {
+        !self.quoted && !self.dynamic && self.value == value
+    }
+}
+
+/// I/O redirection such as `> out.txt` or `2>&1`
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub operator: String,
+    pub target: Word,
+}
+
+/// `NAME=value ... program args ... redirects`
+#[derive(Debug, Clone, Default)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Word>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+    /// Source text of this command, used in error messages
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// Subshells, brace groups, `if`, `while`, `until`, `for` and `case`. `words` holds the
+    /// expanded words of `for`/`case` headers, whose substitutions also run.
+    Compound {
+        bodies: Vec<Script>,
+        words: Vec<Word>,
+        redirects: Vec<Redirect>,
+    },
+    FunctionDef {
+        name: String,
+        body: Box<Command>,
+    },
+}
+
+/// Commands connected with `|`
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+}
+
+/// A list of pipelines separated by `;`, `&`, `&&`, `||` or newlines
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    pub pipelines: Vec<Pipeline>,
+}
+
+impl Script {
+    /// Visit every pipeline, including those nested in compound commands and substitutions
+    pub fn visit_pipelines<'a>(&'a self, visit: &mut dyn FnMut(&'a Pipeline)) {
+        for pipeline in &self.pipelines {
+            visit(pipeline);
+            for command in &pipeline.commands {
+                command.visit_nested(visit);
+            }
+        }
+    }
+}
+
+impl Command {
+    /// Visit every pipeline nested inside this command
+    pub fn visit_nested<'a>(&'a self, visit: &mut dyn FnMut(&'a Pipeline)) {
+        match self {
+            Command::Simple(simple) => {
+                let redirect_targets = simple.redirects.iter().map(|r| &r.target);
+                for word in simple
+                    .assignments
+                    .iter()
+                    .chain(&simple.words)
+                    .chain(redirect_targets)
+                {
+                    for substitution in &word.substitutions {
+                        substitution.visit_pipelines(visit);
+                    }
+                }
+            }
+            Command::Compound {
+                bodies,
+                words,
+                redirects,
+            } => {
+                for body in bodies {
+                    body.visit_pipelines(visit);
+                }
+                for word in words.iter().chain(redirects.iter().map(|r| &r.target)) {
+                    for substitution in &word.substitutions {
+                        substitution.visit_pipelines(visit);
+                    }
+                }
+            }
+            Command::FunctionDef { body, .. } => body.visit_nested(visit),
+        }
+    }
+}
+
+/// Parse a POSIX shell command line
+pub fn parse(source: &str) -> Result<Script> {
+    parse_nested(source, 0)
+}
+
+fn parse_nested(source: &str, depth: usize) -> Result<Script> {
+    if depth > MAX_NESTING_DEPTH {
+        return Err(anyhow::anyhow!("command substitutions are nested too deeply"));
+    }
+
+    let mut lexer = Lexer::new(source, depth);
+    let tokens = lexer.tokenize()?;
+
+    let mut parser = Parser {
+        source,
+        tokens,
+        pos: 0,
+    };
+    let mut script = parser.parse_script(&[], &[])?;
+    if let Some(token) = parser.peek() {
+        return Err(anyhow::anyhow!(
+            "unexpected '{}' at offset {}",
+            &source[token.start..token.end],
+            token.start
+        ));
+    }
+
+    // Unquoted here-document bodies are expanded, so their substitutions run too
+    for substitution in lexer.heredoc_substitutions {
+        script.pipelines.extend(substitution.pipelines);
+    }
+
+    Ok(script)
+}
+
+#[derive(Debug, Clone)]
+enum TokenKind {
+    Word(Word),
+    Operator(String),
+    Newline,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+}
+
+struct PendingHeredoc {
+    delimiter: String,
+    strip_tabs: bool,
+    expand: bool,
+}
+
+struct Lexer<'a> {
+    source: &'a str,
+    chars: Vec<char>,
+    // Byte offset of each char, plus one past the end
+    offsets: Vec<usize>,
+    pos: usize,
+    depth: usize,
+    pending_heredocs: Vec<PendingHeredoc>,
+    heredoc_substitutions: Vec<Script>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str, depth: usize) -> Self {
+        let mut chars = Vec::new();
+        let mut offsets = Vec::new();
+        for (offset, c) in source.char_indices() {
+            offsets.push(offset);
+            chars.push(c);
+        }
+        offsets.push(source.len());
+
+        Self {
+            source,
+            chars,
+            offsets,
+            pos: 0,
+            depth,
+            pending_heredocs: Vec::new(),
+            heredoc_substitutions: Vec::new(),
+        }
+    }
+
+    fn peek_char(&self, ahead: usize) -> Option<char> {
+        self.chars.get(self.pos + ahead).copied()
+    }
+
+    fn tokenize(&mut self) -> Result<Vec<Token>> {
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut expecting_heredoc_delimiter: Option<bool> = None;
+
+        loop {
+            // Skip blanks and line continuations
+            while let Some(c) = self.peek_char(0) {
+                if c == ' ' || c == '\t' {
+                    self.pos += 1;
+                } else if c == '\\' && self.peek_char(1) == Some('\n') {
+                    self.pos += 2;
+                } else {
+                    break;
+                }
+            }
+
+            let Some(c) = self.peek_char(0) else {
+                break;
+            };
+            let start = self.offsets[self.pos];
+
+            if c == '#' {
+                while self.peek_char(0).is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+                continue;
+            }
+
+            if c == '\n' {
+                self.pos += 1;
+                tokens.push(Token {
+                    kind: TokenKind::Newline,
+                    start,
+                    end: start + 1,
+                });
+                self.read_heredoc_bodies()?;
+                continue;
+            }
+
+            // Process substitution is a word, not a redirection
+            let is_process_substitution = (c == '<' || c == '>') && self.peek_char(1) == Some('(');
+
+            if !is_process_substitution {
+                if let Some(operator) = self.match_operator() {
+                    self.pos += operator.chars().count();
+                    if operator == "<<" || operator == "<<-" {
+                        expecting_heredoc_delimiter = Some(operator == "<<-");
+                    }
+                    tokens.push(Token {
+                        kind: TokenKind::Operator(operator.to_string()),
+                        start,
+                        end: self.offsets[self.pos],
+                    });
+                    continue;
+                }
+
+                // File descriptor prefix of a redirection, e.g. the 2 in `2>&1`
+                if c.is_ascii_digit() {
+                    let mut len = 0;
+                    while self.peek_char(len).is_some_and(|c| c.is_ascii_digit()) {
+                        len += 1;
+                    }
+                    if matches!(self.peek_char(len), Some('<') | Some('>')) {
+                        let saved = self.pos;
+                        self.pos += len;
+                        if let Some(operator) = self.match_operator() {
+                            self.pos += operator.chars().count();
+                            if operator == "<<" || operator == "<<-" {
+                                expecting_heredoc_delimiter = Some(operator == "<<-");
+                            }
+                            tokens.push(Token {
+                                kind: TokenKind::Operator(operator.to_string()),
+                                start,
+                                end: self.offsets[self.pos],
+                            });
+                            continue;
+                        }
+                        self.pos = saved;
+                    }
+                }
+            }
+
+            let word = self.read_word()?;
+            if let Some(strip_tabs) = expecting_heredoc_delimiter.take() {
+                self.pending_heredocs.push(PendingHeredoc {
+                    delimiter: word.value.clone(),
+                    strip_tabs,
+                    expand: !word.quoted,
+                });
+            }
+            tokens.push(Token {
+                kind: TokenKind::Word(word),
+                start,
+                end: self.offsets[self.pos],
+            });
+        }
+
+        Ok(tokens)
+    }
+
+    fn match_operator(&self) -> Option<&'static str> {
+        let rest = &self.source[self.offsets[self.pos]..];
+        OPERATORS.iter().copied().find(|op| rest.starts_with(op))
+    }
+
+    fn read_word(&mut self) -> Result<Word> {
+        let mut word = Word::default();
+
+        while let Some(c) = self.peek_char(0) {
+            match c {
+                ' ' | '\t' | '\n' | ';' | '&' | '|' | '(' | ')' => break,
+                '<' | '>' => {
+                    if self.peek_char(1) == Some('(') && word.value.is_empty() {
+                        // <(cmd) or >(cmd)
+                        let open = self.pos + 1;
+                        let close = self.find_matching_paren(open)?;
+                        let inner = self.slice(open + 1, close);
+                        word.substitutions.push(parse_nested(inner, self.depth + 1)?);
+                        word.value.push_str(self.slice(self.pos, close + 1));
+                        word.dynamic = true;
+                        self.pos = close + 1;
+                    } else {
+                        break;
+                    }
+                }
+                '\'' => {
+                    word.quoted = true;
+                    self.pos += 1;
+                    loop {
+                        match self.peek_char(0) {
+                            Some('\'') => {
+                                self.pos += 1;
+                                break;
+                            }
+                            Some(c) => {
+                                word.value.push(c);
+                                self.pos += 1;
+                            }
+                            None => return Err(anyhow::anyhow!("unterminated single quote")),
+                        }
+                    }
+                }
+                '"' => {
+                    word.quoted = true;
+                    self.pos += 1;
+                    self.read_double_quoted(&mut word, false)?;
+                }
+                '\\' => {
+                    word.quoted = true;
+                    match self.peek_char(1) {
+                        Some('\n') => self.pos += 2,
+                        Some(escaped) => {
+                            word.value.push(escaped);
+                            self.pos += 2;
+                        }
+                        None => self.pos += 1,
+                    }
+                }
+                '$' => self.read_dollar(&mut word)?,
+                '`' => self.read_backtick(&mut word)?,
+                _ => {
+                    word.value.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        Ok(word)
+    }
+
+    /// Read double-quoted text up to the closing quote, or to the end of input when
+    /// `until_end` is set (used for here-documents and `${...}` bodies)
+    fn read_double_quoted(&mut self, word: &mut Word, until_end: bool) -> Result<()> {
+        loop {
+            let Some(c) = self.peek_char(0) else {
+                if until_end {
+                    return Ok(());
+                }
+                return Err(anyhow::anyhow!("unterminated double quote"));
+            };
+
+            match c {
+                '"' if !until_end => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                '\\' => {
+                    match self.peek_char(1) {
+                        Some('\n') => {}
+                        Some(escaped @ ('$' | '`' | '"' | '\\')) => word.value.push(escaped),
+                        Some(other) => {
+                            word.value.push('\\');
+                            word.value.push(other);
+                        }
+                        None => word.value.push('\\'),
+                    }
+                    self.pos = (self.pos + 2).min(self.chars.len());
+                }
+                '$' => self.read_dollar(word)?,
+                '`' => self.read_backtick(word)?,
+                _ => {
+                    word.value.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn read_dollar(&mut self, word: &mut Word) -> Result<()> {
+        let start = self.pos;
+        match self.peek_char(1) {
+            Some('(') => {
+                let close = self.find_matching_paren(self.pos + 1)?;
+                let inner = self.slice(self.pos + 2, close);
+                if self.peek_char(2) == Some('(') {
+                    // $((...)) is arithmetic, but $((1 + $(cmd))) still runs cmd
+                    let mut body_lexer = Lexer::new(inner, self.depth + 1);
+                    let mut body = Word::default();
+                    body_lexer.read_double_quoted(&mut body, true)?;
+                    word.substitutions.extend(body.substitutions);
+                } else {
+                    word.substitutions.push(parse_nested(inner, self.depth + 1)?);
+                }
+                self.pos = close + 1;
+            }
+            Some('{') => {
+                let close = self.find_matching_brace(self.pos + 1)?;
+                // ${var:-$(cmd)} runs cmd, so scan the body like double-quoted text
+                let inner = self.slice(self.pos + 2, close);
+                let mut body_lexer = Lexer::new(inner, self.depth + 1);
+                let mut body = Word::default();
+                body_lexer.read_double_quoted(&mut body, true)?;
+                word.substitutions.extend(body.substitutions);
+                self.pos = close + 1;
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                self.pos += 1;
+                while self
+                    .peek_char(0)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    self.pos += 1;
+                }
+            }
+            Some(c) if c.is_ascii_digit() || "@*#?-$!".contains(c) => self.pos += 2,
+            _ => {
+                // A lone dollar sign is literal
+                word.value.push('$');
+                self.pos += 1;
+                return Ok(());
+            }
+        }
+
+        word.value.push_str(self.slice(start, self.pos));
+        word.dynamic = true;
+        Ok(())
+    }
+
+    fn read_backtick(&mut self, word: &mut Word) -> Result<()> {
+        let start = self.pos;
+        self.pos += 1;
+
+        let mut inner = String::new();
+        loop {
+            match self.peek_char(0) {
+                Some('`') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('\\') if matches!(self.peek_char(1), Some('`' | '\\' | '$')) => {
+                    inner.push(self.chars[self.pos + 1]);
+                    self.pos += 2;
+                }
+                Some(c) => {
+                    inner.push(c);
+                    self.pos += 1;
+                }
+                None => return Err(anyhow::anyhow!("unterminated backtick substitution")),
+            }
+        }
+
+        word.substitutions.push(parse_nested(&inner, self.depth + 1)?);
+        word.value.push_str(self.slice(start, self.pos));
+        word.dynamic = true;
+        Ok(())
+    }
+
+    /// Find the `)` matching the `(` at char index `open`, skipping quoted text
+    fn find_matching_paren(&self, open: usize) -> Result<usize> {
+        self.find_matching(open, '(', ')')
+    }
+
+    fn find_matching_brace(&self, open: usize) -> Result<usize> {
+        self.find_matching(open, '{', '}')
+    }
+
+    fn find_matching(&self, open: usize, open_char: char, close_char: char) -> Result<usize> {
+        let mut depth = 0;
+        let mut i = open;
+
+        while i < self.chars.len() {
+            let c = self.chars[i];
+            if c == '\\' {
+                i += 2;
+                continue;
+            } else if c == '\'' {
+                i += 1;
+                while i < self.chars.len() && self.chars[i] != '\'' {
+                    i += 1;
+                }
+            } else if c == '"' || c == '`' {
+                i += 1;
+                while i < self.chars.len() && self.chars[i] != c {
+                    if self.chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            } else if c == open_char {
+                depth += 1;
+            } else if c == close_char {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i);
+                }
+            }
+            i += 1;
+        }
+
+        Err(anyhow::anyhow!(
+            "unterminated '{}' starting at offset {}",
+            open_char,
+            self.offsets[open]
+        ))
+    }
+
+    fn slice(&self, start: usize, end: usize) -> &'a str {
+        &self.source[self.offsets[start]..self.offsets[end]]
+    }
+
+    /// Consume the bodies of here-documents whose operators appeared on the line just ended
+    fn read_heredoc_bodies(&mut self) -> Result<()> {
+        for heredoc in std::mem::take(&mut self.pending_heredocs) {
+            let body_start = self.pos;
+            let mut body_end = self.chars.len();
+
+            while self.pos < self.chars.len() {
+                let line_start = self.pos;
+                while self.peek_char(0).is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+                let line = self.slice(line_start, self.pos);
+                let line = if heredoc.strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    line
+                };
+                let is_delimiter = line == heredoc.delimiter;
+                if self.peek_char(0) == Some('\n') {
+                    self.pos += 1;
+                }
+                if is_delimiter {
+                    body_end = line_start;
+                    break;
+                }
+            }
+
+            if heredoc.expand {
+                let body = self.slice(body_start, body_end);
+                let mut body_lexer = Lexer::new(body, self.depth + 1);
+                let mut word = Word::default();
+                body_lexer.read_double_quoted(&mut word, true)?;
+                self.heredoc_substitutions.extend(word.substitutions);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_word(&self) -> Option<&Word> {
+        match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Word(word)) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn peek_operator(&self) -> Option<&str> {
+        match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Operator(op)) => Some(op.as_str()),
+            _ => None,
+        }
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        self.peek_word().is_some_and(|w| w.is_literal(keyword))
+    }
+
+    fn skip_newlines(&mut self) {
+        while matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Newline)) {
+            self.pos += 1;
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> anyhow::Error {
+        match self.peek() {
+            Some(token) => anyhow::anyhow!(
+                "expected {} but found '{}' at offset {}",
+                expected,
+                &self.source[token.start..token.end],
+                token.start
+            ),
+            None => anyhow::anyhow!("expected {} but reached end of command", expected),
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.at_keyword(keyword) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", keyword)))
+        }
+    }
+
+    fn expect_operator(&mut self, operator: &str) -> Result<()> {
+        if self.peek_operator() == Some(operator) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", operator)))
+        }
+    }
+
+    fn expect_word(&mut self) -> Result<Word> {
+        match self.peek_word().cloned() {
+            Some(word) => {
+                self.pos += 1;
+                Ok(word)
+            }
+            None => Err(self.unexpected("a word")),
+        }
+    }
+
+    /// Parse a list of pipelines until end of input or one of the stop words/operators
+    fn parse_script(&mut self, stop_words: &[&str], stop_operators: &[&str]) -> Result<Script> {
+        let mut script = Script::default();
+
+        loop {
+            // Separators between and-or lists
+            while let Some(token) = self.peek() {
+                match &token.kind {
+                    TokenKind::Newline => self.pos += 1,
+                    TokenKind::Operator(op) if op == ";" || op == "&" => self.pos += 1,
+                    _ => break,
+                }
+            }
+
+            if self.peek().is_none()
+                || stop_words.iter().any(|w| self.at_keyword(w))
+                || self
+                    .peek_operator()
+                    .is_some_and(|op| stop_operators.contains(&op))
+            {
+                return Ok(script);
+            }
+
+            self.parse_and_or(&mut script)?;
+
+            match self.peek().map(|t| &t.kind) {
+                None | Some(TokenKind::Newline) => {}
+                Some(TokenKind::Operator(op)) if op == ";" || op == "&" => {}
+                Some(TokenKind::Operator(op)) if stop_operators.contains(&op.as_str()) => {}
+                Some(TokenKind::Word(w)) if stop_words.iter().any(|s| w.is_literal(s)) => {}
+                _ => return Err(self.unexpected("end of command")),
+            }
+        }
+    }
+
+    fn parse_and_or(&mut self, script: &mut Script) -> Result<()> {
+        script.pipelines.push(self.parse_pipeline()?);
+        while matches!(self.peek_operator(), Some("&&") | Some("||")) {
+            self.pos += 1;
+            self.skip_newlines();
+            script.pipelines.push(self.parse_pipeline()?);
+        }
+        Ok(())
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline> {
+        if self.at_keyword("!") {
+            self.pos += 1;
+        }
+
+        let mut pipeline = Pipeline {
+            commands: vec![self.parse_command()?],
+        };
+        while matches!(self.peek_operator(), Some("|") | Some("|&")) {
+            self.pos += 1;
+            self.skip_newlines();
+            pipeline.commands.push(self.parse_command()?);
+        }
+        Ok(pipeline)
+    }
+
+    fn parse_command(&mut self) -> Result<Command> {
+        if self.peek_operator() == Some("(") {
+            self.pos += 1;
+            let body = self.parse_script(&[], &[")"])?;
+            self.expect_operator(")")?;
+            return self.finish_compound(vec![body], Vec::new());
+        }
+
+        let Some(word) = self.peek_word().cloned() else {
+            return Err(self.unexpected("a command"));
+        };
+
+        if !word.quoted && !word.dynamic {
+            match word.value.as_str() {
+                "{" => {
+                    self.pos += 1;
+                    let body = self.parse_script(&["}"], &[])?;
+                    self.expect_keyword("}")?;
+                    return self.finish_compound(vec![body], Vec::new());
+                }
+                "if" => return self.parse_if(),
+                "while" | "until" => {
+                    self.pos += 1;
+                    let condition = self.parse_script(&["do"], &[])?;
+                    self.expect_keyword("do")?;
+                    let body = self.parse_script(&["done"], &[])?;
+                    self.expect_keyword("done")?;
+                    return self.finish_compound(vec![condition, body], Vec::new());
+                }
+                "for" | "select" => return self.parse_for(),
+                "case" => return self.parse_case(),
+                "function" => {
+                    self.pos += 1;
+                    let name = self.expect_word()?.value;
+                    if self.peek_operator() == Some("(") {
+                        self.pos += 1;
+                        self.expect_operator(")")?;
+                    }
+                    self.skip_newlines();
+                    let body = self.parse_command()?;
+                    return Ok(Command::FunctionDef {
+                        name,
+                        body: Box::new(body),
+                    });
+                }
+                _ => {}
+            }
+
+            // name() compound-command
+            let is_function = matches!(
+                (self.tokens.get(self.pos + 1), self.tokens.get(self.pos + 2)),
+                (Some(Token { kind: TokenKind::Operator(open), .. }), Some(Token { kind: TokenKind::Operator(close), .. }))
+                    if open == "(" && close == ")"
+            );
+            if is_function {
+                self.pos += 3;
+                self.skip_newlines();
+                let body = self.parse_command()?;
+                return Ok(Command::FunctionDef {
+                    name: word.value,
+                    body: Box::new(body),
+                });
+            }
+        }
+
+        self.parse_simple_command()
+    }
+
+    fn parse_if(&mut self) -> Result<Command> {
+        self.expect_keyword("if")?;
+        let mut bodies = vec![self.parse_script(&["then"], &[])?];
+        self.expect_keyword("then")?;
+        bodies.push(self.parse_script(&["elif", "else", "fi"], &[])?);
+
+        while self.at_keyword("elif") {
+            self.pos += 1;
+            bodies.push(self.parse_script(&["then"], &[])?);
+            self.expect_keyword("then")?;
+            bodies.push(self.parse_script(&["elif", "else", "fi"], &[])?);
+        }
+
+        if self.at_keyword("else") {
+            self.pos += 1;
+            bodies.push(self.parse_script(&["fi"], &[])?);
+        }
+
+        self.expect_keyword("fi")?;
+        self.finish_compound(bodies, Vec::new())
+    }
+
+    /// `for` or `select`, which share the same syntax
+    fn parse_for(&mut self) -> Result<Command> {
+        self.pos += 1;
+        self.expect_word()?;
+        self.skip_newlines();
+
+        let mut words = Vec::new();
+        if self.at_keyword("in") {
+            self.pos += 1;
+            while let Some(word) = self.peek_word().cloned() {
+                self.pos += 1;
+                words.push(word);
+            }
+        }
+
+        // Separator before `do`
+        if self.peek_operator() == Some(";") {
+            self.pos += 1;
+        }
+        self.skip_newlines();
+
+        self.expect_keyword("do")?;
+        let body = self.parse_script(&["done"], &[])?;
+        self.expect_keyword("done")?;
+        self.finish_compound(vec![body], words)
+    }
+
+    fn parse_case(&mut self) -> Result<Command> {
+        self.expect_keyword("case")?;
+        let mut words = vec![self.expect_word()?];
+        self.skip_newlines();
+        self.expect_keyword("in")?;
+
+        let mut bodies = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.at_keyword("esac") {
+                self.pos += 1;
+                break;
+            }
+
+            if self.peek_operator() == Some("(") {
+                self.pos += 1;
+            }
+            loop {
+                words.push(self.expect_word()?);
+                if self.peek_operator() == Some("|") {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+            self.expect_operator(")")?;
+
+            bodies.push(self.parse_script(&["esac"], &[";;"])?);
+            if self.peek_operator() == Some(";;") {
+                self.pos += 1;
+            }
+        }
+
+        self.finish_compound(bodies, words)
+    }
+
+    /// Collect redirections that follow a compound command
+    fn finish_compound(&mut self, bodies: Vec<Script>, words: Vec<Word>) -> Result<Command> {
+        let mut redirects = Vec::new();
+        while let Some(operator) = self.peek_operator().filter(|op| is_redirect(op)) {
+            let operator = operator.to_string();
+            self.pos += 1;
+            redirects.push(Redirect {
+                operator,
+                target: self.expect_word()?,
+            });
+        }
+
+        Ok(Command::Compound {
+            bodies,
+            words,
+            redirects,
+        })
+    }
+
+    fn parse_simple_command(&mut self) -> Result<Command> {
+        let start = self.peek().map(|t| t.start).unwrap_or(self.source.len());
+        let mut end = start;
+        let mut command = SimpleCommand::default();
+
+        while let Some(token) = self.peek().cloned() {
+            match token.kind {
+                TokenKind::Word(word) => {
+                    if command.words.is_empty() && is_assignment(&word) {
+                        command.assignments.push(word);
+                    } else {
+                        command.words.push(word);
+                    }
+                    end = token.end;
+                    self.pos += 1;
+                }
+                TokenKind::Operator(operator) if is_redirect(&operator) => {
+                    self.pos += 1;
+                    let target = self.expect_word()?;
+                    end = self.tokens[self.pos - 1].end;
+                    command.redirects.push(Redirect { operator, target });
+                }
+                _ => break,
+            }
+        }
+
+        if command.words.is_empty() && command.assignments.is_empty() && command.redirects.is_empty()
+        {
+            return Err(self.unexpected("a command"));
+        }
+
+        command.text = self.source[start..end].to_string();
+        Ok(Command::Simple(command))
+    }
+}
+
+fn is_redirect(operator: &str) -> bool {
+    operator.starts_with('<') || operator.starts_with('>') || operator.starts_with("&>")
+}
+
+fn is_assignment(word: &Word) -> bool {
+    match word.value.split_once('=') {
+        Some((name, _)) => {
+            let mut chars = name.chars();
+            chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+/// The program a simple command actually runs, after skipping wrappers like `sudo` or `env`
+pub struct Invocation<'a> {
+    pub program: String,
+    pub args: &'a [Word],
+    pub dynamic_program: bool,
+    pub privileged: bool,
+}
+
+/// Command passed as a string to another shell with `-c`, or to `eval`
+pub fn nested_source(invocation: &Invocation) -> Option<String> {
+    if is_shell(&invocation.program) {
+        invocation
+            .args
+            .iter()
+            .position(|arg| arg.value.starts_with('-') && !arg.value.starts_with("--") && arg.value.contains('c'))
+            .and_then(|i| invocation.args.get(i + 1))
+            .map(|arg| arg.value.clone())
+    } else if invocation.program == "eval" {
+        Some(
+            invocation
+                .args
+                .iter()
+                .map(|arg| arg.value.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+    } else {
+        None
+    }
+}
+
+/// Program and arguments of every command a command line runs: chained, piped, nested in
+/// compound commands or substitutions, behind wrappers such as `sudo` or `env`, and passed to
+/// another shell. None if the command line can't be parsed.
+pub fn invocations(command: &str) -> Option<Vec<Vec<String>>> {
+    fn collect(script: &Script, depth: usize, found: &mut Vec<Vec<String>>) -> Option<()> {
+        if depth > MAX_SHELL_NESTING {
+            return None;
+        }
+        let mut nested = Vec::new();
+        script.visit_pipelines(&mut |pipeline| {
+            for command in &pipeline.commands {
+                let Command::Simple(simple) = command else {
+                    continue;
+                };
+                let Some(invocation) = resolve_invocation(&simple.words) else {
+                    continue;
+                };
+                let mut words = vec![invocation.program.clone()];
+                words.extend(invocation.args.iter().map(|arg| arg.value.clone()));
+                found.push(words);
+                nested.extend(nested_source(&invocation));
+            }
+        });
+        for source in nested {
+            collect(&parse(&source).ok()?, depth + 1, found)?;
+        }
+        Some(())
+    }
+
+    let mut found = Vec::new();
+    collect(&parse(command).ok()?, 0, &mut found)?;
+    Some(found)
+}
+
+/// Skip wrapper commands (and their options) to find the program that really runs
+pub fn resolve_invocation(words: &[Word]) -> Option<Invocation<'_>> {
+    let mut i = 0;
+    let mut privileged = false;
+
+    while let Some(word) = words.get(i) {
+        let program = program_name(&word.value);
+        // Options of each wrapper that take a separate value
+        let value_options: &[&str] = match program {
+            "sudo" | "doas" => {
+                privileged = true;
+                &["-u", "-g", "-C", "-p", "-h", "-U", "-r", "-t", "-D"]
+            }
+            "env" => &["-u", "-C", "-S", "--unset", "--chdir"],
+            "nice" => &["-n", "--adjustment"],
+            "ionice" => &["-c", "-n", "-p"],
+            "timeout" => &["-s", "-k", "--signal", "--kill-after"],
+            "xargs" => &["-I", "-n", "-P", "-L", "-d", "-s", "-E", "-a"],
+            "exec" => &["-a"],
+            "nohup" | "time" | "command" | "builtin" | "coproc" | "stdbuf" | "busybox" => &[],
+            _ => {
+                return Some(Invocation {
+                    program: program.to_string(),
+                    args: &words[i + 1..],
+                    dynamic_program: word.dynamic,
+                    privileged,
+                })
+            }
+        };
+
+        i += 1;
+        while let Some(arg) = words.get(i) {
+            if arg.value == "--" {
+                i += 1;
+                break;
+            } else if value_options.contains(&arg.value.as_str()) {
+                i += 2;
+            } else if arg.value.starts_with('-') || (program == "env" && arg.value.contains('=')) {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+
+        // `timeout DURATION command`
+        if program == "timeout" && i < words.len() {
+            i += 1;
+        }
+    }
+
+    None
+}
+
+/// Basename of the program, so /sbin/shutdown is treated like shutdown
+fn program_name(value: &str) -> &str {
+    value.rsplit('/').next().unwrap_or(value)
+}
+
+pub fn is_shell(program: &str) -> bool {
+    matches!(program, "sh" | "bash" | "zsh" | "dash" | "ksh")
+}
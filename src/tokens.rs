@@ -0,0 +1,86 @@
+use crate::llm::{ContentBlock, LlmRequest, Message};
+
+/// Rough per-message cost of roles and block framing
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Estimate how many tokens `text` takes. Tokenizers average about four characters of English
+/// or code per token, but closer to one per character for other scripts, so ASCII is counted
+/// in quarters and everything else as a whole token.
+pub fn estimate(text: &str) -> usize {
+    quarters(text).div_ceil(4)
+}
+
+/// Estimated tokens of a conversation's messages
+pub fn estimate_messages(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|message| {
+            let content: usize = message
+                .content
+                .iter()
+                .map(|block| match block {
+                    ContentBlock::Text { text } => estimate(text),
+                    ContentBlock::ToolUse { name, input, .. } => {
+                        estimate(name) + estimate(&input.to_string())
+                    }
+                    ContentBlock::ToolResult { content, .. } => estimate(content),
+                })
+                .sum();
+            content + MESSAGE_OVERHEAD_TOKENS
+        })
+        .sum()
+}
+
+/// Estimated input tokens of a request: system prompt, tool definitions and messages
+pub fn estimate_request(request: &LlmRequest) -> usize {
+    let tools: usize = request
+        .tools
+        .iter()
+        .map(|tool| {
+            estimate(&tool.name)
+                + estimate(&tool.description)
+                + estimate(&tool.input_schema.to_string())
+        })
+        .sum();
+    estimate(&request.system) + tools + estimate_messages(&request.messages)
+}
+
+/// Longest prefix of `text` estimated at no more than `max_tokens`. Always ends on a character
+/// boundary.
+pub fn prefix(text: &str, max_tokens: usize) -> &str {
+    let limit = max_tokens.saturating_mul(4);
+    let mut used = 0;
+    for (i, c) in text.char_indices() {
+        used += char_quarters(c);
+        if used > limit {
+            return &text[..i];
+        }
+    }
+    text
+}
+
+/// Longest suffix of `text` estimated at no more than `max_tokens`. Always starts on a
+/// character boundary.
+pub fn suffix(text: &str, max_tokens: usize) -> &str {
+    let limit = max_tokens.saturating_mul(4);
+    let mut used = 0;
+    for (i, c) in text.char_indices().rev() {
+        used += char_quarters(c);
+        if used > limit {
+            return &text[i + c.len_utf8()..];
+        }
+    }
+    text
+}
+
+fn quarters(text: &str) -> usize {
+    text.chars().map(char_quarters).sum()
+}
+
+fn char_quarters(c: char) -> usize {
+    if c.is_ascii() {
+        1
+    } else {
+        4
+    }
+}
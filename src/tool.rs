@@ -1,3 +1,4 @@
+use crate::output::OutputProcessor;
 use anyhow::Result;
 use serde_json::Value;
 
@@ -34,6 +35,12 @@ pub trait Tool: Send + Sync {
 
     /// Actual implementation of the tool execution
     async fn execute(&self, arguments: &str) -> Result<String>;
+
+    /// How long outputs are shortened before they go back to the model. None keeps them whole,
+    /// apart from the agent's byte and token limits.
+    fn output_processor(&self) -> Option<&dyn OutputProcessor> {
+        None
+    }
 }
 
 /// Helper function to convert tools to JSON format for Claude API
@@ -0,0 +1,194 @@
+use crate::llm::Usage;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pricing {
+    pub input: f64,
+    pub output: f64,
+    pub cache_write: f64,
+    pub cache_read: f64,
+}
+
+impl Pricing {
+    /// Pricing with Anthropic's cache multipliers: writes cost 1.25x and reads 0.1x input
+    pub fn new(input: f64, output: f64) -> Self {
+        Self {
+            input,
+            output,
+            cache_write: input * 1.25,
+            cache_read: input * 0.1,
+        }
+    }
+
+    /// List prices for well-known model families
+    pub fn for_model(model: &str) -> Option<Self> {
+        let model = model.to_lowercase();
+        if model.contains("opus") {
+            Some(Self::new(15.0, 75.0))
+        } else if model.contains("sonnet") {
+            Some(Self::new(3.0, 15.0))
+        } else if model.contains("haiku") {
+            Some(Self::new(0.8, 4.0))
+        } else if model.contains("gpt-4o-mini") {
+            Some(Self::new(0.15, 0.6))
+        } else if model.contains("gpt-4o") {
+            Some(Self::new(2.5, 10.0))
+        } else {
+            None
+        }
+    }
+
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.input_tokens as f64 * self.input
+            + usage.output_tokens as f64 * self.output
+            + usage.cache_creation_input_tokens as f64 * self.cache_write
+            + usage.cache_read_input_tokens as f64 * self.cache_read)
+            / 1_000_000.0
+    }
+}
+
+/// Per-task limits, from the `[budget]` settings. Unset limits are not enforced.
+#[derive(Debug, Clone, Default)]
+pub struct BudgetConfig {
+    /// Input, output and cache tokens across all agents
+    pub max_tokens: Option<u64>,
+    /// Estimated cost in USD across all agents
+    pub max_cost_usd: Option<f64>,
+    /// Overrides the built-in list prices, for models without one
+    pub pricing: Option<Pricing>,
+}
+
+impl BudgetConfig {
+    fn pricing_for(&self, model: &str) -> Option<Pricing> {
+        self.pricing.or_else(|| Pricing::for_model(model))
+    }
+}
+
+/// Usage of one agent
+#[derive(Debug, Clone, Default)]
+pub struct AgentUsage {
+    pub requests: u64,
+    pub usage: Usage,
+    /// Estimated cost in USD. Excludes responses from models without known pricing.
+    pub cost_usd: f64,
+}
+
+impl AgentUsage {
+    fn add(&mut self, other: &AgentUsage) {
+        self.requests += other.requests;
+        self.usage += other.usage;
+        self.cost_usd += other.cost_usd;
+    }
+}
+
+/// Sums token usage of every agent working on a task and checks it against the budget.
+/// Shared by the orchestrator and the agents it delegates to.
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    budget: BudgetConfig,
+    agents: Mutex<BTreeMap<String, AgentUsage>>,
+    /// Models used without known pricing, so the cost estimate is incomplete
+    unpriced: Mutex<Vec<String>>,
+}
+
+impl UsageTracker {
+    pub fn new(budget: BudgetConfig) -> Self {
+        Self {
+            budget,
+            ..Self::default()
+        }
+    }
+
+    /// Add the usage of one response
+    pub fn record(&self, agent: &str, model: &str, usage: &Usage) {
+        let cost = match self.budget.pricing_for(model) {
+            Some(pricing) => pricing.cost(usage),
+            None => {
+                let mut unpriced = self.unpriced.lock().unwrap();
+                if !unpriced.iter().any(|m| m == model) {
+                    if self.budget.max_cost_usd.is_some() {
+                        log::warn!(
+                            "No pricing for model {}; its cost isn't counted against the budget. Set LLM_INPUT_PRICE_PER_MTOK and LLM_OUTPUT_PRICE_PER_MTOK.",
+                            model
+                        );
+                    }
+                    unpriced.push(model.to_string());
+                }
+                0.0
+            }
+        };
+
+        let mut agents = self.agents.lock().unwrap();
+        let entry = agents.entry(agent.to_string()).or_default();
+        entry.requests += 1;
+        entry.usage += *usage;
+        entry.cost_usd += cost;
+    }
+
+    pub fn by_agent(&self) -> BTreeMap<String, AgentUsage> {
+        self.agents.lock().unwrap().clone()
+    }
+
+    pub fn total(&self) -> AgentUsage {
+        let mut total = AgentUsage::default();
+        for usage in self.agents.lock().unwrap().values() {
+            total.add(usage);
+        }
+        total
+    }
+
+    /// Why the task has to stop, once a budget is used up
+    pub fn exceeded(&self) -> Option<String> {
+        let total = self.total();
+
+        if let Some(max_tokens) = self.budget.max_tokens {
+            if total.usage.total() >= max_tokens {
+                return Some(format!(
+                    "token budget exceeded ({} of {} tokens used)",
+                    total.usage.total(),
+                    max_tokens
+                ));
+            }
+        }
+        if let Some(max_cost) = self.budget.max_cost_usd {
+            if total.cost_usd >= max_cost {
+                return Some(format!(
+                    "cost budget exceeded (${:.4} of ${:.4} estimated)",
+                    total.cost_usd, max_cost
+                ));
+            }
+        }
+        None
+    }
+
+    /// Totals for the task followed by one line per agent
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!("Token usage: {}", describe(&self.total()))];
+        for (agent, usage) in self.by_agent() {
+            lines.push(format!("  {}: {}", agent, describe(&usage)));
+        }
+
+        let unpriced = self.unpriced.lock().unwrap();
+        if !unpriced.is_empty() {
+            lines.push(format!(
+                "  (no pricing for {}; cost excludes it)",
+                unpriced.join(", ")
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+fn describe(usage: &AgentUsage) -> String {
+    format!(
+        "{} requests, {} input, {} output, {} cache write, {} cache read tokens, est. ${:.4}",
+        usage.requests,
+        usage.usage.input_tokens,
+        usage.usage.output_tokens,
+        usage.usage.cache_creation_input_tokens,
+        usage.usage.cache_read_input_tokens,
+        usage.cost_usd
+    )
+}
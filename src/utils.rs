@@ -1,36 +1,59 @@
 use anyhow::Result;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
-use std::sync::atomic::{AtomicUsize, Ordering};
-
-/// Global counter for sequential file naming
-static GLOBAL_COUNTER: AtomicUsize = AtomicUsize::new(1);
-
-/// Clear the bin directory at the start of a new task
-pub fn clear_bin_directory() -> Result<()> {
-    let bin_path = Path::new("bin");
-    
-    if bin_path.exists() {
-        // Remove all contents of bin directory
-        fs::remove_dir_all(bin_path)?;
-        log::info!("Cleared bin directory for new task");
-    }
-    
-    // Recreate the bin directory structure for messages
-    fs::create_dir_all("bin/messages/orchestrator")?;
-    fs::create_dir_all("bin/messages/file_agent")?;
-    
-    // Reset counter
-    GLOBAL_COUNTER.store(1, Ordering::SeqCst);
-    
-    log::info!("Bin directory structure recreated");
-    Ok(())
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Where messages are stored outside of a session
+pub const DEFAULT_MESSAGE_DIR: &str = "bin/messages";
+
+tokio::task_local! {
+    /// Message directory of the session being processed
+    static MESSAGE_DIR: PathBuf;
+}
+
+/// Next sequence number of each message directory
+static COUNTERS: OnceLock<Mutex<HashMap<PathBuf, usize>>> = OnceLock::new();
+
+/// Run `future` with messages stored under `dir` instead of `bin/messages`
+pub async fn with_message_dir<F: Future>(dir: PathBuf, future: F) -> F::Output {
+    MESSAGE_DIR.scope(dir, future).await
+}
+
+/// Directory messages are currently stored in
+pub fn message_dir() -> PathBuf {
+    MESSAGE_DIR
+        .try_with(|dir| dir.clone())
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_MESSAGE_DIR))
+}
+
+/// Get the next sequence number for a message directory. Numbering continues after the
+/// messages already stored there, so a session's files stay in order across messages.
+pub fn next_sequence_number(dir: &Path) -> usize {
+    let mut counters = COUNTERS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+    let next = counters
+        .entry(dir.to_path_buf())
+        .or_insert_with(|| highest_sequence_number(dir) + 1);
+    let number = *next;
+    *next += 1;
+    number
 }
 
-/// Get the next global sequence number
-pub fn get_next_global_sequence_number() -> usize {
-    GLOBAL_COUNTER.fetch_add(1, Ordering::SeqCst)
+fn highest_sequence_number(dir: &Path) -> usize {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.split('_').next()?.parse::<usize>().ok()
+        })
+        .max()
+        .unwrap_or(0)
 }
 
 /// Generate a sequential filename for messages
@@ -40,14 +63,26 @@ pub fn generate_message_filename(agent_type: &str, sequence_number: usize) -> St
 
 /// Store Claude message in message history - shared across all agents
 pub fn store_claude_message(agent_type: &str, message: &Value) -> Result<()> {
-    let sequence_number = get_next_global_sequence_number();
+    let root = message_dir();
+    let sequence_number = next_sequence_number(&root);
     let filename = generate_message_filename(agent_type, sequence_number);
-    let dir_path_str = format!("bin/messages/{}", agent_type);
-    let dir_path = Path::new(&dir_path_str);
-    fs::create_dir_all(dir_path)?;
+    let dir_path = root.join(agent_type);
+    fs::create_dir_all(&dir_path)?;
 
     let file_path = dir_path.join(&filename);
     fs::write(file_path, serde_json::to_string_pretty(message)?)?;
     log::debug!("Stored {} Claude message: {}", agent_type, filename);
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Longest prefix of `text` with at most `max_bytes` bytes, ending on a character boundary
+pub fn truncate_bytes(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
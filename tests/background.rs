@@ -0,0 +1,164 @@
+mod common;
+
+use common::TempDir;
+use file_agent::agents::file::tools::background::OutputBuffer;
+use file_agent::agents::file::tools::{BashTool, ProcessRegistry};
+use file_agent::agents::file::{Sandbox, Workspace};
+use file_agent::tool::Tool;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+#[test]
+fn characters_split_across_reads_are_decoded_whole() {
+    let mut buffer = OutputBuffer::new(1024);
+    let text = "né 日本";
+    for byte in text.as_bytes() {
+        buffer.push(&[*byte]);
+    }
+    assert_eq!(buffer.take(), text);
+
+    // Half a character stays pending until the next chunk, and a bad byte is replaced
+    buffer.push(&[b'a', 0xe6, 0x97]);
+    assert_eq!(buffer.take(), "a");
+    buffer.push(&[0xa5, 0xff, b'b']);
+    assert_eq!(buffer.take(), "日\u{fffd}b");
+
+    buffer.push(&[0xe6]);
+    buffer.close();
+    assert_eq!(buffer.take(), "\u{fffd}");
+    assert!(buffer.is_closed());
+}
+
+#[test]
+fn buffer_keeps_the_latest_output() {
+    let mut buffer = OutputBuffer::new(10);
+    buffer.push(b"0123456789");
+    buffer.push("abcé".as_bytes());
+    assert_eq!(
+        buffer.take(),
+        "[... 5 bytes of earlier output dropped ...]\n56789abcé"
+    );
+    assert_eq!(buffer.take(), "");
+}
+
+#[tokio::test]
+async fn finished_processes_are_removed_once_their_output_is_read() {
+    let registry = ProcessRegistry::new();
+    let env: BTreeMap<String, String> =
+        [("PATH".to_string(), std::env::var("PATH").unwrap())].into();
+    let id = registry
+        .spawn(
+            "echo done",
+            &std::env::temp_dir(),
+            &env,
+            &Sandbox::disabled(),
+        )
+        .unwrap();
+
+    let mut output = registry.read_new_output(&id).unwrap();
+    for _ in 0..100 {
+        if output.removed {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        output = registry.read_new_output(&id).unwrap();
+    }
+    assert!(output.removed);
+    assert!(!output.running);
+    assert!(registry.list().is_empty());
+    assert!(registry.read_new_output(&id).is_err());
+}
+
+#[tokio::test]
+async fn unread_output_of_a_chatty_process_is_capped() {
+    let registry = ProcessRegistry::new();
+    let env: BTreeMap<String, String> =
+        [("PATH".to_string(), std::env::var("PATH").unwrap())].into();
+    let id = registry
+        .spawn(
+            "head -c 3000000 /dev/zero | tr '\\0' a; echo; echo done",
+            &std::env::temp_dir(),
+            &env,
+            &Sandbox::disabled(),
+        )
+        .unwrap();
+
+    // Wait for the process to exit without reading its output
+    for _ in 0..100 {
+        if !registry.list()[0].running {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let output = registry.read_new_output(&id).unwrap();
+    assert!(output.stdout.starts_with("[... "));
+    assert!(output.stdout.ends_with("done\n"));
+    assert!(output.stdout.len() < 1024 * 1024 + 100);
+}
+
+/// True once `pid` has exited, including when it is a zombie nobody reaped yet
+fn is_dead(pid: &str) -> bool {
+    match std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())) {
+        Ok(stat) => stat
+            .rsplit(')')
+            .next()
+            .is_some_and(|rest| rest.trim_start().starts_with('Z')),
+        Err(_) => true,
+    }
+}
+
+async fn wait_until_dead(pid: &str) -> bool {
+    for _ in 0..40 {
+        if is_dead(pid) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn timed_out_commands_are_killed_with_their_children() {
+    let dir = TempDir::new();
+    let workspace = Workspace::new(dir.path(), &[]).unwrap();
+    let tool = BashTool::new(workspace, ProcessRegistry::new(), Sandbox::disabled());
+
+    let started = Instant::now();
+    let args = json!({"command": "sleep 30 & echo $! > child.pid; sleep 30", "timeout": 300});
+    let output = tool.execute(&args.to_string()).await.unwrap();
+    assert!(output.contains("Summary: Command timed out after 300 ms and was killed"), "{}", output);
+    assert!(started.elapsed() < Duration::from_secs(10));
+
+    // The background child is in the command's process group, so it was killed too
+    assert!(wait_until_dead(&dir.read("child.pid")).await);
+}
+
+#[tokio::test]
+async fn killed_background_processes_take_their_children_with_them() {
+    let registry = ProcessRegistry::new();
+    let env: BTreeMap<String, String> =
+        [("PATH".to_string(), std::env::var("PATH").unwrap())].into();
+    let id = registry
+        .spawn(
+            "sleep 30 & echo $!; wait",
+            &std::env::temp_dir(),
+            &env,
+            &Sandbox::disabled(),
+        )
+        .unwrap();
+
+    let mut pid = String::new();
+    for _ in 0..100 {
+        pid.push_str(&registry.read_new_output(&id).unwrap().stdout);
+        if pid.ends_with('\n') {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    registry.kill(&id).unwrap();
+    assert!(wait_until_dead(&pid).await);
+}
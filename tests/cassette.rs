@@ -0,0 +1,95 @@
+mod common;
+
+use common::{file_agent, TempDir};
+use file_agent::agent::Agent;
+use file_agent::llm::cassette::load_cassette;
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::{MatchBy, RecordingProvider, ReplayProvider, ScriptedProvider};
+use serde_json::json;
+use std::sync::Arc;
+
+/// Record a two-round file agent run into `cassette.jsonl` inside `dir`
+async fn record_run(dir: &TempDir) {
+    dir.write("notes.txt", "recorded content\n");
+
+    let scripted = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("t1", "read", json!({"file_path": "notes.txt"}))]),
+        text_turn("The notes say recorded content"),
+    ]));
+    let recorder = RecordingProvider::new(scripted, &dir.path().join("cassette.jsonl")).unwrap();
+    let agent = file_agent(Arc::new(recorder), dir);
+
+    let answer = agent.execute("Read notes.txt").await.unwrap();
+    assert_eq!(answer, "The notes say recorded content");
+}
+
+#[tokio::test]
+async fn recorded_run_replays_by_order() {
+    let dir = TempDir::new();
+    record_run(&dir).await;
+
+    let entries = load_cassette(&dir.path().join("cassette.jsonl")).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].index, 0);
+    assert_eq!(entries[0].provider, "scripted");
+    assert_eq!(entries[1].request.messages.len(), 3);
+    assert!(entries.iter().all(|entry| entry.response.is_some()));
+
+    let replay = Arc::new(
+        ReplayProvider::from_file(&dir.path().join("cassette.jsonl"), MatchBy::Order).unwrap(),
+    );
+    let agent = file_agent(replay.clone(), &dir);
+
+    let answer = agent.execute("Read notes.txt").await.unwrap();
+    assert_eq!(answer, "The notes say recorded content");
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[tokio::test]
+async fn replay_by_hash_rejects_a_different_request() {
+    let dir = TempDir::new();
+    record_run(&dir).await;
+
+    let replay = Arc::new(
+        ReplayProvider::from_file(&dir.path().join("cassette.jsonl"), MatchBy::Hash).unwrap(),
+    );
+
+    // Identical run matches every recorded request
+    let agent = file_agent(replay.clone(), &dir);
+    assert_eq!(
+        agent.execute("Read notes.txt").await.unwrap(),
+        "The notes say recorded content"
+    );
+    assert_eq!(replay.remaining(), 0);
+
+    // A different task has no recorded response
+    let replay = Arc::new(
+        ReplayProvider::from_file(&dir.path().join("cassette.jsonl"), MatchBy::Hash).unwrap(),
+    );
+    let agent = file_agent(replay.clone(), &dir);
+    let error = agent.execute("Read other.txt").await.unwrap_err();
+    assert!(error.to_string().contains("no unused recorded response"));
+    assert_eq!(replay.remaining(), 2);
+}
+
+#[tokio::test]
+async fn provider_errors_are_recorded_and_replayed() {
+    let dir = TempDir::new();
+    let path = dir.path().join("cassette.jsonl");
+
+    let recorder = RecordingProvider::new(Arc::new(ScriptedProvider::new(vec![])), &path).unwrap();
+    let agent = file_agent(Arc::new(recorder), &dir);
+    let recorded = agent.execute("Anything").await.unwrap_err().to_string();
+
+    let entries = load_cassette(&path).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].response.is_none());
+    assert_eq!(entries[0].error.as_deref(), Some(recorded.as_str()));
+
+    let replay = ReplayProvider::from_file(&path, MatchBy::Order).unwrap();
+    let agent = file_agent(Arc::new(replay), &dir);
+    assert_eq!(
+        agent.execute("Anything").await.unwrap_err().to_string(),
+        recorded
+    );
+}
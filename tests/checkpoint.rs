@@ -0,0 +1,238 @@
+mod common;
+
+use common::{file_agent, tool_results, TempDir};
+use file_agent::checkpoint::{self, Checkpoint, CheckpointEntry, JsonlCheckpoint};
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::{LlmProvider, LlmRequest, LlmResponse, Message, Role, ScriptedProvider};
+use file_agent::session::Session;
+use file_agent::OrchestratorAgent;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn rebuild_marks_calls_by_how_far_they_got() {
+    let entries = vec![
+        CheckpointEntry::Message {
+            message: Message::user_text("Build it"),
+        },
+        CheckpointEntry::Message {
+            message: Message {
+                role: Role::Assistant,
+                content: tool_use_turn(&[
+                    ("t1", "read", json!({"file_path": "a.txt"})),
+                    ("t2", "bash", json!({"command": "make"})),
+                    ("t3", "write", json!({"file_path": "b.txt", "content": "b"})),
+                ])
+                .content,
+            },
+        },
+        CheckpointEntry::CallStarted {
+            tool_use_id: "t1".to_string(),
+            name: "read".to_string(),
+        },
+        CheckpointEntry::CallStarted {
+            tool_use_id: "t2".to_string(),
+            name: "bash".to_string(),
+        },
+        CheckpointEntry::CallFinished {
+            tool_use_id: "t1".to_string(),
+            name: "read".to_string(),
+            is_error: false,
+        },
+    ];
+
+    let messages = checkpoint::rebuild(&entries);
+    assert_eq!(messages.len(), 3);
+
+    let request = LlmRequest {
+        system: String::new(),
+        messages,
+        tools: Vec::new(),
+    };
+    let results = tool_results(&request);
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|(_, _, is_error)| *is_error));
+    assert!(results[0]
+        .1
+        .starts_with("read: finished, but its output was lost when the task stopped."));
+    assert!(results[1]
+        .1
+        .starts_with("bash: interrupted before it finished."));
+    assert_eq!(
+        results[2].1,
+        "write: not run, because the task stopped before it started."
+    );
+}
+
+#[test]
+fn incomplete_last_line_is_skipped() {
+    let dir = TempDir::new();
+    let path = dir.path().join("conversation.jsonl");
+
+    let checkpoint = JsonlCheckpoint::open(&path).unwrap();
+    checkpoint
+        .record(&CheckpointEntry::Message {
+            message: Message::user_text("hi"),
+        })
+        .unwrap();
+    drop(checkpoint);
+
+    // A crash in the middle of writing the next entry
+    let mut content = dir.read("conversation.jsonl");
+    content.push_str("{\"type\":\"message\",\"message\":{\"role\":\"assis");
+    dir.write("conversation.jsonl", &content);
+
+    let entries = JsonlCheckpoint::load(&path).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(
+        checkpoint::rebuild(&entries),
+        vec![Message::user_text("hi")]
+    );
+
+    // Anything else that doesn't parse is an error
+    dir.write("conversation.jsonl", "not json\n");
+    let error = JsonlCheckpoint::load(&path).unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("Invalid entry on line 1 of checkpoint"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn task_cut_off_by_a_failed_request_is_resumed() {
+    let workspace = TempDir::new();
+    let sessions = TempDir::new();
+    let session = Session::from_context(sessions.path(), &json!({"session_id": "s1"})).unwrap();
+
+    // The orchestrator's second request fails, after the file agent wrote a.txt
+    let worker = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("w1", "write", json!({"file_path": "a.txt", "content": "a"}))]),
+        text_turn("Wrote a.txt"),
+    ]));
+    let failing = Arc::new(ScriptedProvider::new(vec![tool_use_turn(&[(
+        "o1",
+        "file_agent",
+        json!({"task": "Write a.txt"}),
+    )])]));
+    let orchestrator = OrchestratorAgent::with_agents(
+        failing.clone(),
+        vec![Box::new(file_agent(worker.clone(), &workspace))],
+    );
+    assert!(orchestrator
+        .execute_in_session(&session, "Create a.txt")
+        .await
+        .is_err());
+
+    // A new process picks the task up where it stopped
+    let provider = Arc::new(ScriptedProvider::new(vec![text_turn("Created a.txt")]));
+    let orchestrator = OrchestratorAgent::with_agents(
+        provider.clone(),
+        vec![Box::new(file_agent(worker.clone(), &workspace))],
+    );
+    assert_eq!(
+        orchestrator.resume(&session).await.unwrap(),
+        "Created a.txt"
+    );
+    let request = &provider.requests()[0];
+    assert_eq!(request.messages.len(), 3);
+    assert_eq!(
+        tool_results(request),
+        vec![("o1".to_string(), "Wrote a.txt".to_string(), false)]
+    );
+
+    // The answer was checkpointed, so there is nothing left to resume
+    assert_eq!(session.load_history().unwrap().len(), 4);
+    let error = orchestrator.resume(&session).await.unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "orchestrator has no unfinished task to resume"
+    );
+}
+
+/// Provider that answers from a script and then never answers again, like a process that
+/// stopped while waiting for the model
+struct StallingProvider(ScriptedProvider);
+
+#[async_trait::async_trait]
+impl LlmProvider for StallingProvider {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn model(&self) -> &str {
+        self.0.model()
+    }
+
+    async fn send(&self, request: &LlmRequest) -> anyhow::Result<LlmResponse> {
+        if self.0.remaining() == 0 {
+            std::future::pending::<()>().await;
+        }
+        self.0.send(request).await
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn interrupted_delegation_resumes_from_the_file_agent_checkpoint() {
+    let workspace = TempDir::new();
+    let sessions = TempDir::new();
+    let session = Session::from_context(sessions.path(), &json!({"session_id": "s1"})).unwrap();
+
+    // The process stops while the file agent waits for its second response
+    let orchestrator = OrchestratorAgent::with_agents(
+        Arc::new(ScriptedProvider::new(vec![tool_use_turn(&[(
+            "o1",
+            "file_agent",
+            json!({"task": "Write a.txt and b.txt"}),
+        )])])),
+        vec![Box::new(file_agent(
+            Arc::new(StallingProvider(ScriptedProvider::new(vec![tool_use_turn(&[(
+                "w1",
+                "write",
+                json!({"file_path": "a.txt", "content": "a\n"}),
+            )])]))),
+            &workspace,
+        ))],
+    );
+    let stopped = tokio::time::timeout(
+        Duration::from_millis(500),
+        orchestrator.execute_in_session(&session, "Create a.txt and b.txt"),
+    )
+    .await;
+    assert!(stopped.is_err());
+    assert_eq!(workspace.read("a.txt"), "a\n");
+    assert_eq!(session.interrupted_calls().unwrap(), ["o1"]);
+    assert!(session.has_delegation("o1"));
+
+    // The file agent carries on after writing a.txt instead of starting over
+    let worker = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("w2", "write", json!({"file_path": "b.txt", "content": "b\n"}))]),
+        text_turn("Wrote a.txt and b.txt"),
+    ]));
+    let provider = Arc::new(ScriptedProvider::new(vec![text_turn("Created both")]));
+    let orchestrator = OrchestratorAgent::with_agents(
+        provider.clone(),
+        vec![Box::new(file_agent(worker.clone(), &workspace))],
+    );
+    assert_eq!(orchestrator.resume(&session).await.unwrap(), "Created both");
+    assert_eq!(workspace.read("b.txt"), "b\n");
+
+    let resumed = &worker.requests()[0];
+    assert_eq!(resumed.messages.len(), 3);
+    assert_eq!(
+        tool_results(resumed)
+            .iter()
+            .map(|(id, _, is_error)| (id.as_str(), *is_error))
+            .collect::<Vec<_>>(),
+        [("w1", false)]
+    );
+    assert_eq!(
+        tool_results(&provider.requests()[0]),
+        vec![("o1".to_string(), "Wrote a.txt and b.txt".to_string(), false)]
+    );
+
+    // The delegation's result was checkpointed with the orchestrator's answer
+    assert!(session.interrupted_calls().unwrap().is_empty());
+    let history = session.load_history().unwrap();
+    assert_eq!(history.len(), 4);
+    assert_eq!(session.load_delegation("o1").unwrap().len(), 6);
+}
@@ -0,0 +1,206 @@
+#![allow(dead_code)]
+
+use file_agent::agents::file::{FileAgent, Sandbox, Workspace};
+use file_agent::llm::{ContentBlock, LlmProvider, LlmRequest, ProviderKind, Role};
+use file_agent::policy::PermissionPolicy;
+use file_agent::retry::RetryConfig;
+use file_agent::usage::BudgetConfig;
+use file_agent::ClaudeConfig;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// Temporary workspace directory, removed on drop
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "file-agent-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self {
+            path: path.canonicalize().unwrap(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&self, name: &str, content: &str) {
+        let path = self.path.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
+        std::fs::write(path, content).unwrap();
+    }
+
+    pub fn read(&self, name: &str) -> String {
+        std::fs::read_to_string(self.path.join(name)).unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// File agent confined to `dir`, allowing every tool call
+pub fn file_agent(provider: Arc<dyn LlmProvider>, dir: &TempDir) -> FileAgent {
+    let workspace = Workspace::new(dir.path(), &[]).unwrap();
+    let policy = PermissionPolicy::allow_all(workspace.root());
+    FileAgent::with_provider(provider, workspace, policy, Sandbox::disabled())
+}
+
+/// Tool results sent back in the last message of a request, as (tool_use_id, content, is_error)
+pub fn tool_results(request: &LlmRequest) -> Vec<(String, String, bool)> {
+    let message = request.messages.last().expect("request has no messages");
+    assert_eq!(message.role, Role::User);
+    message
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+            } => Some((tool_use_id.clone(), content.clone(), *is_error)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Serve one request with a 200 response of the given content type, writing the body in the
+/// given chunks. Returns the server URL and a handle yielding the JSON request body.
+pub fn serve_http(content_type: &str, chunks: Vec<Vec<u8>>) -> (String, JoinHandle<Value>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v1/messages", listener.local_addr().unwrap());
+    let head = format!(
+        "HTTP/1.1 200 OK\r\ncontent-type: {}\r\nconnection: close\r\n\r\n",
+        content_type
+    );
+
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let body = read_request(&stream);
+
+        let mut stream = stream;
+        stream.write_all(head.as_bytes()).unwrap();
+        for chunk in chunks {
+            stream.write_all(&chunk).unwrap();
+            stream.flush().unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        serde_json::from_slice(&body).unwrap()
+    });
+
+    (url, handle)
+}
+
+/// Answer one request per connection with each of the given raw HTTP responses, in order.
+/// Returns the server URL and a handle yielding the JSON body of each request served.
+pub fn serve_responses(responses: Vec<String>) -> (String, JoinHandle<Vec<Value>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let url = format!("http://{}/v1/messages", listener.local_addr().unwrap());
+
+    let handle = thread::spawn(move || {
+        let mut requests = Vec::new();
+        for response in responses {
+            // Stop once the client gives up instead of waiting for requests that never come
+            let deadline = std::time::Instant::now() + Duration::from_millis(500);
+            let stream = loop {
+                match listener.accept() {
+                    Ok((stream, _)) => break Some(stream),
+                    Err(_) if std::time::Instant::now() < deadline => {
+                        thread::sleep(Duration::from_millis(5))
+                    }
+                    Err(_) => break None,
+                }
+            };
+            let Some(mut stream) = stream else {
+                break;
+            };
+            stream.set_nonblocking(false).unwrap();
+            let body = read_request(&stream);
+            stream.write_all(response.as_bytes()).unwrap();
+            requests.push(serde_json::from_slice(&body).unwrap());
+        }
+        requests
+    });
+
+    (url, handle)
+}
+
+/// Raw HTTP response with a JSON body and extra header lines
+pub fn http_response(status: &str, headers: &[&str], body: &str) -> String {
+    let mut head = format!(
+        "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n",
+        status,
+        body.len()
+    );
+    for header in headers {
+        head.push_str(header);
+        head.push_str("\r\n");
+    }
+    format!("{}\r\n{}", head, body)
+}
+
+/// Read a request's head and return its body
+fn read_request(stream: &TcpStream) -> Vec<u8> {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+    body
+}
+
+/// Anthropic config talking to `url`, without retries
+pub fn anthropic_config(url: &str) -> ClaudeConfig {
+    ClaudeConfig {
+        provider: ProviderKind::Anthropic,
+        script_path: None,
+        cassette: None,
+        api_key: "test-key".to_string(),
+        api_url: url.to_string(),
+        model: "claude-test".to_string(),
+        max_tokens: 1024,
+        temperature: 0.0,
+        timeout_seconds: 10,
+        stream: false,
+        prompt_caching: false,
+        retry: RetryConfig {
+            max_retries: 0,
+            ..RetryConfig::default()
+        },
+        budget: BudgetConfig::default(),
+        client: reqwest::Client::new(),
+    }
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use common::{file_agent, TempDir};
+use file_agent::agent::Agent;
+use file_agent::agents::file::result_store;
+use file_agent::compaction::{self, CompactionSettings};
+use file_agent::config::AgentSettings;
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::{ContentBlock, Message, Role, ScriptedProvider};
+use serde_json::json;
+use std::sync::Arc;
+
+fn round(calls: &[(&str, &str, serde_json::Value)], results: &[(&str, String)]) -> Vec<Message> {
+    vec![
+        Message {
+            role: Role::Assistant,
+            content: tool_use_turn(calls).content,
+        },
+        Message {
+            role: Role::User,
+            content: results
+                .iter()
+                .map(|(id, content)| ContentBlock::ToolResult {
+                    tool_use_id: id.to_string(),
+                    content: content.clone(),
+                    is_error: false,
+                })
+                .collect(),
+        },
+    ]
+}
+
+fn result_content(message: &Message, index: usize) -> &str {
+    match &message.content[index] {
+        ContentBlock::ToolResult { content, .. } => content,
+        other => panic!("expected a tool result, got {:?}", other),
+    }
+}
+
+#[test]
+fn old_results_are_stubbed_and_the_todo_list_is_kept() {
+    let file = "fn main() {}\n".repeat(400);
+    let todos = "Todo Progress: 0/2\n".repeat(20);
+
+    let mut messages = vec![Message::user_text("Refactor src/x.rs")];
+    messages.extend(round(
+        &[
+            ("t1", "read", json!({"file_path": "src/x.rs"})),
+            ("t2", "todo_write", json!({"todos": []})),
+        ],
+        &[("t1", file.clone()), ("t2", todos.clone())],
+    ));
+    messages.extend(round(
+        &[("t3", "grep", json!({"pattern": "main", "path": "src"}))],
+        &[("t3", "src/x.rs:1:fn main() {}\n".repeat(20))],
+    ));
+    messages.extend(round(
+        &[("t4", "read", json!({"file_path": "src/y.rs"}))],
+        &[("t4", file.clone())],
+    ));
+
+    assert_eq!(compaction::elide_old_results(&mut messages, 1), 2);
+    assert_eq!(
+        result_content(&messages[2], 0),
+        "read of src/x.rs, 400 lines, elided to save context. Run it again if you still need the output."
+    );
+    assert_eq!(result_content(&messages[2], 1), todos);
+    assert!(result_content(&messages[4], 0).starts_with("grep for 'main' in src, 20 lines, elided"));
+    // The latest round is untouched
+    assert_eq!(result_content(&messages[6], 0), file);
+
+    // Stubs aren't elided again
+    assert_eq!(compaction::elide_old_results(&mut messages, 1), 0);
+}
+
+#[test]
+fn stubs_of_stored_outputs_point_to_their_handle() {
+    let cut = format!(
+        "{}\n\n{}",
+        "fn main() {}\n".repeat(100),
+        result_store::note("002_read", 4000)
+    );
+
+    let mut messages = vec![Message::user_text("Refactor src/x.rs")];
+    messages.extend(round(
+        &[("t1", "read", json!({"file_path": "src/x.rs"}))],
+        &[("t1", cut)],
+    ));
+    messages.extend(round(
+        &[("t2", "read", json!({"file_path": "src/y.rs"}))],
+        &[("t2", "fn y() {}\n".repeat(100))],
+    ));
+
+    assert_eq!(compaction::elide_old_results(&mut messages, 1), 1);
+    assert_eq!(
+        result_content(&messages[2], 0),
+        "read of src/x.rs, 103 lines, elided to save context. Its full output is stored as 002_read. Use read_result with handle \"002_read\" if you still need it."
+    );
+}
+
+#[tokio::test]
+async fn older_rounds_are_summarized_past_the_threshold() {
+    let dir = TempDir::new();
+    dir.write("a.txt", &"some line of text in the file\n".repeat(100));
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[
+            ("t1", "read", json!({"file_path": "a.txt"})),
+            (
+                "t2",
+                "todo_write",
+                json!({"todos": [{"id": "1", "content": "Check a.txt", "status": "in_progress", "priority": "high"}]}),
+            ),
+        ]),
+        tool_use_turn(&[("t3", "read", json!({"file_path": "a.txt"}))]),
+        text_turn("Read a.txt and started checking it"),
+        text_turn("Done"),
+    ]));
+    let mut agent = file_agent(provider.clone(), &dir);
+    agent.apply_settings(&AgentSettings {
+        compaction: CompactionSettings {
+            threshold_tokens: 1,
+            keep_rounds: 1,
+            summarize: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    assert_eq!(agent.execute("Check a.txt").await.unwrap(), "Done");
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 4);
+
+    // The first round was elided, then summarized
+    let summary_request = &requests[2];
+    assert!(summary_request.system.starts_with("You summarize"));
+    assert!(summary_request.tools.is_empty());
+    match &summary_request.messages[0].content[0] {
+        ContentBlock::Text { text } => {
+            assert!(text.contains("User: Check a.txt"));
+            assert!(text.contains("read result:\nread of a.txt, "));
+            assert!(text.contains(" lines, elided to save context."));
+        }
+        other => panic!("expected the transcript, got {:?}", other),
+    }
+
+    let last = &requests[3].messages;
+    assert_eq!(last.len(), 3);
+    assert_eq!(last[1].role, Role::Assistant);
+    match &last[0].content[..] {
+        [ContentBlock::Text { text: task }, ContentBlock::Text { text: summary }] => {
+            assert_eq!(task, "Check a.txt");
+            assert!(summary.starts_with("Summary of the conversation so far"));
+            assert!(summary.contains("Read a.txt and started checking it"));
+            assert!(summary.contains("Current todo list:"));
+            assert!(summary.contains("Check a.txt"));
+        }
+        other => panic!("expected the task and summary, got {:?}", other),
+    }
+    // The latest round is sent as it was
+    assert!(result_content(&last[2], 0).contains("some line of text"));
+}
@@ -0,0 +1,334 @@
+mod common;
+
+use common::{file_agent, TempDir};
+use file_agent::agent::Agent;
+use file_agent::agents::file::{SandboxLevel, Workspace};
+use file_agent::config::{Layer, Settings, PROJECT_CONFIG_FILE};
+use file_agent::conversation::ConversationError;
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::{ProviderKind, ScriptedProvider};
+use file_agent::policy::{Decision, PermissionPolicy};
+use serde_json::json;
+use std::sync::Arc;
+
+fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+fn env(pairs: &[(&str, &str)]) -> Layer {
+    Layer::env(vars(pairs), ProviderKind::Anthropic).unwrap()
+}
+
+#[test]
+fn later_layers_override_earlier_ones() {
+    let file = Layer::toml(
+        "file-agent.toml",
+        r#"
+        [llm]
+        model = "from-file"
+        temperature = 0.1
+
+        [agents.file_agent]
+        max_rounds = 5
+        temperature = 0.3
+
+        [agents.file_agent.context.lines]
+        read = 500
+        "#,
+    )
+    .unwrap();
+    let env = env(&[("CLAUDE_MODEL", "from-env"), ("TASK_MAX_TOKENS", "1000")]);
+    let context = Layer::context(&json!({
+        "session_id": "s1",
+        "space": "default",
+        "config": {"agents": {"file_agent": {"max_rounds": 7}}}
+    }))
+    .unwrap()
+    .unwrap();
+
+    let settings = Settings::from_layers(&[file, env, context]).unwrap();
+
+    assert_eq!(settings.llm.model.as_deref(), Some("from-env"));
+    assert_eq!(settings.llm.temperature, 0.1);
+    assert_eq!(settings.llm.max_tokens, 8192);
+    assert_eq!(settings.budget().unwrap().max_tokens, Some(1000));
+
+    let agent = settings.agent("file_agent");
+    assert_eq!(agent.max_rounds, Some(7));
+    assert_eq!(agent.limits().max_rounds, 7);
+    assert_eq!(agent.context.lines.get("read"), Some(&500));
+    assert_eq!(agent.context.lines.get("grep"), None);
+
+    assert_eq!(settings.llm_for("file_agent").temperature, 0.3);
+    assert_eq!(settings.llm_for("orchestrator").temperature, 0.1);
+    assert_eq!(settings.agent("orchestrator").limits().max_rounds, 100);
+
+    // A context without config adds no layer
+    assert!(Layer::context(&json!({"session_id": "s1"}))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn model_variables_follow_the_provider() {
+    let pairs = [("CLAUDE_MODEL", "claude"), ("OPENAI_MODEL", "gpt")];
+
+    let settings = Settings::from_layers(&[env(&pairs)]).unwrap();
+    assert_eq!(settings.llm.model.as_deref(), Some("claude"));
+
+    let openai = Layer::env(vars(&pairs), ProviderKind::OpenAi).unwrap();
+    let settings = Settings::from_layers(&[openai]).unwrap();
+    assert_eq!(settings.llm.model.as_deref(), Some("gpt"));
+
+    let mut pairs = pairs.to_vec();
+    pairs.push(("LLM_PROVIDER", "openai"));
+    let settings = Settings::from_layers(&[env(&pairs)]).unwrap();
+    assert_eq!(settings.llm.provider, ProviderKind::OpenAi);
+    assert_eq!(settings.llm.model.as_deref(), Some("gpt"));
+}
+
+#[test]
+fn invalid_values_are_errors() {
+    let error = |layer: anyhow::Result<Layer>| layer.unwrap_err().to_string();
+
+    assert_eq!(
+        error(Layer::env(
+            vars(&[("CLAUDE_TEMPERATURE", "abc")]),
+            ProviderKind::Anthropic
+        )),
+        "Invalid value for CLAUDE_TEMPERATURE: 'abc' is not a number"
+    );
+    assert_eq!(
+        error(Layer::env(
+            vars(&[("CLAUDE_STREAM", "maybe")]),
+            ProviderKind::Anthropic
+        )),
+        "Invalid value for CLAUDE_STREAM: 'maybe' is not true or false"
+    );
+    assert_eq!(
+        error(Layer::toml("a.toml", "[llm]\ntemperature = 3.5\n")),
+        "Invalid settings in a.toml: llm.temperature must be between 0 and 2, got 3.5"
+    );
+    assert_eq!(
+        error(Layer::toml(
+            "a.toml",
+            "[agents.file_agent]\nmax_rounds = 0\n"
+        )),
+        "Invalid settings in a.toml: agents.file_agent.max_rounds must be greater than 0, got 0"
+    );
+    assert!(error(Layer::toml(
+        "a.toml",
+        "[agents.file_agent.tools]\ndisabled = [\"rm\"]\n"
+    ))
+    .starts_with("Invalid settings in a.toml: Unknown tool 'rm' in agents.file_agent.tools."));
+    assert_eq!(
+        error(Layer::toml(
+            "a.toml",
+            "[agents.file_agent.context.lines]\ngrep = 0\n"
+        )),
+        "Invalid settings in a.toml: agents.file_agent.context.lines.grep must be greater than 0, got 0"
+    );
+    assert!(
+        error(Layer::toml("a.toml", "[agents.coder]\nmodel = \"x\"\n"))
+            .starts_with("Invalid settings in a.toml: Unknown agent 'coder' in agents.")
+    );
+    assert!(
+        error(Layer::toml("a.toml", "[llm]\nmodle = \"x\"\n")).contains("unknown field `modle`")
+    );
+    assert!(error(
+        Layer::context(&json!({"config": {"llm": {"max_tokens": "lots"}}}))
+            .map(|layer| layer.unwrap())
+    )
+    .starts_with("Invalid settings in Raworc context: invalid type: string \"lots\""));
+
+    // Each price is valid on its own, but not without the other
+    let error = Settings::from_layers(&[env(&[("LLM_INPUT_PRICE_PER_MTOK", "3")])]).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("budget.input_price_per_mtok and budget.output_price_per_mtok"));
+}
+
+#[test]
+fn context_can_only_change_allowed_keys() {
+    let context = |config: serde_json::Value| Layer::context(&json!({ "config": config }));
+
+    let layer = context(json!({
+        "llm": {"model": "claude-x", "max_tokens": 2048},
+        "budget": {"max_cost_usd": 1.5},
+        "agents": {"file_agent": {"max_rounds": 3}}
+    }))
+    .unwrap()
+    .unwrap();
+    let settings = Settings::from_layers(&[layer]).unwrap();
+    assert_eq!(settings.llm.model.as_deref(), Some("claude-x"));
+    assert_eq!(settings.agent("file_agent").max_rounds, Some(3));
+
+    for (config, key) in [
+        (json!({"llm": {"api_url": "https://evil.example"}}), "llm.api_url"),
+        (json!({"llm": {"provider": "openai"}}), "llm.provider"),
+        (json!({"workspace": {"root": "/"}}), "workspace.root"),
+        (json!({"sandbox": {"level": "off"}}), "sandbox.level"),
+        (
+            json!({"agents": {"file_agent": {"tools": {"enabled": ["bash"]}}}}),
+            "agents.file_agent.tools.enabled",
+        ),
+    ] {
+        let error = context(config).unwrap_err().to_string();
+        assert!(
+            error.starts_with(&format!(
+                "Invalid settings in Raworc context: {} can't be set from a message context.",
+                key
+            )),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn project_file_comes_from_the_user_root_and_cant_change_operator_settings() {
+    let home = TempDir::new();
+    let project = TempDir::new();
+    home.write(
+        "file-agent/config.toml",
+        &format!("[workspace]\nroot = \"{}\"\n", project.path().display()),
+    );
+    project.write(PROJECT_CONFIG_FILE, "[llm]\nmodel = \"claude-project\"\n");
+    std::env::set_var("XDG_CONFIG_HOME", home.path());
+    std::env::remove_var("FILE_AGENT_CONFIG");
+    std::env::remove_var("FILE_AGENT_WORKSPACE_ROOT");
+
+    let settings = Settings::load(&serde_json::Value::Null).unwrap();
+    assert_eq!(settings.llm.model.as_deref(), Some("claude-project"));
+    assert_eq!(settings.workspace.root.as_deref(), Some(project.path()));
+
+    for (content, key) in [
+        ("[llm]\napi_url = \"https://evil.example\"\n", "llm.api_url"),
+        ("[workspace]\nroot = \"/\"\n", "workspace"),
+        ("[sandbox]\nlevel = \"off\"\n", "sandbox"),
+        ("[session]\ndir = \"sessions\"\n", "session"),
+    ] {
+        project.write(PROJECT_CONFIG_FILE, content);
+        let error = Settings::load(&serde_json::Value::Null)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains(&format!("{} can only be set outside the workspace", key)),
+            "{}",
+            error
+        );
+    }
+}
+
+#[tokio::test]
+async fn agent_settings_limit_tools_and_rounds() {
+    let dir = TempDir::new();
+    let settings = Settings::from_layers(&[Layer::toml(
+        "file-agent.toml",
+        r#"
+        [agents.file_agent]
+        max_rounds = 1
+
+        [agents.file_agent.tools]
+        disabled = ["bash", "bash_kill"]
+        "#,
+    )
+    .unwrap()])
+    .unwrap();
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("t1", "ls", json!({"path": "."}))]),
+        text_turn("never reached"),
+    ]));
+    let mut agent = file_agent(provider.clone(), &dir);
+    agent.apply_settings(&settings.agent("file_agent"));
+
+    let error = agent.execute("List files").await.unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<ConversationError>(),
+        Some(ConversationError::MaxRoundsReached { max_rounds: 1, .. })
+    ));
+
+    let tools: Vec<String> = provider.requests()[0]
+        .tools
+        .iter()
+        .map(|tool| tool.name.clone())
+        .collect();
+    assert!(tools.contains(&"bash_output".to_string()));
+    assert!(!tools.contains(&"bash".to_string()));
+    assert!(!tools.contains(&"bash_kill".to_string()));
+}
+
+#[test]
+fn workspace_and_sandbox_come_from_settings() {
+    let dir = TempDir::new();
+    let docs = TempDir::new();
+    let etc = TempDir::new();
+    etc.write(
+        "policy.toml",
+        "[[rules]]\ntool = \"write\"\ndecision = \"deny\"\n",
+    );
+
+    let file = Layer::toml(
+        "file-agent.toml",
+        &format!(
+            r#"
+            [workspace]
+            root = "{}"
+            policy = "{}"
+
+            [sandbox]
+            level = "workspace"
+
+            [sandbox.limits]
+            memory_mb = 512
+            "#,
+            dir.path().display(),
+            etc.path().join("policy.toml").display()
+        ),
+    )
+    .unwrap();
+    let env = env(&[
+        ("FILE_AGENT_SANDBOX", "limits"),
+        (
+            "FILE_AGENT_READ_ONLY_ROOTS",
+            &docs.path().display().to_string(),
+        ),
+    ]);
+    let settings = Settings::from_layers(&[file, env]).unwrap();
+
+    assert_eq!(settings.sandbox.level, SandboxLevel::Limits);
+    assert_eq!(settings.sandbox.limits.memory_mb, 512);
+    assert_eq!(settings.sandbox.limits.cpu_seconds, 600);
+    // Sessions are kept out of the workspace unless configured otherwise
+    assert!(settings.session.dir.is_absolute());
+    assert!(!settings.session.dir.starts_with(dir.path()));
+
+    let workspace = Workspace::from_settings(&settings.workspace).unwrap();
+    assert_eq!(workspace.root(), dir.path().canonicalize().unwrap());
+    assert_eq!(
+        workspace.read_only_roots(),
+        [docs.path().canonicalize().unwrap()]
+    );
+
+    let policy =
+        PermissionPolicy::load(settings.workspace.policy.as_deref(), workspace.root()).unwrap();
+    assert_eq!(
+        policy
+            .evaluate("write", r#"{"file_path": "a.txt"}"#)
+            .decision,
+        Decision::Deny
+    );
+
+    // The agent could rewrite a policy inside the workspace
+    dir.write("policy.toml", "default = \"allow\"\n");
+    let error = PermissionPolicy::load(Some(&dir.path().join("policy.toml")), workspace.root())
+        .unwrap_err();
+    assert!(error.to_string().contains("is inside the workspace"));
+
+    let error = Layer::toml("a.toml", "[sandbox]\nlevel = \"tight\"\n").unwrap_err();
+    assert!(error.to_string().contains("Invalid sandbox level 'tight'"));
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::{file_agent, TempDir};
+use file_agent::agent::Agent;
+use file_agent::agents::file::context_manager::{ContextLimits, ContextManager};
+use file_agent::conversation::{CallResult, ResultProcessor};
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::{ContentBlock, ScriptedProvider};
+use file_agent::tokens;
+use serde_json::json;
+use std::sync::Arc;
+
+fn result(id: &str, output: String) -> CallResult {
+    CallResult {
+        tool_use_id: id.to_string(),
+        name: "bash".to_string(),
+        output,
+        is_error: false,
+    }
+}
+
+#[test]
+fn estimates_and_cuts_land_on_character_boundaries() {
+    assert_eq!(tokens::estimate(""), 0);
+    assert_eq!(tokens::estimate("abcd"), 1);
+    assert_eq!(tokens::estimate("abcde"), 2);
+    assert_eq!(tokens::estimate("日本語"), 3);
+
+    let text = "ab日本語cd";
+    assert_eq!(tokens::prefix(text, 1), "ab");
+    assert_eq!(tokens::prefix(text, 2), "ab日");
+    assert_eq!(tokens::suffix(text, 1), "cd");
+    assert_eq!(tokens::suffix(text, 2), "語cd");
+    assert_eq!(tokens::prefix(text, 100), text);
+
+    // A byte limit inside a character backs off to the character before it
+    let manager = ContextManager::with_limits(ContextLimits {
+        output_bytes: 4,
+        ..Default::default()
+    });
+    assert_eq!(
+        manager.truncate_content("aéééé"),
+        "aé... [TRUNCATED - 9 total bytes]"
+    );
+}
+
+#[test]
+fn round_budget_is_shared_and_shrinks_as_the_conversation_grows() {
+    let manager = ContextManager::with_limits(ContextLimits {
+        context_tokens: 100_000,
+        turn_tokens: 10_000,
+        ..Default::default()
+    });
+    assert_eq!(manager.turn_budget(0), 10_000);
+    assert_eq!(manager.turn_budget(50_000), 5_000);
+    // Never below the minimum, even with a full context window
+    assert_eq!(manager.turn_budget(150_000), 2_000);
+
+    let small = "ok ".repeat(100);
+    let large = "é".repeat(20_000);
+    let results = vec![
+        result("t1", large.clone()),
+        result("t2", small.clone()),
+        result("t3", large.clone()),
+    ];
+
+    let fitted = manager.process(results.clone(), 0).unwrap();
+    assert_eq!(fitted[1].output, small);
+    for output in [&fitted[0].output, &fitted[2].output] {
+        assert!(output.contains("[TRUNCATED - showing about"));
+        let size = tokens::estimate(output);
+        assert!(size <= (10_000 - tokens::estimate(&small)) / 2, "{}", size);
+        assert!(size > 4_000, "{}", size);
+    }
+
+    // The same results get less room in a fuller conversation
+    let fuller = manager.process(results, 50_000).unwrap();
+    assert!(tokens::estimate(&fuller[0].output) <= 2_500);
+    assert_eq!(fuller[1].output, small);
+}
+
+#[tokio::test]
+async fn file_agent_results_fit_the_round_budget() {
+    let dir = TempDir::new();
+    dir.write("big.txt", &"línea de texto\n".repeat(1_500));
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("t1", "read", json!({"file_path": "big.txt"}))]),
+        text_turn("Read it"),
+    ]));
+    let mut agent = file_agent(provider.clone(), &dir);
+    agent.apply_settings(&file_agent::config::AgentSettings {
+        context: ContextLimits {
+            turn_tokens: 3_000,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    assert_eq!(agent.execute("Read big.txt").await.unwrap(), "Read it");
+    match &provider.requests()[1].messages[2].content[0] {
+        ContentBlock::ToolResult { content, .. } => {
+            assert!(content.contains("[TRUNCATED - showing about"));
+            assert!(tokens::estimate(content) <= 3_000);
+        }
+        other => panic!("expected a tool result, got {:?}", other),
+    }
+}
@@ -0,0 +1,288 @@
+mod common;
+
+use common::{file_agent, tool_results, TempDir};
+use file_agent::agent::Agent;
+use file_agent::agents::file::tools::{BashTool, ProcessRegistry, ShellSession};
+use file_agent::agents::file::{FileAgent, Sandbox, Workspace};
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::{ContentBlock, Role, ScriptedProvider};
+use file_agent::policy::PermissionPolicy;
+use file_agent::tool::Tool;
+use serde_json::json;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn read_result_is_sent_back_to_the_model() {
+    let dir = TempDir::new();
+    dir.write("notes.txt", "hello from the workspace\n");
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("t1", "read", json!({"file_path": "notes.txt"}))]),
+        text_turn("The file says hello"),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    let answer = agent.execute("What does notes.txt say?").await.unwrap();
+    assert_eq!(answer, "The file says hello");
+
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[0].messages[0].content,
+        vec![ContentBlock::Text {
+            text: "What does notes.txt say?".to_string()
+        }]
+    );
+    assert!(requests[0].tools.iter().any(|tool| tool.name == "read"));
+
+    let results = tool_results(&requests[1]);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0, "t1");
+    assert!(results[0].1.contains("hello from the workspace"));
+    assert!(!results[0].2);
+}
+
+#[tokio::test]
+async fn write_then_edit_changes_the_file_on_disk() {
+    let dir = TempDir::new();
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[(
+            "t1",
+            "write",
+            json!({"file_path": "main.rs", "content": "fn main() {\n    println!(\"old\");\n}\n"}),
+        )]),
+        tool_use_turn(&[(
+            "t2",
+            "edit",
+            json!({"file_path": "main.rs", "old_string": "old", "new_string": "new"}),
+        )]),
+        tool_use_turn(&[(
+            "t3",
+            "multi_edit",
+            json!({"file_path": "main.rs", "edits": [
+                {"old_string": "fn main", "new_string": "pub fn main"},
+                {"old_string": "new", "new_string": "newer"}
+            ]}),
+        )]),
+        text_turn("Done"),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    assert_eq!(agent.execute("Create and update main.rs").await.unwrap(), "Done");
+    assert_eq!(
+        dir.read("main.rs"),
+        "pub fn main() {\n    println!(\"newer\");\n}\n"
+    );
+
+    for request in &provider.requests()[1..] {
+        let results = tool_results(request);
+        assert!(!results[0].2, "tool failed: {}", results[0].1);
+    }
+}
+
+#[tokio::test]
+async fn parallel_calls_are_answered_in_one_message_in_order() {
+    let dir = TempDir::new();
+    dir.write("a.txt", "alpha\n");
+    dir.write("b.txt", "beta\n");
+    dir.write("nested/c.rs", "fn c() {}\n");
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[
+            ("t1", "read", json!({"file_path": "a.txt"})),
+            ("t2", "glob", json!({"pattern": "**/*.rs"})),
+            ("t3", "ls", json!({"path": "."})),
+            ("t4", "read", json!({"file_path": "b.txt"})),
+        ]),
+        text_turn("Looked around"),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    agent.execute("Look around").await.unwrap();
+
+    let request = &provider.requests()[1];
+    // user task, assistant tool_use turn, one user message with every result
+    assert_eq!(request.messages.len(), 3);
+    assert_eq!(request.messages[1].role, Role::Assistant);
+
+    let results = tool_results(request);
+    let ids: Vec<&str> = results.iter().map(|(id, _, _)| id.as_str()).collect();
+    assert_eq!(ids, ["t1", "t2", "t3", "t4"]);
+    assert!(results[0].1.contains("alpha"));
+    assert!(results[1].1.contains("c.rs"));
+    assert!(results[2].1.contains("a.txt"));
+    assert!(results[3].1.contains("beta"));
+}
+
+#[tokio::test]
+async fn bash_keeps_its_working_directory_within_a_task() {
+    let dir = TempDir::new();
+    dir.write("sub/marker.txt", "marker\n");
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("t1", "bash", json!({"command": "cd sub && export GREETING=hi"}))]),
+        tool_use_turn(&[("t2", "bash", json!({"command": "ls; echo $GREETING"}))]),
+        text_turn("Done"),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    agent.execute("Run some commands").await.unwrap();
+
+    let results = tool_results(&provider.requests()[2]);
+    assert!(!results[0].2);
+    assert!(results[0].1.contains("marker.txt"));
+    assert!(results[0].1.contains("hi"));
+}
+
+#[tokio::test]
+async fn bash_keeps_its_working_directory_across_tasks() {
+    let dir = TempDir::new();
+    dir.write("sub/marker.txt", "marker\n");
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("t1", "bash", json!({"command": "cd sub && export GREETING=hi"}))]),
+        text_turn("Moved"),
+        tool_use_turn(&[("t2", "bash", json!({"command": "ls; echo $GREETING"}))]),
+        text_turn("Listed"),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    agent.execute("Go to sub").await.unwrap();
+    agent.execute("List files").await.unwrap();
+
+    let results = tool_results(&provider.requests()[3]);
+    assert!(results[0].1.contains("marker.txt"));
+    assert!(results[0].1.contains("hi"));
+}
+
+#[tokio::test]
+async fn concurrent_tasks_keep_each_others_shell_state() {
+    let dir = TempDir::new();
+    dir.write("sub/marker.txt", "marker\n");
+    let workspace = Workspace::new(dir.path(), &[]).unwrap();
+    let session = Arc::new(tokio::sync::Mutex::new(ShellSession::new(workspace.root())));
+    let bash = || {
+        BashTool::new(
+            workspace.clone(),
+            ProcessRegistry::new(),
+            Sandbox::disabled(),
+        )
+        .with_session(session.clone())
+    };
+    let (slow, fast) = (bash(), bash());
+
+    let (moved, exported) = tokio::join!(
+        slow.execute(r#"{"command": "cd sub && sleep 0.3"}"#),
+        async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            fast.execute(r#"{"command": "export GREETING=hi"}"#).await
+        }
+    );
+    moved.unwrap();
+    exported.unwrap();
+
+    let state = slow.session().await;
+    assert_eq!(state.cwd, workspace.root().join("sub"));
+    assert_eq!(state.env.get("GREETING").map(String::as_str), Some("hi"));
+}
+
+#[tokio::test]
+async fn dangerous_bash_commands_are_reported_as_errors() {
+    let dir = TempDir::new();
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("t1", "bash", json!({"command": "sudo rm -rf /"}))]),
+        text_turn("Refused"),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    agent.execute("Clean up").await.unwrap();
+
+    let results = tool_results(&provider.requests()[1]);
+    assert!(results[0].2);
+    assert!(results[0].1.contains("Forbidden command"));
+}
+
+#[tokio::test]
+async fn paths_outside_the_workspace_are_rejected() {
+    let dir = TempDir::new();
+    let outside = TempDir::new();
+    outside.write("secret.txt", "secret\n");
+    let secret = outside.path().join("secret.txt");
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[
+            ("t1", "read", json!({"file_path": secret})),
+            ("t2", "write", json!({"file_path": "../escape.txt", "content": "x"})),
+        ]),
+        text_turn("Could not access"),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    agent.execute("Read the secret").await.unwrap();
+
+    let results = tool_results(&provider.requests()[1]);
+    assert!(results.iter().all(|(_, _, is_error)| *is_error));
+    assert!(results[0].1.contains("outside the workspace root"));
+    assert!(!dir.path().parent().unwrap().join("escape.txt").exists());
+}
+
+#[tokio::test]
+async fn unknown_tools_are_reported_as_errors() {
+    let dir = TempDir::new();
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("t1", "teleport", json!({}))]),
+        text_turn("No such tool"),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    agent.execute("Teleport").await.unwrap();
+
+    let results = tool_results(&provider.requests()[1]);
+    assert_eq!(
+        results,
+        vec![("t1".to_string(), "Tool not found: teleport".to_string(), true)]
+    );
+}
+
+#[tokio::test]
+async fn policy_denials_are_returned_to_the_model() {
+    let dir = TempDir::new();
+    dir.write(
+        "policy.toml",
+        "[[rules]]\ntool = \"write\"\npath = \"**/.env*\"\ndecision = \"deny\"\nreason = \"secrets\"\n",
+    );
+
+    let workspace = Workspace::new(dir.path(), &[]).unwrap();
+    let policy = PermissionPolicy::from_file(&dir.path().join("policy.toml"), workspace.root()).unwrap();
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("t1", "write", json!({"file_path": ".env", "content": "KEY=1"}))]),
+        text_turn("Denied"),
+    ]));
+    let agent = FileAgent::with_provider(provider.clone(), workspace, policy, Sandbox::disabled());
+
+    agent.execute("Write the env file").await.unwrap();
+
+    let results = tool_results(&provider.requests()[1]);
+    assert!(results[0].2);
+    assert!(results[0].1.starts_with("Permission denied: write is not allowed by policy (secrets)"));
+    assert!(!dir.path().join(".env").exists());
+}
+
+#[tokio::test]
+async fn running_out_of_script_is_an_error() {
+    let dir = TempDir::new();
+
+    let provider = Arc::new(ScriptedProvider::new(vec![tool_use_turn(&[(
+        "t1",
+        "ls",
+        json!({"path": "."}),
+    )])]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    let error = agent.execute("List").await.unwrap_err();
+    assert!(error.to_string().contains("no turn left"));
+    assert_eq!(provider.remaining(), 0);
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use common::{file_agent, TempDir};
+use file_agent::agent::Agent;
+use file_agent::config::{AgentSettings, Layer, Settings};
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::{ContentBlock, ProviderKind, ScriptedProvider, StopReason};
+use serde_json::json;
+use std::sync::Arc;
+
+#[test]
+fn agents_phases_and_escalation_pick_their_own_models() {
+    let file = Layer::toml(
+        "file-agent.toml",
+        r#"
+        [llm]
+        model = "default"
+        temperature = 0.5
+
+        [agents.orchestrator]
+        model = "router"
+        temperature = 0.0
+
+        [agents.file_agent]
+        max_tokens = 4096
+
+        [[agents.file_agent.phases]]
+        tools = ["edit", "multi_edit"]
+        model = "editor"
+
+        [agents.file_agent.escalation]
+        model = "stronger"
+        temperature = 0.2
+        "#,
+    )
+    .unwrap();
+    let env = Layer::env(
+        vec![("FILE_AGENT_MODEL".to_string(), "worker".to_string())],
+        ProviderKind::Anthropic,
+    )
+    .unwrap();
+    let settings = Settings::from_layers(&[file, env]).unwrap();
+
+    let orchestrator = settings.llm_for("orchestrator");
+    assert_eq!(orchestrator.model.as_deref(), Some("router"));
+    assert_eq!(orchestrator.temperature, 0.0);
+
+    let worker = settings.llm_for("file_agent");
+    assert_eq!(worker.model.as_deref(), Some("worker"));
+    assert_eq!(worker.temperature, 0.5);
+    assert_eq!(worker.max_tokens, 4096);
+
+    let agent = settings.agent("file_agent");
+    let editor = settings.llm_for_phase("file_agent", &agent.phases[0]);
+    assert_eq!(editor.model.as_deref(), Some("editor"));
+    assert_eq!(editor.max_tokens, 4096);
+
+    let stronger = settings.llm_for_escalation("file_agent", agent.escalation.as_ref().unwrap());
+    assert_eq!(stronger.model.as_deref(), Some("stronger"));
+    assert_eq!(stronger.temperature, 0.2);
+
+    let error = Layer::toml(
+        "a.toml",
+        "[agents.orchestrator.escalation]\nmodel = \"x\"\n",
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid settings in a.toml: agents.orchestrator.escalation is only supported for file_agent"
+    );
+}
+
+#[tokio::test]
+async fn rounds_after_a_phase_tool_use_the_phase_provider() {
+    let dir = TempDir::new();
+    dir.write("a.txt", "old\n");
+
+    let default = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("t1", "read", json!({"file_path": "a.txt"}))]),
+        tool_use_turn(&[(
+            "t2",
+            "edit",
+            json!({"file_path": "a.txt", "old_string": "old", "new_string": "new"}),
+        )]),
+        text_turn("Edited a.txt"),
+    ]));
+    let editor = Arc::new(ScriptedProvider::new(vec![tool_use_turn(&[(
+        "t3",
+        "read",
+        json!({"file_path": "a.txt"}),
+    )])]));
+    let mut agent = file_agent(default.clone(), &dir);
+    agent.add_phase(vec!["edit".to_string()], editor.clone());
+
+    assert_eq!(agent.execute("Edit a.txt").await.unwrap(), "Edited a.txt");
+    assert_eq!(dir.read("a.txt").trim(), "new");
+
+    // read -> default, edit -> editor, read -> default again
+    assert_eq!(default.requests().len(), 3);
+    assert_eq!(editor.requests().len(), 1);
+    assert_eq!(editor.requests()[0].messages.len(), 5);
+}
+
+#[tokio::test]
+async fn failed_attempt_is_escalated_to_the_stronger_model() {
+    let dir = TempDir::new();
+
+    let weak = Arc::new(ScriptedProvider::new(vec![tool_use_turn(&[(
+        "t1",
+        "ls",
+        json!({"path": "."}),
+    )])]));
+    let strong = Arc::new(ScriptedProvider::new(vec![text_turn("Done properly")]));
+    let mut agent = file_agent(weak.clone(), &dir);
+    agent.apply_settings(&AgentSettings {
+        max_rounds: Some(1),
+        ..Default::default()
+    });
+    agent.set_escalation(strong.clone());
+
+    assert_eq!(agent.execute("List files").await.unwrap(), "Done properly");
+    assert_eq!(weak.requests().len(), 1);
+
+    let retry = &strong.requests()[0];
+    assert_eq!(retry.messages.len(), 1);
+    match &retry.messages[0].content[0] {
+        ContentBlock::Text { text } => {
+            assert!(text.starts_with("List files\n\nA previous attempt at this task did not finish: file_agent stopped after reaching the maximum of 1 rounds"));
+            assert!(text.contains("check their current state"));
+        }
+        other => panic!("expected the task, got {:?}", other),
+    }
+}
+
+
+#[tokio::test]
+async fn refused_attempt_is_escalated_to_the_stronger_model() {
+    let dir = TempDir::new();
+
+    let weak = Arc::new(ScriptedProvider::new(vec![{
+        let mut turn = text_turn("I can't help with that.");
+        turn.stop_reason = Some(StopReason::Refusal);
+        turn
+    }]));
+    let strong = Arc::new(ScriptedProvider::new(vec![text_turn("Done properly")]));
+    let mut agent = file_agent(weak.clone(), &dir);
+    agent.set_escalation(strong.clone());
+
+    assert_eq!(agent.execute("List files").await.unwrap(), "Done properly");
+    assert_eq!(weak.requests().len(), 1);
+    match &strong.requests()[0].messages[0].content[0] {
+        ContentBlock::Text { text } => assert!(text.contains(
+            "A previous attempt at this task did not finish: file_agent model refused the request"
+        )),
+        other => panic!("expected the task, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn provider_errors_are_not_escalated() {
+    let dir = TempDir::new();
+
+    // An empty script fails the first request, like an auth or config error from the API
+    let failing = Arc::new(ScriptedProvider::new(vec![]));
+    let strong = Arc::new(ScriptedProvider::new(vec![text_turn("Done properly")]));
+    let mut agent = file_agent(failing.clone(), &dir);
+    agent.set_escalation(strong.clone());
+
+    assert!(agent.execute("List files").await.is_err());
+    assert_eq!(failing.requests().len(), 1);
+    assert!(strong.requests().is_empty());
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{file_agent, tool_results, TempDir};
+use file_agent::agent::Agent;
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::ScriptedProvider;
+use file_agent::OrchestratorAgent;
+use serde_json::json;
+use std::sync::Arc;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn orchestrator_delegates_to_the_file_agent() {
+    let dir = TempDir::new();
+    dir.write("README.md", "# Project\n");
+
+    // Orchestrator and file agent share the script, so turns are listed in call order
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("o1", "file_agent", json!({"task": "Read README.md"}))]),
+        tool_use_turn(&[("t1", "read", json!({"file_path": "README.md"}))]),
+        text_turn("README.md has a Project heading"),
+        text_turn("The project README has a single heading"),
+    ]));
+    let orchestrator = OrchestratorAgent::with_agents(
+        provider.clone(),
+        vec![Box::new(file_agent(provider.clone(), &dir))],
+    );
+
+    let answer = orchestrator.execute("Summarize the README").await.unwrap();
+    assert_eq!(answer, "The project README has a single heading");
+
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 4);
+    assert_eq!(requests[0].tools.len(), 1);
+    assert_eq!(requests[0].tools[0].name, "file_agent");
+    assert!(tool_results(&requests[2])[0].1.contains("# Project"));
+    assert_eq!(
+        tool_results(&requests[3]),
+        vec![(
+            "o1".to_string(),
+            "README.md has a Project heading".to_string(),
+            false
+        )]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scripted_provider_is_selectable_through_config() {
+    let dir = TempDir::new();
+    dir.write("data.txt", "42\n");
+    dir.write(
+        "script.json",
+        &json!([
+            {"content": [{"type": "tool_use", "id": "o1", "name": "file_agent", "input": {"task": "read data.txt"}}]},
+            {"content": [{"type": "tool_use", "id": "t1", "name": "read", "input": {"file_path": "data.txt"}}]},
+            {"content": [{"type": "text", "text": "It is 42"}]},
+            {"content": [{"type": "text", "text": "The answer is 42"}]}
+        ])
+        .to_string(),
+    );
+
+    // The only test in this binary that touches the environment
+    std::env::set_var("LLM_PROVIDER", "scripted");
+    std::env::set_var("LLM_SCRIPT", dir.path().join("script.json"));
+    std::env::set_var("FILE_AGENT_WORKSPACE_ROOT", dir.path());
+
+    let orchestrator = OrchestratorAgent::new().unwrap();
+    let answer = orchestrator.execute("What is in data.txt?").await.unwrap();
+    assert_eq!(answer, "The answer is 42");
+
+    let provider = ScriptedProvider::shared(&dir.path().join("script.json")).unwrap();
+    assert_eq!(provider.requests().len(), 4);
+    assert_eq!(provider.remaining(), 0);
+}
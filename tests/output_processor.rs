@@ -0,0 +1,127 @@
+mod common;
+
+use anyhow::Result;
+use common::{file_agent, tool_results, TempDir};
+use file_agent::agent::Agent;
+use file_agent::agents::file::context_manager::{ContextLimits, ContextManager};
+use file_agent::conversation::{CallResult, ResultProcessor};
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::ScriptedProvider;
+use file_agent::output::OutputProcessor;
+use file_agent::tool::Tool;
+use file_agent::utils;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Reports how many lines it dropped instead of showing any of them
+struct LineCount;
+
+impl OutputProcessor for LineCount {
+    fn max_lines(&self) -> usize {
+        10
+    }
+
+    fn shorten(&self, output: &str, max_lines: usize) -> String {
+        let lines = output.lines().count();
+        if lines <= max_lines {
+            return output.to_string();
+        }
+        format!("{} lines", lines)
+    }
+}
+
+struct LogTool;
+
+#[async_trait::async_trait]
+impl Tool for LogTool {
+    fn name(&self) -> &str {
+        "log"
+    }
+
+    fn description(&self) -> &str {
+        "Print the log"
+    }
+
+    fn parameters(&self) -> Value {
+        json!({"type": "object", "properties": {}})
+    }
+
+    async fn execute(&self, _arguments: &str) -> Result<String> {
+        Ok(String::new())
+    }
+
+    fn output_processor(&self) -> Option<&dyn OutputProcessor> {
+        Some(&LineCount)
+    }
+}
+
+fn result(name: &str, lines: usize) -> CallResult {
+    CallResult {
+        tool_use_id: format!("t_{}", name),
+        name: name.to_string(),
+        output: (1..=lines).map(|i| format!("line {}\n", i)).collect(),
+        is_error: false,
+    }
+}
+
+#[test]
+fn tools_shorten_their_own_outputs_at_configured_thresholds() {
+    let mut tools: BTreeMap<String, Box<dyn Tool>> = BTreeMap::new();
+    tools.insert("log".to_string(), Box::new(LogTool));
+
+    let mut manager = ContextManager::new();
+    manager.set_store_results(false);
+    let processed = manager
+        .for_tools(&tools)
+        .process(vec![result("log", 12), result("other", 12)], 0)
+        .unwrap();
+    assert_eq!(processed[0].output, "12 lines");
+    // Tools without a processor keep their output
+    assert_eq!(processed[1].output, result("other", 12).output);
+
+    let mut manager = ContextManager::with_limits(ContextLimits {
+        lines: BTreeMap::from([("log".to_string(), 20)]),
+        ..Default::default()
+    });
+    manager.set_store_results(false);
+    let processed = manager
+        .for_tools(&tools)
+        .process(vec![result("log", 12)], 0)
+        .unwrap();
+    assert_eq!(processed[0].output, result("log", 12).output);
+}
+
+#[tokio::test]
+async fn find_and_bash_outputs_are_excerpted_once() {
+    let dir = TempDir::new();
+    let messages = TempDir::new();
+    for i in 0..150 {
+        dir.write(&format!("files/f{:03}.txt", i), "");
+    }
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[
+            ("t1", "find", json!({"path": "files", "name": ".txt"})),
+            ("t2", "bash", json!({"command": "seq 1 500"})),
+        ]),
+        text_turn("Done"),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    let answer =
+        utils::with_message_dir(messages.path().to_path_buf(), agent.execute("Look around")).await;
+    assert_eq!(answer.unwrap(), "Done");
+
+    let requests = provider.requests();
+    let results = tool_results(&requests[1]);
+    let find = &results[0].1;
+    assert!(find.starts_with("Found 150 matches"));
+    assert!(find.contains("... [TRUNCATED"));
+    assert!(find.contains("stored as 001_find."));
+
+    let bash = &results[1].1;
+    assert_eq!(bash.matches("... [TRUNCATED").count(), 1);
+    assert!(bash.contains("\n500\n"));
+    assert!(bash.contains("stored as 002_bash."));
+}
@@ -0,0 +1,244 @@
+mod common;
+
+use common::{tool_results, TempDir};
+use file_agent::conversation::{Callable, Conversation};
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::ScriptedProvider;
+use file_agent::policy::{Decision, PermissionPolicy};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn policy(dir: &TempDir, rules: &str) -> PermissionPolicy {
+    dir.write("policy.toml", rules);
+    PermissionPolicy::from_file(&dir.path().join("policy.toml"), dir.path()).unwrap()
+}
+
+fn decide(policy: &PermissionPolicy, tool: &str, args: serde_json::Value) -> Decision {
+    policy.evaluate(tool, &args.to_string()).decision
+}
+
+fn bash(command: &str) -> serde_json::Value {
+    json!({ "command": command })
+}
+
+#[test]
+fn most_restrictive_matching_rule_wins() {
+    let dir = TempDir::new();
+    let policy = policy(
+        &dir,
+        r#"
+        default = "deny"
+
+        [[rules]]
+        tool = "read"
+        decision = "allow"
+
+        [[rules]]
+        tool = "*"
+        path = "secrets/*"
+        decision = "ask"
+
+        [[rules]]
+        tool = "bash*"
+        decision = "allow"
+        "#,
+    );
+
+    assert_eq!(decide(&policy, "read", json!({"file_path": "a.txt"})), Decision::Allow);
+    assert_eq!(
+        decide(&policy, "read", json!({"file_path": "secrets/key"})),
+        Decision::Ask
+    );
+    assert_eq!(decide(&policy, "bash_kill", json!({"id": "1"})), Decision::Allow);
+    // Nothing matches, so the default applies
+    assert_eq!(decide(&policy, "write", json!({"file_path": "a.txt"})), Decision::Deny);
+
+    let outcome = policy.evaluate("read", r#"{"file_path": "secrets/key"}"#);
+    assert_eq!(outcome.reason, "policy rule #2");
+}
+
+#[test]
+fn path_rules_match_however_the_path_is_spelled() {
+    let dir = TempDir::new();
+    let policy = policy(
+        &dir,
+        r#"
+        [[rules]]
+        tool = "write"
+        path = "**/.env*"
+        decision = "deny"
+
+        [[rules]]
+        path = "secrets/*"
+        decision = "deny"
+        "#,
+    );
+
+    let env = dir.path().join("app/.env.local");
+    for path in [
+        ".env",
+        "app/.env.local",
+        env.to_str().unwrap(),
+        "secrets/key",
+        "./secrets/key",
+        "docs/../secrets/key",
+        dir.path().join("docs/../secrets/key").to_str().unwrap(),
+    ] {
+        assert_eq!(
+            decide(&policy, "write", json!({"file_path": path})),
+            Decision::Deny,
+            "{}",
+            path
+        );
+    }
+    assert_eq!(decide(&policy, "ls", json!({"path": "secrets/x"})), Decision::Deny);
+
+    assert_eq!(
+        decide(&policy, "write", json!({"file_path": "src/env.rs"})),
+        Decision::Allow
+    );
+    assert_eq!(
+        decide(&policy, "write", json!({"file_path": "secrets-old/key"})),
+        Decision::Allow
+    );
+    // `*` stops at a directory separator
+    assert_eq!(
+        decide(&policy, "write", json!({"file_path": "secrets/old/key"})),
+        Decision::Allow
+    );
+    // Rules with a path don't apply to calls without one
+    assert_eq!(decide(&policy, "bash", bash("cat secrets/key")), Decision::Allow);
+}
+
+#[test]
+fn command_prefixes_match_every_command_that_runs() {
+    let dir = TempDir::new();
+    let policy = policy(
+        &dir,
+        r#"
+        [[rules]]
+        tool = "bash"
+        command_prefix = "git push"
+        decision = "deny"
+        "#,
+    );
+
+    for command in [
+        "git push",
+        "git   push origin main",
+        "cd repo && git push",
+        "git status; git push --force",
+        "sudo -u deploy git push",
+        "FOO=1 /usr/bin/git push",
+        "bash -c 'git push'",
+        "echo $(git push)",
+        "if true; then git push; fi",
+    ] {
+        assert_eq!(decide(&policy, "bash", bash(command)), Decision::Deny, "{}", command);
+    }
+
+    for command in ["git pushy", "git status", "echo git push", "git log --grep push"] {
+        assert_eq!(decide(&policy, "bash", bash(command)), Decision::Allow, "{}", command);
+    }
+}
+
+#[test]
+fn invalid_policies_are_rejected() {
+    let dir = TempDir::new();
+
+    dir.write("bad.toml", "[[rules]]\ntool = \"[\"\ndecision = \"deny\"\n");
+    let error = PermissionPolicy::from_file(&dir.path().join("bad.toml"), dir.path())
+        .unwrap_err()
+        .to_string();
+    assert!(error.starts_with("Invalid glob '[' in policy rule #1"), "{}", error);
+
+    dir.write("bad.json", r#"{"rules": [{"tool": "bash", "decision": "maybe"}]}"#);
+    let error = PermissionPolicy::from_file(&dir.path().join("bad.json"), dir.path())
+        .unwrap_err()
+        .to_string();
+    assert!(error.starts_with("Invalid policy file"), "{}", error);
+
+    dir.write("ok.json", r#"{"default": "ask", "rules": []}"#);
+    let policy = PermissionPolicy::from_file(&dir.path().join("ok.json"), dir.path()).unwrap();
+    assert_eq!(decide(&policy, "read", json!({})), Decision::Ask);
+}
+
+/// Callable that counts its calls
+struct Counter(Arc<AtomicUsize>);
+
+#[async_trait::async_trait]
+impl Callable for Counter {
+    fn name(&self) -> &str {
+        "deploy"
+    }
+
+    fn description(&self) -> &str {
+        "Deploy"
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({"type": "object"})
+    }
+
+    async fn invoke(&self, _input: &serde_json::Value) -> anyhow::Result<String> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok("deployed".to_string())
+    }
+}
+
+#[tokio::test]
+async fn conversation_checks_the_policy_before_any_call_runs() {
+    let dir = TempDir::new();
+    let policy = policy(
+        &dir,
+        r#"
+        [[rules]]
+        tool = "deploy"
+        path = "prod/*"
+        decision = "deny"
+
+        [[rules]]
+        tool = "deploy"
+        path = "staging/*"
+        decision = "ask"
+        reason = "staging is shared"
+        "#,
+    );
+
+    let provider = ScriptedProvider::new(vec![
+        tool_use_turn(&[
+            ("d1", "deploy", json!({"path": "prod/app"})),
+            ("d2", "deploy", json!({"path": "staging/app"})),
+            ("d3", "deploy", json!({"path": "dev/app"})),
+        ]),
+        text_turn("Deployed to dev only"),
+    ]);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let callables: Vec<Box<dyn Callable>> = vec![Box::new(Counter(calls.clone()))];
+
+    Conversation::new(&provider, "deployer", "Deploy things", callables)
+        .with_policy(&policy)
+        .run("Deploy everywhere")
+        .await
+        .unwrap();
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    let results = tool_results(&provider.requests()[1]);
+    assert_eq!(
+        results,
+        vec![
+            (
+                "d1".to_string(),
+                "Permission denied: deploy is not allowed by policy (policy rule #1).".to_string(),
+                true
+            ),
+            (
+                "d2".to_string(),
+                "Permission denied: deploy requires user approval (staging is shared). Ask the user to approve it or choose a different approach.".to_string(),
+                true
+            ),
+            ("d3".to_string(), "deployed".to_string(), false),
+        ]
+    );
+}
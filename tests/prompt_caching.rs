@@ -0,0 +1,112 @@
+mod common;
+
+use common::{anthropic_config, serve_http};
+use file_agent::llm::{
+    AnthropicProvider, ContentBlock, LlmProvider, LlmRequest, LlmResponse, Message, Role,
+    ToolSchema,
+};
+use serde_json::{json, Value};
+
+fn tool(name: &str) -> ToolSchema {
+    ToolSchema {
+        name: name.to_string(),
+        description: format!("The {} tool", name),
+        input_schema: json!({"type": "object", "properties": {}}),
+    }
+}
+
+fn tool_round(id: &str) -> [Message; 2] {
+    [
+        Message {
+            role: Role::Assistant,
+            content: vec![ContentBlock::ToolUse {
+                id: id.to_string(),
+                name: "read".to_string(),
+                input: json!({}),
+            }],
+        },
+        Message {
+            role: Role::User,
+            content: vec![ContentBlock::ToolResult {
+                tool_use_id: id.to_string(),
+                content: "result".to_string(),
+                is_error: false,
+            }],
+        },
+    ]
+}
+
+/// Send a request with two earlier tool rounds and return the payload the server received
+async fn send(prompt_caching: bool) -> (Value, LlmResponse) {
+    let body = json!({
+        "content": [{"type": "text", "text": "Done"}],
+        "stop_reason": "end_turn",
+        "usage": {
+            "input_tokens": 50,
+            "output_tokens": 5,
+            "cache_creation_input_tokens": 200,
+            "cache_read_input_tokens": 1800
+        }
+    });
+    let (url, server) = serve_http("application/json", vec![body.to_string().into_bytes()]);
+
+    let mut config = anthropic_config(&url);
+    config.prompt_caching = prompt_caching;
+
+    let mut messages = vec![Message::user_text("Do the task")];
+    messages.extend(tool_round("t1"));
+    messages.extend(tool_round("t2"));
+    let request = LlmRequest {
+        system: "You are a test".to_string(),
+        messages,
+        tools: vec![tool("ls"), tool("read")],
+    };
+
+    let response = AnthropicProvider::new(config).send(&request).await.unwrap();
+    (server.join().unwrap(), response)
+}
+
+#[tokio::test]
+async fn breakpoints_cover_tools_system_and_recent_messages() {
+    let (payload, response) = send(true).await;
+
+    let ephemeral = json!({"type": "ephemeral"});
+    assert!(payload["tools"][0].get("cache_control").is_none());
+    assert_eq!(payload["tools"][1]["cache_control"], ephemeral);
+    assert_eq!(
+        payload["system"],
+        json!([{"type": "text", "text": "You are a test", "cache_control": ephemeral}])
+    );
+
+    // The last two user messages end with a breakpoint, nothing else does
+    let marked: Vec<usize> = payload["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| {
+            message["content"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|block| block.get("cache_control").is_some())
+        })
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(marked, vec![2, 4]);
+    assert_eq!(
+        payload["messages"][4]["content"][0]["cache_control"],
+        ephemeral
+    );
+
+    assert_eq!(response.usage.cache_read_input_tokens, 1800);
+    assert_eq!(response.usage.cache_creation_input_tokens, 200);
+}
+
+#[tokio::test]
+async fn disabled_caching_sends_plain_payload() {
+    let (payload, _) = send(false).await;
+
+    assert_eq!(payload["system"], json!("You are a test"));
+    assert!(!payload.to_string().contains("cache_control"));
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::{file_agent, tool_results, TempDir};
+use file_agent::agent::Agent;
+use file_agent::agents::file::tools::ReadResultTool;
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::ScriptedProvider;
+use file_agent::tool::Tool;
+use file_agent::utils;
+use serde_json::json;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn truncated_output_can_be_paged_and_searched_by_handle() {
+    let dir = TempDir::new();
+    let messages = TempDir::new();
+    let file: String = (1..=2500).map(|i| format!("entry {}\n", i)).collect();
+    dir.write("big.txt", &file);
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[(
+            "t1",
+            "read",
+            json!({"file_path": "big.txt", "offset": 0, "limit": 2500}),
+        )]),
+        tool_use_turn(&[
+            (
+                "t2",
+                "read_result",
+                json!({"handle": "001_read", "pattern": "→entry 1234$"}),
+            ),
+            (
+                "t3",
+                "read_result",
+                json!({"handle": "001_read", "offset": 1000, "limit": 2}),
+            ),
+        ]),
+        text_turn("Found it"),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    let answer = utils::with_message_dir(
+        messages.path().to_path_buf(),
+        agent.execute("Find entry 1234"),
+    )
+    .await;
+    assert_eq!(answer.unwrap(), "Found it");
+
+    let requests = provider.requests();
+    let sampled = &tool_results(&requests[1])[0].1;
+    assert!(sampled.contains("=== FILE PREVIEW"));
+    assert!(sampled.contains("stored as 001_read. Use read_result with handle \"001_read\""));
+    assert!(messages.path().join("results/001_read.txt").exists());
+
+    let results = tool_results(&requests[2]);
+    assert_eq!(
+        results[0].1,
+        "=== 001_read: 1 of 2504 lines match '→entry 1234$' ===\n\n 1238→ 1234→entry 1234"
+    );
+    assert!(results[1]
+        .1
+        .contains(" 1001→  997→entry 997\n 1002→  998→entry 998"));
+    assert!(results[1]
+        .1
+        .ends_with("... 1502 more lines follow, continue with offset 1002 ..."));
+}
+
+#[tokio::test]
+async fn unknown_and_unsafe_handles_are_rejected() {
+    let messages = TempDir::new();
+    let tool = ReadResultTool::new();
+
+    let error = utils::with_message_dir(
+        messages.path().to_path_buf(),
+        tool.execute(r#"{"handle": "../secrets"}"#),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.to_string(), "Invalid result handle: ../secrets");
+
+    let error = utils::with_message_dir(
+        messages.path().to_path_buf(),
+        tool.execute(r#"{"handle": "007_grep"}"#),
+    )
+    .await
+    .unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("No stored result with handle 007_grep."));
+}
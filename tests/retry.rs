@@ -0,0 +1,139 @@
+mod common;
+
+use common::{http_response, serve_responses};
+use file_agent::retry::{self, ApiRequest, RetryConfig};
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+fn config(max_retries: u32) -> RetryConfig {
+    RetryConfig {
+        max_retries,
+        base_delay_ms: 1,
+        max_delay_ms: 20,
+    }
+}
+
+async fn send(url: &str, retry: &RetryConfig) -> anyhow::Result<serde_json::Value> {
+    let client = reqwest::Client::new();
+    let payload = json!({});
+    let request = ApiRequest {
+        client: &client,
+        service: "Test API",
+        url,
+        headers: &[],
+        payload: &payload,
+        timeout: Some(Duration::from_secs(5)),
+    };
+    retry::send_with_retry(&request, retry).await
+}
+
+#[test]
+fn backoff_doubles_with_jitter_up_to_the_maximum() {
+    let retry = RetryConfig {
+        max_retries: 10,
+        base_delay_ms: 100,
+        max_delay_ms: 1_000,
+    };
+    let ms = |d: Duration| d.as_millis() as u64;
+
+    for _ in 0..50 {
+        assert!((50..=100).contains(&ms(retry.backoff(1))));
+        assert!((100..=200).contains(&ms(retry.backoff(2))));
+        assert!((200..=400).contains(&ms(retry.backoff(3))));
+        assert!((500..=1_000).contains(&ms(retry.backoff(5))));
+        assert!((500..=1_000).contains(&ms(retry.backoff(60))));
+    }
+
+    // A server's retry-after wins over the backoff, but never beyond the maximum
+    assert_eq!(
+        retry.delay(1, Some(Duration::from_millis(300))),
+        Duration::from_millis(300)
+    );
+    assert_eq!(
+        retry.delay(1, Some(Duration::from_secs(3_600))),
+        Duration::from_secs(1)
+    );
+}
+
+#[test]
+fn retry_after_is_read_as_seconds_or_a_date() {
+    let parse = |value: &str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        retry::parse_retry_after(&headers)
+    };
+
+    assert_eq!(parse("3"), Some(Duration::from_secs(3)));
+    assert_eq!(parse(" 1.5 "), Some(Duration::from_millis(1_500)));
+    assert_eq!(parse("-1"), None);
+    assert_eq!(parse("soon"), None);
+    assert_eq!(parse("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+
+    let later = (chrono::Utc::now() + chrono::Duration::seconds(120)).to_rfc2822();
+    let delay = parse(&later).unwrap();
+    assert!(delay > Duration::from_secs(100) && delay <= Duration::from_secs(120));
+
+    assert_eq!(retry::parse_retry_after(&HeaderMap::new()), None);
+}
+
+#[tokio::test]
+async fn overloaded_responses_are_retried_without_waiting_for_long_retry_after() {
+    let (url, server) = serve_responses(vec![
+        http_response("529 Overloaded", &["retry-after: 3600"], "{}"),
+        http_response("429 Too Many Requests", &["retry-after: 3600"], "{}"),
+        http_response("408 Request Timeout", &[], "{}"),
+        http_response("200 OK", &[], r#"{"ok": true}"#),
+    ]);
+
+    let start = Instant::now();
+    let response = send(&url, &config(3)).await.unwrap();
+    assert_eq!(response, json!({"ok": true}));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert_eq!(server.join().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn responses_cut_off_mid_body_are_retried() {
+    let cut_off = "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 100\r\nconnection: close\r\n\r\n{\"ok\"";
+    let (url, server) = serve_responses(vec![
+        cut_off.to_string(),
+        http_response("200 OK", &[], r#"{"ok": true}"#),
+    ]);
+
+    let response = send(&url, &config(3)).await.unwrap();
+    assert_eq!(response, json!({"ok": true}));
+    assert_eq!(server.join().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn client_and_decode_errors_are_not_retried() {
+    let (url, server) = serve_responses(vec![
+        http_response("400 Bad Request", &[], r#"{"error": "bad"}"#),
+        http_response("200 OK", &[], "{}"),
+    ]);
+    let error = send(&url, &config(3)).await.unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("Test API error (400 Bad Request)"));
+    assert_eq!(server.join().unwrap().len(), 1);
+
+    let (url, server) = serve_responses(vec![
+        http_response("200 OK", &[], "not json"),
+        http_response("200 OK", &[], "{}"),
+    ]);
+    assert!(send(&url, &config(3)).await.is_err());
+    assert_eq!(server.join().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn retries_stop_after_the_limit() {
+    let (url, server) = serve_responses(vec![
+        http_response("503 Service Unavailable", &[], "{}"),
+        http_response("503 Service Unavailable", &[], "{}"),
+        http_response("503 Service Unavailable", &[], "{}"),
+    ]);
+    let error = send(&url, &config(1)).await.unwrap_err();
+    assert!(error.to_string().contains("503"));
+    assert_eq!(server.join().unwrap().len(), 2);
+}
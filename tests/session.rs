@@ -0,0 +1,140 @@
+mod common;
+
+use common::{file_agent, TempDir};
+use file_agent::conversation::close_unfinished_calls;
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::{ContentBlock, Message, Role, ScriptedProvider};
+use file_agent::session::Session;
+use file_agent::OrchestratorAgent;
+use serde_json::json;
+use std::sync::Arc;
+
+fn texts(messages: &[Message]) -> Vec<(Role, String)> {
+    messages
+        .iter()
+        .map(|message| {
+            let text = message
+                .content
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("|");
+            (message.role, text)
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn follow_up_messages_continue_the_session() {
+    let workspace = TempDir::new();
+    let sessions = TempDir::new();
+    let context = json!({"session_id": "s1", "space": "team"});
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        text_turn("Hello"),
+        text_turn("You said hi"),
+        text_turn("Fresh start"),
+    ]));
+    let orchestrator = OrchestratorAgent::with_agents(
+        provider.clone(),
+        vec![Box::new(file_agent(provider.clone(), &workspace))],
+    );
+
+    let session = Session::from_context(sessions.path(), &context).unwrap();
+    assert_eq!(session.dir(), sessions.path().join("team").join("s1"));
+    assert_eq!(
+        orchestrator
+            .execute_in_session(&session, "hi")
+            .await
+            .unwrap(),
+        "Hello"
+    );
+
+    // A later message reopens the session from disk
+    let session = Session::from_context(sessions.path(), &context).unwrap();
+    assert_eq!(
+        orchestrator
+            .execute_in_session(&session, "What did I say?")
+            .await
+            .unwrap(),
+        "You said hi"
+    );
+    assert_eq!(
+        texts(&provider.requests()[1].messages),
+        vec![
+            (Role::User, "hi".to_string()),
+            (Role::Assistant, "Hello".to_string()),
+            (Role::User, "What did I say?".to_string()),
+        ]
+    );
+    assert_eq!(session.load_history().unwrap().len(), 4);
+
+    let logs = session.messages_dir().join("orchestrator");
+    assert!(logs.join("001_orchestrator_message.json").exists());
+    assert!(logs.join("002_orchestrator_message.json").exists());
+
+    // Another session ID starts from scratch
+    let other = Session::from_context(sessions.path(), &json!({"session_id": "s2"})).unwrap();
+    assert_eq!(other.space(), "default");
+    orchestrator.execute_in_session(&other, "hi").await.unwrap();
+    assert_eq!(provider.requests()[2].messages.len(), 1);
+}
+
+#[test]
+fn session_names_must_be_safe_directory_names() {
+    let sessions = TempDir::new();
+
+    let error =
+        Session::from_context(sessions.path(), &json!({"session_id": "../etc"})).unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("Invalid session_id '../etc': use up to 128 letters"));
+
+    let error = Session::from_context(sessions.path(), &json!({"space": 3})).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid space in Raworc context: expected a string, got 3"
+    );
+}
+
+#[test]
+fn unfinished_calls_are_marked_as_interrupted() {
+    let mut messages = vec![Message::user_text("Do it")];
+    messages.push(Message {
+        role: Role::Assistant,
+        content: tool_use_turn(&[
+            ("t1", "read", json!({"file_path": "a.txt"})),
+            ("t2", "bash", json!({"command": "make"})),
+        ])
+        .content,
+    });
+    messages.push(Message {
+        role: Role::User,
+        content: vec![ContentBlock::ToolResult {
+            tool_use_id: "t1".to_string(),
+            content: "a".to_string(),
+            is_error: false,
+        }],
+    });
+
+    assert_eq!(close_unfinished_calls(&mut messages), 1);
+    assert_eq!(messages.len(), 3);
+    match &messages[2].content[1] {
+        ContentBlock::ToolResult {
+            tool_use_id,
+            content,
+            is_error,
+        } => {
+            assert_eq!(tool_use_id, "t2");
+            assert!(content.starts_with("bash: interrupted before it finished."));
+            assert!(is_error);
+        }
+        other => panic!("expected a tool result, got {:?}", other),
+    }
+
+    // Nothing left to close
+    assert_eq!(close_unfinished_calls(&mut messages), 0);
+}
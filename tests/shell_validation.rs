@@ -0,0 +1,101 @@
+mod common;
+
+use common::TempDir;
+use file_agent::agents::file::tools::{BashTool, ProcessRegistry};
+use file_agent::agents::file::{Sandbox, Workspace};
+
+fn bash_tool(dir: &TempDir) -> BashTool {
+    let workspace = Workspace::new(dir.path(), &[]).unwrap();
+    BashTool::new(workspace, ProcessRegistry::new(), Sandbox::disabled())
+}
+
+#[test]
+fn commands_are_checked_by_their_parsed_structure() {
+    let dir = TempDir::new();
+    let tool = bash_tool(&dir);
+
+    let accepted = [
+        "ls -la",
+        "echo \"don't shutdown\"",
+        "echo 'rm -rf /'",
+        "grep -r reboot src",
+        "rm -rf build",
+        "rm -rf ./target/..",
+        "rm -rf /tmp/cache",
+        "echo $((1 + 2))",
+        "echo ${HOME:-/tmp}",
+        "cargo build && cargo test | tail -5",
+        "sh -c 'echo hello'",
+        "f() { echo hi; }; f",
+        "select x in a b; do echo $x; break; done",
+        "exec -a name ls",
+    ];
+    for command in accepted {
+        if let Err(e) = tool.validate_command(command) {
+            panic!("`{}` was rejected: {}", command, e);
+        }
+    }
+
+    // (command, sub-command named in the error)
+    let rejected = [
+        ("rm -rf /", "`rm -rf /`"),
+        ("rm  -rf /", "`rm  -rf /`"),
+        ("r''m -rf /", "`r''m -rf /`"),
+        ("\\rm -rf /*", "`\\rm -rf /*`"),
+        ("rm -rf /usr/..", "`rm -rf /usr/..`"),
+        ("rm -rf //bin/..", "`rm -rf //bin/..`"),
+        ("rm -rf ~/.cache/..", "`rm -rf ~/.cache/..`"),
+        (
+            "rm -r --no-preserve-root /x",
+            "`rm -r --no-preserve-root /x`",
+        ),
+        ("sudo rm -rf /", "`sudo rm -rf /`"),
+        ("cd /tmp && shutdown now", "`shutdown now`"),
+        ("echo ok | (reboot)", "`reboot`"),
+        ("echo $(rm -rf /)", "`rm -rf /`"),
+        ("echo `halt`", "`halt`"),
+        ("echo $(( $(rm -rf /) ))", "`rm -rf /`"),
+        ("echo $((1 + `poweroff`))", "`poweroff`"),
+        ("echo ${X:-$(reboot)}", "`reboot`"),
+        ("sh -c 'rm -rf /'", "`rm -rf /`"),
+        (
+            "bash -c \"eval 'mkfs.ext4 /dev/sda'\"",
+            "`mkfs.ext4 /dev/sda`",
+        ),
+        (
+            "dd if=/dev/zero of=/dev/sda",
+            "`dd if=/dev/zero of=/dev/sda`",
+        ),
+        ("chmod -R 777 /", "`chmod -R 777 /`"),
+        ("for x in a; do reboot; done", "`reboot`"),
+        ("bash -c 'select x in a; do reboot; done'", "`reboot`"),
+        ("sh -c \"select x in a; do rm -rf /; done\"", "`rm -rf /`"),
+        ("coproc reboot", "`coproc reboot`"),
+        ("exec -a foo reboot", "`exec -a foo reboot`"),
+        (":(){ :|:& };:", "fork bomb"),
+    ];
+    for (command, expected) in rejected {
+        match tool.validate_command(command) {
+            Ok(()) => panic!("`{}` was accepted", command),
+            Err(e) => assert!(
+                e.to_string().contains(expected),
+                "`{}` was rejected with: {}",
+                command,
+                e
+            ),
+        }
+    }
+}
+
+#[test]
+fn unparsable_commands_are_rejected() {
+    let dir = TempDir::new();
+    let tool = bash_tool(&dir);
+
+    for command in ["echo 'unterminated", "echo $(ls", "echo \"x"] {
+        let error = tool.validate_command(command).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Unable to parse command for safety validation"));
+    }
+}
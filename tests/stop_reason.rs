@@ -0,0 +1,153 @@
+mod common;
+
+use common::{file_agent, tool_results, TempDir};
+use file_agent::agent::Agent;
+use file_agent::conversation::{Callable, Conversation, ConversationError, ConversationLimits};
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::{ContentBlock, LlmResponse, Role, ScriptedProvider, StopReason};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+fn stopped(mut turn: LlmResponse, reason: StopReason) -> LlmResponse {
+    turn.stop_reason = Some(reason);
+    turn
+}
+
+/// Callable that always succeeds, for driving `Conversation` directly
+struct Echo;
+
+#[async_trait::async_trait]
+impl Callable for Echo {
+    fn name(&self) -> &str {
+        "echo"
+    }
+
+    fn description(&self) -> &str {
+        "Echo the input"
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({"type": "object"})
+    }
+
+    async fn invoke(&self, input: &Value) -> anyhow::Result<String> {
+        Ok(input.to_string())
+    }
+}
+
+#[tokio::test]
+async fn cut_off_text_is_continued_and_joined() {
+    let dir = TempDir::new();
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        stopped(text_turn("The answer is forty"), StopReason::MaxTokens),
+        stopped(text_turn("-two, as"), StopReason::MaxTokens),
+        text_turn(" computed."),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    assert_eq!(
+        agent.execute("What is the answer?").await.unwrap(),
+        "The answer is forty-two, as computed."
+    );
+
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 3);
+    let messages = &requests[2].messages;
+    assert_eq!(messages.len(), 5);
+    assert_eq!(messages[3].role, Role::Assistant);
+    match &messages[4].content[0] {
+        ContentBlock::Text { text } => {
+            assert!(text.contains("Continue exactly where you left off"))
+        }
+        other => panic!("expected a continuation prompt, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn incomplete_tool_input_is_not_run() {
+    let dir = TempDir::new();
+    dir.write("a.txt", "a\n");
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        stopped(
+            tool_use_turn(&[
+                ("t1", "read", json!({"file_path": "a.txt"})),
+                (
+                    "t2",
+                    "write",
+                    json!("{\"file_path\": \"b.txt\", \"content\": \"par"),
+                ),
+            ]),
+            StopReason::MaxTokens,
+        ),
+        text_turn("Giving up"),
+    ]));
+    let agent = file_agent(provider.clone(), &dir);
+
+    assert_eq!(agent.execute("Copy a.txt").await.unwrap(), "Giving up");
+    assert!(!dir.path().join("b.txt").exists());
+
+    let results = tool_results(&provider.requests()[1]);
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].0, "t1");
+    assert!(!results[0].2);
+    assert_eq!(results[1].0, "t2");
+    assert!(results[1].2);
+    assert!(results[1]
+        .1
+        .starts_with("Tool input was incomplete because the response reached the output token limit, so write was not run."));
+}
+
+#[tokio::test]
+async fn refusal_is_an_error() {
+    let dir = TempDir::new();
+
+    let provider = Arc::new(ScriptedProvider::new(vec![stopped(
+        text_turn("I can't help with that."),
+        StopReason::Refusal,
+    )]));
+    let agent = file_agent(provider, &dir);
+
+    let error = agent.execute("Do something bad").await.unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<ConversationError>(),
+        Some(&ConversationError::Refused {
+            agent: "file_agent".to_string(),
+            text: "I can't help with that.".to_string()
+        })
+    );
+}
+
+#[tokio::test]
+async fn hitting_max_rounds_is_a_distinct_error() {
+    let provider = ScriptedProvider::new(vec![
+        tool_use_turn(&[("e1", "echo", json!({"n": 1}))]),
+        tool_use_turn(&[("e2", "echo", json!({"n": 2}))]),
+        text_turn("never reached"),
+    ]);
+    let callables: Vec<Box<dyn Callable>> = vec![Box::new(Echo)];
+
+    let error = Conversation::new(&provider, "looper", "Loop", callables)
+        .with_limits(ConversationLimits {
+            max_rounds: 2,
+            ..ConversationLimits::default()
+        })
+        .run("Keep going")
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        error.downcast_ref::<ConversationError>(),
+        Some(&ConversationError::MaxRoundsReached {
+            agent: "looper".to_string(),
+            max_rounds: 2,
+            last_text: String::new(),
+        })
+    );
+    assert_eq!(
+        error.to_string(),
+        "looper stopped after reaching the maximum of 2 rounds without finishing the task"
+    );
+    assert_eq!(provider.remaining(), 1);
+}
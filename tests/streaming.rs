@@ -0,0 +1,253 @@
+mod common;
+
+use common::{anthropic_config, file_agent, serve_http, serve_responses, TempDir};
+use file_agent::agent::Agent;
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::{
+    AnthropicProvider, ContentBlock, LlmProvider, LlmRequest, Message, ScriptedProvider,
+    StopReason, StreamEvent,
+};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+fn sse(event: &str, data: Value) -> String {
+    format!("event: {}\ndata: {}\n\n", event, data)
+}
+
+fn provider(url: &str) -> AnthropicProvider {
+    AnthropicProvider::new(anthropic_config(url))
+}
+
+fn request() -> LlmRequest {
+    LlmRequest {
+        system: "You are a test".to_string(),
+        messages: vec![Message::user_text("Hi")],
+        tools: vec![],
+    }
+}
+
+#[tokio::test]
+async fn streamed_text_and_tool_input_are_assembled() {
+    let body = [
+        sse("message_start", json!({"type": "message_start", "message": {"id": "msg_1", "role": "assistant", "content": [], "stop_reason": null, "usage": {"input_tokens": 12, "output_tokens": 1}}})),
+        sse("content_block_start", json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}})),
+        ": keep-alive comment\n\n".to_string(),
+        sse("ping", json!({"type": "ping"})),
+        sse("content_block_delta", json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Héllo"}})),
+        sse("content_block_delta", json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": " wörld"}})),
+        sse("content_block_stop", json!({"type": "content_block_stop", "index": 0})),
+        sse("content_block_start", json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "t1", "name": "read", "input": {}}})),
+        sse("content_block_delta", json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"file_"}})),
+        sse("content_block_delta", json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "path\": \"a.txt\"}"}})),
+        sse("content_block_stop", json!({"type": "content_block_stop", "index": 1})),
+        sse("message_delta", json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 30}})),
+        sse("message_stop", json!({"type": "message_stop"})),
+    ]
+    .concat()
+    .replace("\n", "\r\n");
+
+    // Split into 7-byte chunks so that lines and multi-byte characters straddle chunk boundaries
+    let chunks = body.as_bytes().chunks(7).map(|c| c.to_vec()).collect();
+    let (url, server) = serve_http("text/event-stream", chunks);
+
+    let events = Mutex::new(Vec::new());
+    let on_event = |event: &StreamEvent| events.lock().unwrap().push(event.clone());
+    let response = provider(&url)
+        .send_streaming(&request(), &on_event)
+        .await
+        .unwrap();
+
+    assert_eq!(server.join().unwrap()["stream"], json!(true));
+    assert_eq!(
+        response.content,
+        vec![
+            ContentBlock::Text {
+                text: "Héllo wörld".to_string()
+            },
+            ContentBlock::ToolUse {
+                id: "t1".to_string(),
+                name: "read".to_string(),
+                input: json!({"file_path": "a.txt"}),
+            },
+        ]
+    );
+    assert_eq!(response.stop_reason, Some(StopReason::ToolUse));
+    assert_eq!(response.usage.input_tokens, 12);
+    assert_eq!(response.usage.output_tokens, 30);
+
+    assert_eq!(
+        events.into_inner().unwrap(),
+        vec![
+            StreamEvent::TextDelta {
+                text: "Héllo".to_string()
+            },
+            StreamEvent::TextDelta {
+                text: " wörld".to_string()
+            },
+            StreamEvent::ToolUseStart {
+                id: "t1".to_string(),
+                name: "read".to_string()
+            },
+            StreamEvent::ToolInputDelta {
+                id: "t1".to_string(),
+                partial_json: "{\"file_".to_string()
+            },
+            StreamEvent::ToolInputDelta {
+                id: "t1".to_string(),
+                partial_json: "path\": \"a.txt\"}".to_string()
+            },
+            StreamEvent::MessageStop {
+                stop_reason: Some(StopReason::ToolUse)
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn stream_error_event_fails_the_request() {
+    let body = [
+        sse("message_start", json!({"type": "message_start", "message": {"id": "msg_1", "content": []}})),
+        sse("error", json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}})),
+    ]
+    .concat();
+    let (url, server) = serve_http("text/event-stream", vec![body.into_bytes()]);
+
+    let error = provider(&url)
+        .send_streaming(&request(), &|_| {})
+        .await
+        .unwrap_err();
+    server.join().unwrap();
+
+    assert!(error.to_string().contains("Claude API stream error"));
+    assert!(error.to_string().contains("overloaded_error"));
+}
+
+#[tokio::test]
+async fn truncated_stream_fails_the_request() {
+    let body = [
+        sse("message_start", json!({"type": "message_start", "message": {"id": "msg_1", "content": []}})),
+        sse("content_block_start", json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}})),
+    ]
+    .concat();
+    let (url, server) = serve_http("text/event-stream", vec![body.into_bytes()]);
+
+    let error = provider(&url)
+        .send_streaming(&request(), &|_| {})
+        .await
+        .unwrap_err();
+    server.join().unwrap();
+
+    assert!(error
+        .to_string()
+        .contains("stream ended before the message was complete"));
+}
+
+#[tokio::test]
+async fn agent_event_handler_receives_events_with_the_agent_name() {
+    let dir = TempDir::new();
+    dir.write("notes.txt", "hello\n");
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("t1", "read", json!({"file_path": "notes.txt"}))]),
+        text_turn("It says hello"),
+    ]));
+    let mut agent = file_agent(provider, &dir);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    agent.set_event_handler(Arc::new(move |agent: &str, event: &StreamEvent| {
+        sink.lock()
+            .unwrap()
+            .push((agent.to_string(), event.clone()));
+    }));
+
+    assert_eq!(
+        agent.execute("Read notes.txt").await.unwrap(),
+        "It says hello"
+    );
+
+    let events = events.lock().unwrap();
+    assert!(events.iter().all(|(agent, _)| agent == "file_agent"));
+    assert_eq!(
+        events
+            .iter()
+            .map(|(_, event)| event.clone())
+            .collect::<Vec<_>>(),
+        vec![
+            StreamEvent::ToolUseStart {
+                id: "t1".to_string(),
+                name: "read".to_string()
+            },
+            StreamEvent::ToolInputDelta {
+                id: "t1".to_string(),
+                partial_json: json!({"file_path": "notes.txt"}).to_string()
+            },
+            StreamEvent::MessageStop {
+                stop_reason: Some(StopReason::ToolUse)
+            },
+            StreamEvent::TextDelta {
+                text: "It says hello".to_string()
+            },
+            StreamEvent::MessageStop {
+                stop_reason: Some(StopReason::EndTurn)
+            },
+        ]
+    );
+}
+
+fn sse_response(events: &[String]) -> String {
+    let body = events.concat();
+    format!(
+        "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+fn message_start(id: &str) -> String {
+    sse("message_start", json!({"type": "message_start", "message": {"id": id, "role": "assistant", "content": [], "stop_reason": null, "usage": {"input_tokens": 10, "output_tokens": 1}}}))
+}
+
+fn message_end(stop_reason: &str) -> Vec<String> {
+    vec![
+        sse("message_delta", json!({"type": "message_delta", "delta": {"stop_reason": stop_reason}, "usage": {"output_tokens": 20}})),
+        sse("message_stop", json!({"type": "message_stop"})),
+    ]
+}
+
+#[tokio::test]
+async fn cut_off_tool_input_is_sent_back_as_an_empty_object() {
+    let dir = TempDir::new();
+
+    let mut cut_off = vec![
+        message_start("msg_1"),
+        sse("content_block_start", json!({"type": "content_block_start", "index": 0, "content_block": {"type": "tool_use", "id": "t1", "name": "write", "input": {}}})),
+        sse("content_block_delta", json!({"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "{\"file_path\": \"b.txt\", \"content\": \"par"}})),
+        sse("content_block_stop", json!({"type": "content_block_stop", "index": 0})),
+    ];
+    cut_off.extend(message_end("max_tokens"));
+    let mut answer = vec![
+        message_start("msg_2"),
+        sse("content_block_start", json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}})),
+        sse("content_block_delta", json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Giving up"}})),
+        sse("content_block_stop", json!({"type": "content_block_stop", "index": 0})),
+    ];
+    answer.extend(message_end("end_turn"));
+    let (url, server) = serve_responses(vec![sse_response(&cut_off), sse_response(&answer)]);
+
+    let mut agent = file_agent(Arc::new(provider(&url)), &dir);
+    agent.set_event_handler(Arc::new(|_: &str, _: &StreamEvent| {}));
+
+    assert_eq!(agent.execute("Write b.txt").await.unwrap(), "Giving up");
+    assert!(!dir.path().join("b.txt").exists());
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests.len(), 2);
+    let messages = requests[1]["messages"].as_array().unwrap();
+    assert_eq!(
+        messages[1]["content"],
+        json!([{"type": "tool_use", "id": "t1", "name": "write", "input": {}}])
+    );
+    assert_eq!(messages[2]["content"][0]["tool_use_id"], json!("t1"));
+    assert_eq!(messages[2]["content"][0]["is_error"], json!(true));
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use common::{file_agent, TempDir};
+use file_agent::agent::Agent;
+use file_agent::llm::scripted::{text_turn, tool_use_turn};
+use file_agent::llm::{LlmResponse, ScriptedProvider, Usage};
+use file_agent::usage::{BudgetConfig, Pricing, UsageTracker};
+use file_agent::OrchestratorAgent;
+use serde_json::json;
+use std::sync::Arc;
+
+fn with_usage(mut turn: LlmResponse, input_tokens: u64, output_tokens: u64) -> LlmResponse {
+    turn.usage = Usage {
+        input_tokens,
+        output_tokens,
+        ..Usage::default()
+    };
+    turn
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn usage_is_summed_per_agent_and_per_task() {
+    let dir = TempDir::new();
+    dir.write("a.txt", "a\n");
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        with_usage(
+            tool_use_turn(&[("o1", "file_agent", json!({"task": "Read a.txt"}))]),
+            100,
+            10,
+        ),
+        with_usage(
+            tool_use_turn(&[("t1", "read", json!({"file_path": "a.txt"}))]),
+            200,
+            20,
+        ),
+        with_usage(text_turn("a.txt contains a"), 300, 30),
+        with_usage(text_turn("It contains a"), 400, 40),
+    ]));
+    let orchestrator = OrchestratorAgent::with_agents(
+        provider.clone(),
+        vec![Box::new(file_agent(provider.clone(), &dir))],
+    );
+
+    assert_eq!(
+        orchestrator.execute("What is in a.txt?").await.unwrap(),
+        "It contains a"
+    );
+
+    let by_agent = orchestrator.usage().by_agent();
+    assert_eq!(by_agent["orchestrator"].requests, 2);
+    assert_eq!(by_agent["orchestrator"].usage.input_tokens, 500);
+    assert_eq!(by_agent["orchestrator"].usage.output_tokens, 50);
+    assert_eq!(by_agent["file_agent"].requests, 2);
+    assert_eq!(by_agent["file_agent"].usage.input_tokens, 500);
+    assert_eq!(orchestrator.usage().total().usage.total(), 1100);
+
+    let summary = orchestrator.usage().summary();
+    assert!(summary.starts_with("Token usage: 4 requests, 1000 input, 100 output"));
+    assert!(summary.contains("  file_agent: 2 requests"));
+}
+
+#[tokio::test]
+async fn token_budget_stops_before_running_more_calls() {
+    let dir = TempDir::new();
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        with_usage(
+            tool_use_turn(&[("t1", "write", json!({"file_path": "a.txt", "content": "a"}))]),
+            60,
+            10,
+        ),
+        with_usage(
+            tool_use_turn(&[("t2", "write", json!({"file_path": "b.txt", "content": "b"}))]),
+            60,
+            10,
+        ),
+        text_turn("Done"),
+    ]));
+    let mut agent = file_agent(provider.clone(), &dir);
+    agent.set_usage_tracker(Arc::new(UsageTracker::new(BudgetConfig {
+        max_tokens: Some(100),
+        ..BudgetConfig::default()
+    })));
+
+    let answer = agent.execute("Write two files").await.unwrap();
+    assert!(answer.starts_with(
+        "Stopped before finishing the task: token budget exceeded (140 of 100 tokens used)."
+    ));
+    assert!(answer.contains("Results of the last calls:\n- write (ok): "));
+
+    // The first write ran, the second was never started
+    assert_eq!(dir.read("a.txt").trim(), "a");
+    assert!(!dir.path().join("b.txt").exists());
+    assert_eq!(provider.remaining(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cost_budget_spent_by_a_delegated_agent_stops_the_orchestrator() {
+    let dir = TempDir::new();
+    dir.write("a.txt", "a\n");
+
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        tool_use_turn(&[("o1", "file_agent", json!({"task": "Read a.txt"}))]),
+        // $1 per million input tokens makes this turn cost $2
+        with_usage(
+            tool_use_turn(&[("t1", "read", json!({"file_path": "a.txt"}))]),
+            2_000_000,
+            0,
+        ),
+        text_turn("unused"),
+        text_turn("unused"),
+    ]));
+    let mut orchestrator = OrchestratorAgent::with_agents(
+        provider.clone(),
+        vec![Box::new(file_agent(provider.clone(), &dir))],
+    );
+    orchestrator.set_usage_tracker(Arc::new(UsageTracker::new(BudgetConfig {
+        max_cost_usd: Some(1.0),
+        pricing: Some(Pricing::new(1.0, 1.0)),
+        ..BudgetConfig::default()
+    })));
+
+    let answer = orchestrator.execute("Read a.txt").await.unwrap();
+    assert!(answer.starts_with(
+        "Stopped before finishing the task: cost budget exceeded ($2.0000 of $1.0000 estimated)."
+    ));
+    // The file agent's partial result is passed on
+    assert!(answer.contains("- file_agent (ok): Stopped before finishing the task"));
+    assert_eq!(provider.requests().len(), 2);
+}
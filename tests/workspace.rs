@@ -0,0 +1,94 @@
+mod common;
+
+use common::TempDir;
+use file_agent::agents::file::Workspace;
+use std::os::unix::fs::symlink;
+
+#[test]
+fn dot_dot_cannot_leave_the_root() {
+    let dir = TempDir::new();
+    dir.write("src/main.rs", "fn main() {}\n");
+    let workspace = Workspace::new(dir.path(), &[]).unwrap();
+
+    assert_eq!(
+        workspace.resolve_read("src/../src/main.rs").unwrap(),
+        dir.path().join("src/main.rs")
+    );
+    assert_eq!(
+        workspace.resolve_write("src/new.rs").unwrap(),
+        dir.path().join("src/new.rs")
+    );
+
+    for path in ["../outside.txt", "src/../../outside.txt", "/etc/passwd"] {
+        let error = workspace.resolve_read(path).unwrap_err().to_string();
+        assert!(error.contains("is outside the workspace root"), "{}", error);
+    }
+
+    // `..` after a directory that doesn't exist yet could point anywhere
+    let error = workspace
+        .resolve_write("new/../../outside.txt")
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("Relative components are not allowed"), "{}", error);
+}
+
+#[test]
+fn symlinks_are_checked_where_they_point() {
+    let dir = TempDir::new();
+    let outside = TempDir::new();
+    outside.write("secret.txt", "secret\n");
+    dir.write("docs/readme.md", "# Docs\n");
+    let workspace = Workspace::new(dir.path(), &[]).unwrap();
+
+    symlink(outside.path(), dir.path().join("escape")).unwrap();
+    symlink(outside.path().join("secret.txt"), dir.path().join("secret.txt")).unwrap();
+    symlink(outside.path().join("missing.txt"), dir.path().join("dangling.txt")).unwrap();
+    symlink(dir.path().join("docs"), dir.path().join("docs-link")).unwrap();
+
+    for path in ["escape/secret.txt", "secret.txt", "escape/new.txt"] {
+        let error = workspace.resolve_write(path).unwrap_err().to_string();
+        assert!(error.contains("outside the workspace root"), "{}", error);
+    }
+    assert!(workspace.resolve_read("secret.txt").is_err());
+
+    // Creating the file would follow the link out of the root
+    let error = workspace
+        .resolve_write("dangling.txt")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.ends_with("It goes through a symlink whose target does not exist."),
+        "{}",
+        error
+    );
+
+    // A link that stays inside the root is fine
+    assert_eq!(
+        workspace.resolve_read("docs-link/readme.md").unwrap(),
+        dir.path().join("docs/readme.md")
+    );
+}
+
+#[test]
+fn read_only_roots_can_be_read_but_not_written() {
+    let dir = TempDir::new();
+    let docs = TempDir::new();
+    docs.write("guide.md", "# Guide\n");
+    let workspace = Workspace::new(dir.path(), &[docs.path().to_path_buf()]).unwrap();
+
+    let guide = docs.path().join("guide.md");
+    assert_eq!(
+        workspace.resolve_read(guide.to_str().unwrap()).unwrap(),
+        guide
+    );
+
+    let error = workspace
+        .resolve_write(guide.to_str().unwrap())
+        .unwrap_err()
+        .to_string();
+    assert!(error.starts_with("Path is in a read-only root and cannot be modified"));
+
+    // A link in the workspace doesn't make a read-only file writable
+    symlink(&guide, dir.path().join("guide.md")).unwrap();
+    assert!(workspace.resolve_write("guide.md").is_err());
+}